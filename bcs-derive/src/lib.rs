@@ -0,0 +1,133 @@
+// Copyright (c) The Diem Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Derive macros for the `bcs` crate, re-exported by `bcs` with its `derive` feature.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{quote, quote_spanned};
+use syn::{
+    meta::ParseNestedMeta, parse_macro_input, spanned::Spanned, Attribute, Data, DeriveInput,
+    Error, Result,
+};
+
+/// Serde attributes that do not change the encoding of a container.
+const CONTAINER_ATTRIBUTES: &[&str] = &[
+    "bound",
+    "crate",
+    "default",
+    "deny_unknown_fields",
+    "rename",
+    "rename_all",
+    "transparent",
+];
+
+/// Serde attributes that do not change the encoding of a field.
+const FIELD_ATTRIBUTES: &[&str] = &["alias", "borrow", "bound", "default", "rename"];
+
+/// Implements `bcs::FixedSize` for a struct whose fields all implement it.
+///
+/// The encoded size of the struct is the sum of the encoded sizes of its fields. Deriving
+/// fails to compile if a field does not have a fixed-size encoding (e.g. a `Vec` or an
+/// `Option`), for enums, whose variants are encoded with a tag, and for serde attributes that
+/// change the encoding of the struct or of a field, such as `skip` or `with`.
+///
+/// ```rust
+/// use bcs::{to_bytes, FixedSize};
+/// use serde::Serialize;
+///
+/// #[derive(Serialize, bcs::BcsFixedSize)]
+/// struct Signature {
+///     r: [u8; 32],
+///     s: [u8; 32],
+///     recovery_id: u8,
+/// }
+///
+/// assert_eq!(Signature::ENCODED_SIZE, 65);
+/// let signature = Signature { r: [1; 32], s: [2; 32], recovery_id: 0 };
+/// assert_eq!(to_bytes(&signature).unwrap().len(), Signature::ENCODED_SIZE);
+/// ```
+#[proc_macro_derive(BcsFixedSize)]
+pub fn derive_fixed_size(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_fixed_size(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand_fixed_size(input: DeriveInput) -> Result<TokenStream2> {
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        Data::Enum(data) => {
+            return Err(Error::new(
+                data.enum_token.span,
+                "BcsFixedSize cannot be derived for enums, whose encoding starts with a \
+                 variable-length variant index",
+            ))
+        }
+        Data::Union(data) => {
+            return Err(Error::new(
+                data.union_token.span,
+                "BcsFixedSize cannot be derived for unions, which serde does not support",
+            ))
+        }
+    };
+    check_serde_attributes(&input.attrs, CONTAINER_ATTRIBUTES, "struct")?;
+    for field in fields {
+        check_serde_attributes(&field.attrs, FIELD_ATTRIBUTES, "field")?;
+    }
+
+    let name = &input.ident;
+    let mut generics = input.generics.clone();
+    let where_clause = generics.make_where_clause();
+    for field in fields {
+        let ty = &field.ty;
+        // Spanned at the field, so that a field without a fixed size is reported there.
+        where_clause
+            .predicates
+            .push(syn::parse_quote_spanned!(ty.span()=> #ty: ::bcs::FixedSize));
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let sizes = fields.iter().map(|field| {
+        let ty = &field.ty;
+        quote_spanned!(ty.span()=> <#ty as ::bcs::FixedSize>::ENCODED_SIZE)
+    });
+
+    Ok(quote! {
+        impl #impl_generics ::bcs::FixedSize for #name #ty_generics #where_clause {
+            const ENCODED_SIZE: usize = 0 #(+ #sizes)*;
+        }
+    })
+}
+
+/// Rejects the `#[serde(...)]` attributes that are not in `allowed`.
+fn check_serde_attributes(attrs: &[Attribute], allowed: &[&str], target: &str) -> Result<()> {
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("serde")) {
+        attr.parse_nested_meta(|meta| {
+            if allowed.iter().any(|name| meta.path.is_ident(name)) {
+                skip_meta_value(&meta)
+            } else {
+                let name = meta
+                    .path
+                    .get_ident()
+                    .map_or_else(|| "this".to_owned(), |ident| format!("`{}`", ident));
+                Err(meta.error(format!(
+                    "BcsFixedSize does not support the {} serde attribute on a {}, which may \
+                     change its encoded size",
+                    name, target
+                )))
+            }
+        })?;
+    }
+    Ok(())
+}
+
+/// Consumes the value of an attribute: `= value`, `(nested = value, ...)`, or nothing.
+fn skip_meta_value(meta: &ParseNestedMeta) -> Result<()> {
+    if meta.input.peek(syn::Token![=]) {
+        meta.value()?.parse::<syn::Expr>()?;
+    } else if meta.input.peek(syn::token::Paren) {
+        meta.parse_nested_meta(|nested| skip_meta_value(&nested))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,9 @@
+use bcs::BcsFixedSize;
+
+#[derive(BcsFixedSize)]
+enum Key {
+    Ed25519([u8; 32]),
+    Secp256k1([u8; 33]),
+}
+
+fn main() {}
@@ -0,0 +1,11 @@
+use bcs::BcsFixedSize;
+use serde::Serialize;
+
+#[derive(Serialize, BcsFixedSize)]
+struct Cached {
+    value: u64,
+    #[serde(skip)]
+    hash: [u8; 32],
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use bcs::BcsFixedSize;
+
+#[derive(BcsFixedSize)]
+struct Message {
+    id: u64,
+    payload: Vec<u8>,
+}
+
+fn main() {}
@@ -0,0 +1,32 @@
+use bcs::{BcsFixedSize, FixedSize};
+use serde::Serialize;
+use std::marker::PhantomData;
+
+#[derive(Serialize, BcsFixedSize)]
+#[serde(rename = "Point")]
+struct Named {
+    #[serde(rename = "x0")]
+    x: u64,
+    y: [u8; 3],
+}
+
+#[derive(Serialize, BcsFixedSize)]
+struct Tuple(bool, (u16, i128), Named);
+
+#[derive(Serialize, BcsFixedSize)]
+struct Unit;
+
+// Serde cannot serialize arrays of any length, but the size is still known.
+#[derive(BcsFixedSize)]
+struct Generic<T, const N: usize> {
+    items: [T; N],
+    marker: PhantomData<String>,
+}
+
+fn main() {
+    const _: () = assert!(Named::ENCODED_SIZE == 11);
+    assert_eq!(Tuple::ENCODED_SIZE, 1 + 2 + 16 + 11);
+    assert_eq!(Unit::ENCODED_SIZE, 0);
+    assert_eq!(<Generic<u32, 5>>::ENCODED_SIZE, 20);
+    assert_eq!(<Generic<Named, 2>>::ENCODED_SIZE, 22);
+}
@@ -1,8 +1,12 @@
 // Copyright (c) The Diem Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
-use bcs::to_bytes;
-use criterion::{criterion_group, criterion_main, Criterion};
+use bcs::{
+    from_bytes, from_bytes_in_place, from_reader, serialized_size, to_bytes, to_bytes_le_slice,
+    to_bytes_with_capacity, ByteBuf, PreSorted,
+};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, HashMap};
 
 pub fn bcs_benchmark(c: &mut Criterion) {
@@ -22,7 +26,197 @@ pub fn bcs_benchmark(c: &mut Criterion) {
             to_bytes(&hash_map).unwrap();
         })
     });
+
+    // Big-endian array keys sort the same way as their BCS bytes.
+    let sorted_map: BTreeMap<[u8; 4], u32> =
+        (0u32..2000u32).map(|i| (i.to_be_bytes(), i)).collect();
+    c.bench_function("serialize pre-sorted btree map", |b| {
+        b.iter(|| {
+            to_bytes(&PreSorted(&sorted_map)).unwrap();
+        })
+    });
+
+    let records: Vec<(u64, String)> = (0u64..2000u64).map(|i| (i, i.to_string())).collect();
+    let records_bytes = to_bytes(&records).unwrap();
+    let mut place: Vec<(u64, String)> = Vec::new();
+    c.bench_function("deserialize vec in place", |b| {
+        b.iter(|| {
+            from_bytes_in_place(&records_bytes, &mut place).unwrap();
+        })
+    });
+}
+
+#[derive(Deserialize, Serialize)]
+struct MerklePath {
+    #[serde(with = "bcs::array")]
+    siblings: [u64; 64],
+}
+
+#[derive(Deserialize, Serialize)]
+struct Siblings([[u64; 32]; 2]);
+
+pub fn array_benchmark(c: &mut Criterion) {
+    let path = MerklePath { siblings: [7; 64] };
+    let path_bytes = to_bytes(&path).unwrap();
+    c.bench_function("deserialize bulk integer array", |b| {
+        b.iter(|| {
+            from_bytes::<MerklePath>(&path_bytes).unwrap();
+        })
+    });
+
+    let siblings = Siblings([[7; 32]; 2]);
+    let siblings_bytes = to_bytes(&siblings).unwrap();
+    c.bench_function("deserialize integer array", |b| {
+        b.iter(|| {
+            from_bytes::<Siblings>(&siblings_bytes).unwrap();
+        })
+    });
+}
+
+pub fn bytes_benchmark(c: &mut Criterion) {
+    let blob: Vec<u8> = (0..1 << 20).map(|i| i as u8).collect();
+    let blob_bytes = to_bytes(&blob).unwrap();
+    c.bench_function("deserialize 1 MiB Vec<u8>", |b| {
+        b.iter(|| {
+            from_bytes::<Vec<u8>>(&blob_bytes).unwrap();
+        })
+    });
+    c.bench_function("deserialize 1 MiB ByteBuf", |b| {
+        b.iter(|| {
+            from_bytes::<ByteBuf>(&blob_bytes).unwrap();
+        })
+    });
+}
+
+#[derive(Serialize)]
+struct Transfer {
+    sender: [u8; 32],
+    amount: u64,
+    memo: String,
+}
+
+pub fn capacity_benchmark(c: &mut Criterion) {
+    let transfers: Vec<Transfer> = (0u64..10_000u64)
+        .map(|i| Transfer {
+            sender: [i as u8; 32],
+            amount: i,
+            memo: i.to_string(),
+        })
+        .collect();
+    let size = serialized_size(&transfers).unwrap();
+    c.bench_function("serialize vec of structs", |b| {
+        b.iter(|| {
+            to_bytes(&transfers).unwrap();
+        })
+    });
+    c.bench_function("serialize vec of structs with capacity hint", |b| {
+        b.iter(|| {
+            to_bytes_with_capacity(&transfers, size).unwrap();
+        })
+    });
+}
+
+#[derive(Deserialize, Serialize)]
+struct Account {
+    address: [u8; 32],
+    balance: u64,
+    sequence_number: u64,
+    name: String,
+    tags: Vec<(u16, Option<u32>)>,
+    frozen: bool,
+}
+
+#[derive(Deserialize, Serialize)]
+struct Ledger {
+    version: u64,
+    accounts: Vec<Account>,
+}
+
+fn ledger() -> Ledger {
+    Ledger {
+        version: 42,
+        accounts: (0u64..1000u64)
+            .map(|i| Account {
+                address: [i as u8; 32],
+                balance: i * 1_000,
+                sequence_number: i,
+                name: format!("account {}", i),
+                tags: (0..4).map(|j| (j, Some(j.into()))).collect(),
+                frozen: i % 2 == 0,
+            })
+            .collect(),
+    }
+}
+
+/// Decodes the same inputs from a slice and from a reader, whose costs differ sharply.
+pub fn decode_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode");
+
+    let ledger_bytes = to_bytes(&ledger()).unwrap();
+    let blob_bytes = to_bytes(&vec![7u8; 1 << 20]).unwrap();
+    let map: BTreeMap<u64, u64> = (0u64..10_000u64).map(|i| (i, i)).collect();
+    let map_bytes = to_bytes(&map).unwrap();
+
+    group.throughput(Throughput::Bytes(ledger_bytes.len() as u64));
+    group.bench_function(BenchmarkId::new("nested struct", "from_bytes"), |b| {
+        b.iter(|| from_bytes::<Ledger>(&ledger_bytes).unwrap())
+    });
+    group.bench_function(BenchmarkId::new("nested struct", "from_reader"), |b| {
+        b.iter(|| from_reader::<Ledger>(&ledger_bytes[..]).unwrap())
+    });
+
+    group.throughput(Throughput::Bytes(blob_bytes.len() as u64));
+    group.bench_function(BenchmarkId::new("1 MiB Vec<u8>", "from_bytes"), |b| {
+        b.iter(|| from_bytes::<Vec<u8>>(&blob_bytes).unwrap())
+    });
+    group.bench_function(BenchmarkId::new("1 MiB Vec<u8>", "from_reader"), |b| {
+        b.iter(|| from_reader::<Vec<u8>>(&blob_bytes[..]).unwrap())
+    });
+
+    // Decoding maps checks that keys are canonically ordered.
+    group.throughput(Throughput::Bytes(map_bytes.len() as u64));
+    group.bench_function(BenchmarkId::new("map", "from_bytes"), |b| {
+        b.iter(|| from_bytes::<BTreeMap<u64, u64>>(&map_bytes).unwrap())
+    });
+    group.bench_function(BenchmarkId::new("map", "from_reader"), |b| {
+        b.iter(|| from_reader::<BTreeMap<u64, u64>>(&map_bytes[..]).unwrap())
+    });
+
+    group.finish();
+}
+
+pub fn encode_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("encode");
+
+    let ledger = ledger();
+    group.bench_function("nested struct", |b| b.iter(|| to_bytes(&ledger).unwrap()));
+
+    let blob = vec![7u8; 1 << 20];
+    group.throughput(Throughput::Bytes(blob.len() as u64));
+    group.bench_function("1 MiB Vec<u8>", |b| b.iter(|| to_bytes(&blob).unwrap()));
+
+    // Encoding maps sorts their serialized keys.
+    let map: HashMap<u64, u64> = (0u64..10_000u64).map(|i| (i, i)).collect();
+    group.throughput(Throughput::Elements(map.len() as u64));
+    group.bench_function("map", |b| b.iter(|| to_bytes(&map).unwrap()));
+
+    let amounts: Vec<u64> = (0u64..100_000u64).collect();
+    group.throughput(Throughput::Elements(amounts.len() as u64));
+    group.bench_function("Vec<u64>", |b| b.iter(|| to_bytes(&amounts).unwrap()));
+    group.bench_function("Vec<u64> in bulk", |b| {
+        b.iter(|| to_bytes_le_slice(&amounts).unwrap())
+    });
+
+    group.finish();
 }
 
-criterion_group!(benches, bcs_benchmark);
+criterion_group!(
+    benches,
+    bcs_benchmark,
+    array_benchmark,
+    bytes_benchmark,
+    capacity_benchmark,
+    decode_benchmark,
+    encode_benchmark
+);
 criterion_main!(benches);
@@ -0,0 +1,176 @@
+// Copyright (c) The Diem Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Fast (de)serialization of fixed-size arrays of integers, to be used with
+//! `#[serde(with = "bcs::array")]`.
+//!
+//! The encoding is the same as for a regular `[T; N]`: the little-endian bytes of each element,
+//! without any length prefix. When decoding BCS, however, the `N * size_of::<T>()` bytes are
+//! read all at once and converted in a tight loop, instead of going through serde element by
+//! element. This only applies to arrays of the primitive integer types implementing
+//! [`LeInteger`]; arrays of structs or other types should use the default implementation.
+//!
+//! ```rust
+//! # use bcs::{from_bytes, to_bytes, Result};
+//! # use serde::{Deserialize, Serialize};
+//! # fn main() -> Result<()> {
+//! #[derive(Debug, Deserialize, PartialEq, Serialize)]
+//! struct MerklePath {
+//!     #[serde(with = "bcs::array")]
+//!     siblings: [u64; 64],
+//! }
+//!
+//! let path = MerklePath { siblings: [7; 64] };
+//! let bytes = to_bytes(&path)?;
+//! assert_eq!(bytes.len(), 64 * 8);
+//! assert_eq!(from_bytes::<MerklePath>(&bytes)?, path);
+//! # Ok(())}
+//! ```
+
+use core::{fmt, marker::PhantomData};
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+use serde::{
+    de::{self, Deserialize, Deserializer, SeqAccess, Visitor},
+    ser::{Serialize, SerializeTuple, Serializer},
+};
+
+mod private {
+    pub trait Sealed {}
+}
+
+/// Fixed-width integers that are encoded as their little-endian bytes.
+///
+/// This trait is sealed and implemented for all primitive integer types.
+pub trait LeInteger:
+    private::Sealed + Copy + Default + Serialize + for<'de> Deserialize<'de>
+{
+    /// The size of the encoded integer, in bytes.
+    const SIZE: usize;
+
+    #[doc(hidden)]
+    const TUPLE_STRUCT_NAME: &'static str;
+
+    #[doc(hidden)]
+    fn from_le_slice(bytes: &[u8]) -> Self;
+
+    #[doc(hidden)]
+    fn extend_le_bytes(slice: &[Self], output: &mut Vec<u8>);
+}
+
+macro_rules! le_integer {
+    ($($ty:ty),*) => {
+        $(
+            impl private::Sealed for $ty {}
+
+            impl LeInteger for $ty {
+                const SIZE: usize = core::mem::size_of::<$ty>();
+                const TUPLE_STRUCT_NAME: &'static str = element_size_name(Self::SIZE);
+
+                fn from_le_slice(bytes: &[u8]) -> Self {
+                    let mut le_bytes = [0; core::mem::size_of::<$ty>()];
+                    le_bytes.copy_from_slice(bytes);
+                    <$ty>::from_le_bytes(le_bytes)
+                }
+
+                fn extend_le_bytes(slice: &[Self], output: &mut Vec<u8>) {
+                    for element in slice {
+                        output.extend_from_slice(&element.to_le_bytes());
+                    }
+                }
+            }
+        )*
+    };
+}
+
+le_integer!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+/// Names of the tuple structs used to tell the BCS deserializer to read arrays in bulk, by
+/// element size.
+const ELEMENT_SIZE_NAMES: [(usize, &str); 5] = [
+    (1, "$bcs::array::1"),
+    (2, "$bcs::array::2"),
+    (4, "$bcs::array::4"),
+    (8, "$bcs::array::8"),
+    (16, "$bcs::array::16"),
+];
+
+const fn element_size_name(size: usize) -> &'static str {
+    let mut i = 0;
+    while i < ELEMENT_SIZE_NAMES.len() {
+        if ELEMENT_SIZE_NAMES[i].0 == size {
+            return ELEMENT_SIZE_NAMES[i].1;
+        }
+        i += 1;
+    }
+    panic!("unsupported integer size")
+}
+
+/// Returns the element size of a bulk-decoded array, if `name` designates one.
+pub(crate) fn element_size(name: &str) -> Option<usize> {
+    ELEMENT_SIZE_NAMES
+        .iter()
+        .find(|(_, element_name)| *element_name == name)
+        .map(|(size, _)| *size)
+}
+
+pub fn serialize<S, T, const N: usize>(array: &[T; N], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    T: LeInteger,
+{
+    let mut tuple = serializer.serialize_tuple(N)?;
+    for element in array {
+        tuple.serialize_element(element)?;
+    }
+    tuple.end()
+}
+
+pub fn deserialize<'de, D, T, const N: usize>(deserializer: D) -> Result<[T; N], D::Error>
+where
+    D: Deserializer<'de>,
+    T: LeInteger,
+{
+    deserializer.deserialize_tuple_struct(T::TUPLE_STRUCT_NAME, N, ArrayVisitor(PhantomData))
+}
+
+struct ArrayVisitor<T, const N: usize>(PhantomData<T>);
+
+impl<'de, T, const N: usize> Visitor<'de> for ArrayVisitor<T, N>
+where
+    T: LeInteger,
+{
+    type Value = [T; N];
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "an array of {} integers", N)
+    }
+
+    fn visit_bytes<E>(self, bytes: &[u8]) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        if bytes.len() != N * T::SIZE {
+            return Err(de::Error::invalid_length(bytes.len(), &self));
+        }
+        let mut array = [T::default(); N];
+        for (element, le_bytes) in array.iter_mut().zip(bytes.chunks_exact(T::SIZE)) {
+            *element = T::from_le_slice(le_bytes);
+        }
+        Ok(array)
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut array = [T::default(); N];
+        for (i, element) in array.iter_mut().enumerate() {
+            *element = seq
+                .next_element()?
+                .ok_or_else(|| de::Error::invalid_length(i, &self))?;
+        }
+        Ok(array)
+    }
+}
@@ -0,0 +1,253 @@
+// Copyright (c) The Diem Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Byte strings that are (de)serialized in bulk.
+
+use core::{
+    fmt,
+    ops::{Deref, DerefMut},
+};
+use serde::{
+    de::{self, Deserialize, Deserializer, SeqAccess, Visitor},
+    ser::{Serialize, Serializer},
+};
+
+#[cfg(not(feature = "std"))]
+use alloc::{boxed::Box, rc::Rc, sync::Arc, vec::Vec};
+#[cfg(feature = "std")]
+use std::{rc::Rc, sync::Arc};
+
+/// An owned byte string, encoded like `Vec<u8>` but (de)serialized in bulk.
+///
+/// Serde treats `Vec<u8>` and `&[u8]` as generic sequences: although their BCS encoding is just a
+/// ULEB128 length followed by the raw bytes, each byte goes through `serialize_u8` and
+/// `deserialize_u8` on its own. [`ByteBuf`] and [`Bytes`] have the exact same encoding, but are
+/// (de)serialized with `serialize_bytes` and `deserialize_bytes`, which copy the whole payload at
+/// once. This makes a large difference for blobs such as signatures, hashes, or opaque payloads,
+/// and removes the need for the `serde_bytes` crate.
+///
+/// Use [`ByteBuf`] in place of an owned `Vec<u8>`, and [`Bytes`] in place of a `&[u8]` borrowed
+/// from the input, e.g. when deserializing with [`from_bytes`](crate::from_bytes).
+///
+/// ```rust
+/// # use bcs::{from_bytes, to_bytes, ByteBuf, Bytes, Result};
+/// # use serde::{Deserialize, Serialize};
+/// # fn main() -> Result<()> {
+/// #[derive(Debug, Deserialize, PartialEq, Serialize)]
+/// struct Blob {
+///     payload: ByteBuf,
+/// }
+///
+/// let blob = Blob {
+///     payload: ByteBuf::from(vec![1, 2, 3]),
+/// };
+/// let bytes = to_bytes(&blob)?;
+/// assert_eq!(bytes, to_bytes(&vec![1u8, 2, 3])?);
+/// assert_eq!(from_bytes::<Blob>(&bytes)?, blob);
+///
+/// // Borrow the payload from the input instead of copying it.
+/// let payload: Bytes = from_bytes(&bytes)?;
+/// assert_eq!(&*payload, &[1, 2, 3]);
+/// # Ok(())}
+/// ```
+///
+/// # Boxed and shared byte strings
+///
+/// Serde decodes `Box<[u8]>`, and `Rc<[u8]>` or `Arc<[u8]>` with its `rc` feature, as a
+/// `Vec<u8>`, one byte at a time, and may need to shrink the vector before boxing it. Decoding a
+/// `ByteBuf` instead and converting it with `into` allocates the payload once, with the exact
+/// size: the conversion into a `Box<[u8]>` does not copy it. `Rc<[u8]>` and `Arc<[u8]>` store
+/// their reference counts in the same allocation as the payload, so the conversion into them
+/// copies it once, which no decoder can avoid.
+///
+/// ```rust
+/// # use bcs::{from_bytes, to_bytes, ByteBuf, Result};
+/// # use std::sync::Arc;
+/// # fn main() -> Result<()> {
+/// let bytes = to_bytes(&vec![7u8; 1024])?;
+/// let payload: Box<[u8]> = from_bytes::<ByteBuf>(&bytes)?.into();
+/// let shared: Arc<[u8]> = from_bytes::<ByteBuf>(&bytes)?.into();
+/// assert_eq!(payload, shared.as_ref().into());
+/// # Ok(())}
+/// ```
+#[derive(Clone, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct ByteBuf(Vec<u8>);
+
+impl ByteBuf {
+    /// Creates an empty byte string.
+    pub const fn new() -> Self {
+        Self(Vec::new())
+    }
+
+    /// Returns the underlying vector.
+    pub fn into_vec(self) -> Vec<u8> {
+        self.0
+    }
+
+    /// Converts into a boxed slice. This does not copy the bytes of a `ByteBuf` decoded by BCS,
+    /// whose capacity is exactly its length.
+    pub fn into_boxed_slice(self) -> Box<[u8]> {
+        self.0.into_boxed_slice()
+    }
+}
+
+impl From<Vec<u8>> for ByteBuf {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+}
+
+impl From<ByteBuf> for Vec<u8> {
+    fn from(bytes: ByteBuf) -> Self {
+        bytes.0
+    }
+}
+
+impl From<ByteBuf> for Box<[u8]> {
+    fn from(bytes: ByteBuf) -> Self {
+        bytes.into_boxed_slice()
+    }
+}
+
+impl From<ByteBuf> for Rc<[u8]> {
+    fn from(bytes: ByteBuf) -> Self {
+        bytes.0.into()
+    }
+}
+
+impl From<ByteBuf> for Arc<[u8]> {
+    fn from(bytes: ByteBuf) -> Self {
+        bytes.0.into()
+    }
+}
+
+impl Deref for ByteBuf {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Vec<u8> {
+        &self.0
+    }
+}
+
+impl DerefMut for ByteBuf {
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.0
+    }
+}
+
+impl AsRef<[u8]> for ByteBuf {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl Serialize for ByteBuf {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_bytes(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for ByteBuf {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_byte_buf(ByteBufVisitor)
+    }
+}
+
+struct ByteBufVisitor;
+
+impl<'de> Visitor<'de> for ByteBufVisitor {
+    type Value = ByteBuf;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a byte string")
+    }
+
+    fn visit_bytes<E>(self, bytes: &[u8]) -> Result<ByteBuf, E>
+    where
+        E: de::Error,
+    {
+        Ok(ByteBuf(bytes.to_vec()))
+    }
+
+    fn visit_byte_buf<E>(self, bytes: Vec<u8>) -> Result<ByteBuf, E>
+    where
+        E: de::Error,
+    {
+        Ok(ByteBuf(bytes))
+    }
+
+    // Lets other formats, which may encode bytes as sequences, round-trip the type.
+    fn visit_seq<A>(self, mut seq: A) -> Result<ByteBuf, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(4096));
+        while let Some(byte) = seq.next_element()? {
+            bytes.push(byte);
+        }
+        Ok(ByteBuf(bytes))
+    }
+}
+
+/// A byte string borrowed from the input, encoded like `&[u8]` but (de)serialized in bulk.
+///
+/// Only [`from_bytes`](crate::from_bytes) and the other functions decoding from a slice can lend
+/// out their input; readers fail with a type error.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Bytes<'a>(&'a [u8]);
+
+impl<'a> Bytes<'a> {
+    /// Wraps a byte slice.
+    pub const fn new(bytes: &'a [u8]) -> Self {
+        Self(bytes)
+    }
+
+    /// Returns the underlying slice, with its original lifetime.
+    pub const fn as_slice(&self) -> &'a [u8] {
+        self.0
+    }
+}
+
+impl<'a> From<&'a [u8]> for Bytes<'a> {
+    fn from(bytes: &'a [u8]) -> Self {
+        Self(bytes)
+    }
+}
+
+impl Deref for Bytes<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.0
+    }
+}
+
+impl AsRef<[u8]> for Bytes<'_> {
+    fn as_ref(&self) -> &[u8] {
+        self.0
+    }
+}
+
+impl Serialize for Bytes<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_bytes(self.0)
+    }
+}
+
+impl<'de: 'a, 'a> Deserialize<'de> for Bytes<'a> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        <&'a [u8]>::deserialize(deserializer).map(Bytes)
+    }
+}
@@ -0,0 +1,130 @@
+// Copyright (c) The Diem Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Values decoded along with their exact encoding.
+
+use crate::ByteBuf;
+use core::{fmt, marker::PhantomData};
+use serde::{
+    de::{self, Deserialize, Deserializer, SeqAccess, Visitor},
+    ser::{Serialize, Serializer},
+};
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+/// The name under which `Captured` asks the BCS deserializer to capture the bytes of a value.
+pub(crate) const CAPTURED_NAME: &str = "bcs::Captured";
+
+/// A value decoded together with the exact bytes it was decoded from.
+///
+/// Wrapping a field in `Captured` keeps its encoding next to its decoded form, e.g. to store or
+/// verify the signature of an embedded message without serializing it again. This works with
+/// all the functions decoding BCS, including from readers, where the bytes are recorded as they
+/// are read, and captures may be nested. Other deserializers, which cannot tell where a value
+/// starts and ends in their input, fail to decode a `Captured`.
+///
+/// A `Captured` is serialized as its value, so it has the same encoding as `T`.
+///
+/// # Examples
+///
+/// ```
+/// use bcs::{from_reader, to_bytes, Captured};
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Debug, Deserialize, PartialEq, Serialize)]
+/// struct Transfer {
+///     amount: u64,
+///     memo: String,
+/// }
+///
+/// #[derive(Deserialize)]
+/// struct Signed {
+///     message: Captured<Transfer>,
+///     signature: [u8; 4],
+/// }
+///
+/// let transfer = Transfer { amount: 3, memo: "rent".to_owned() };
+/// let mut bytes = to_bytes(&transfer).unwrap();
+/// bytes.extend_from_slice(&[0xaa; 4]);
+///
+/// let signed: Signed = from_reader(&bytes[..]).unwrap();
+/// assert_eq!(signed.message.bytes(), &bytes[..13]);
+/// assert_eq!(*signed.message.value(), transfer);
+/// ```
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Captured<T> {
+    value: T,
+    bytes: Vec<u8>,
+}
+
+impl<T> Captured<T> {
+    /// Returns the decoded value.
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+
+    /// Returns the bytes the value was decoded from.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Returns the decoded value and the bytes it was decoded from.
+    pub fn into_parts(self) -> (T, Vec<u8>) {
+        (self.value, self.bytes)
+    }
+}
+
+impl<T: Serialize> Serialize for Captured<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.value.serialize(serializer)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Captured<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_newtype_struct(CAPTURED_NAME, CapturedVisitor(PhantomData))
+    }
+}
+
+/// Receives the value and then its bytes from the BCS deserializer.
+struct CapturedVisitor<T>(PhantomData<T>);
+
+impl<'de, T: Deserialize<'de>> Visitor<'de> for CapturedVisitor<T> {
+    type Value = Captured<T>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("a value with its BCS bytes")
+    }
+
+    fn visit_newtype_struct<D>(self, _deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Err(de::Error::custom(
+            "`Captured` can only be deserialized from BCS",
+        ))
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let value = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let bytes: ByteBuf = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        Ok(Captured {
+            value,
+            bytes: bytes.into(),
+        })
+    }
+}
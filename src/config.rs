@@ -0,0 +1,451 @@
+// Copyright (c) The Diem Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use core::cmp::Ordering;
+use serde::{Deserialize, Serialize};
+
+/// An order of the serialized keys of maps, see [`Config::map_key_order`].
+type KeyOrder = fn(&[u8], &[u8]) -> Ordering;
+
+/// Limits enforced while deserializing, for use with `from_bytes_with_config` and
+/// `from_reader_with_config`.
+///
+/// The default configuration matches `from_bytes`: a container depth of
+/// [`MAX_CONTAINER_DEPTH`](crate::MAX_CONTAINER_DEPTH) and up to
+/// [`MAX_SEQUENCE_LENGTH`](crate::MAX_SEQUENCE_LENGTH) map entries. See [`SerializerConfig`] for
+/// the serialization counterpart.
+///
+/// # Examples
+///
+/// ```
+/// use bcs::{from_bytes_with_config, to_bytes, Config, Error};
+/// use std::collections::BTreeMap;
+///
+/// let config = Config::new().max_map_entries(2);
+///
+/// let entries: Vec<_> = (0u8..3).map(|i| (i, i)).collect();
+/// let bytes = to_bytes(&entries).unwrap();
+///
+/// // Sequences are still only bounded by `MAX_SEQUENCE_LENGTH`...
+/// assert!(from_bytes_with_config::<Vec<(u8, u8)>>(&bytes, &config).is_ok());
+/// // ...while maps with the same encoding are rejected.
+/// assert_eq!(
+///     from_bytes_with_config::<BTreeMap<u8, u8>>(&bytes, &config),
+///     Err(Error::ExceededMaxMapEntries { len: 3, max: 2 }),
+/// );
+/// ```
+///
+/// Configurations compare equal when they enforce the same limits and options. Orders set with
+/// [`Config::map_key_order`] cannot be compared, so they are all considered equal to each
+/// other, but never to the canonical order.
+#[derive(Clone, Copy, Debug)]
+pub struct Config {
+    pub(crate) max_container_depth: usize,
+    pub(crate) max_map_entries: usize,
+    /// The order set with `map_key_order`, if any, in place of `canonical_key_cmp`.
+    pub(crate) map_key_order: Option<KeyOrder>,
+    pub(crate) allow_non_canonical_uleb128: bool,
+    pub(crate) max_materialized_bytes: usize,
+    pub(crate) hex_context_on_error: bool,
+    pub(crate) variant_maps: &'static [VariantMap],
+    pub(crate) max_collection_nesting: usize,
+}
+
+impl Config {
+    /// Creates the default configuration.
+    pub const fn new() -> Self {
+        Self {
+            max_container_depth: crate::MAX_CONTAINER_DEPTH,
+            max_map_entries: crate::MAX_SEQUENCE_LENGTH,
+            map_key_order: None,
+            allow_non_canonical_uleb128: false,
+            max_materialized_bytes: usize::MAX,
+            hex_context_on_error: false,
+            variant_maps: &[],
+            max_collection_nesting: usize::MAX,
+        }
+    }
+
+    /// Sets the maximal container depth. It cannot exceed `MAX_CONTAINER_DEPTH`.
+    pub const fn max_container_depth(mut self, limit: usize) -> Self {
+        self.max_container_depth = limit;
+        self
+    }
+
+    /// Sets the order in which the serialized keys of a map must appear, in place of the
+    /// lexicographic order of BCS, [`canonical_key_cmp`]. This is only meant for compatibility
+    /// with legacy encodings.
+    ///
+    /// Keys must still be strictly increasing according to `order`, and identical keys are
+    /// always rejected, whatever `order` says. Any order set here, even `canonical_key_cmp`, is
+    /// reported as non-canonical by [`Config::format_profile`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bcs::{from_bytes, from_bytes_with_config, Config, Error};
+    /// use std::collections::BTreeMap;
+    ///
+    /// // A legacy format sorting keys in decreasing order.
+    /// let config = Config::new().map_key_order(|a, b| b.cmp(a));
+    /// let bytes = vec![2, 7, b'x', 3, b'y'];
+    ///
+    /// let map: BTreeMap<u8, u8> = from_bytes_with_config(&bytes, &config).unwrap();
+    /// assert_eq!(map.into_iter().collect::<Vec<_>>(), vec![(3, b'y'), (7, b'x')]);
+    /// assert_eq!(
+    ///     from_bytes::<BTreeMap<u8, u8>>(&bytes),
+    ///     Err(Error::NonCanonicalMap { index: 1, offset: 3 }),
+    /// );
+    /// ```
+    pub const fn map_key_order(mut self, order: fn(&[u8], &[u8]) -> Ordering) -> Self {
+        self.map_key_order = Some(order);
+        self
+    }
+
+    /// Sets how many levels of sequences, maps, and options may be nested, failing with
+    /// `Error::ExceededCollectionNesting` beyond. This is unbounded by default.
+    ///
+    /// Unlike the container depth, this ignores structs and enums, e.g. a `Vec<Vec<u8>>` field
+    /// nests two collections whatever the depth of its struct. Strings and byte arrays decoded
+    /// in bulk, such as `String` and `ByteBuf`, are not collections here.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bcs::{from_bytes_with_config, to_bytes, Config, Error};
+    ///
+    /// let config = Config::new().max_collection_nesting(2);
+    /// let bytes = to_bytes(&vec![Some(1u8)]).unwrap();
+    /// assert!(from_bytes_with_config::<Vec<Option<u8>>>(&bytes, &config).is_ok());
+    ///
+    /// let bytes = to_bytes(&vec![vec![Some(1u8)]]).unwrap();
+    /// assert_eq!(
+    ///     from_bytes_with_config::<Vec<Vec<Option<u8>>>>(&bytes, &config),
+    ///     Err(Error::ExceededCollectionNesting { limit: 2 }),
+    /// );
+    /// ```
+    pub const fn max_collection_nesting(mut self, limit: usize) -> Self {
+        self.max_collection_nesting = limit;
+        self
+    }
+
+    /// Sets the maximal number of entries of a map. Maps are bounded separately from other
+    /// sequences because each entry costs two decodes and a comparison of the key bytes.
+    pub const fn max_map_entries(mut self, limit: usize) -> Self {
+        self.max_map_entries = limit;
+        self
+    }
+
+    /// Accepts ULEB128 integers (lengths and variant indices) that are not minimally encoded,
+    /// i.e. that end with zero digits such as `[0x81, 0x00]` for 1. This is only meant for data
+    /// produced by legacy encoders, and is disabled by default.
+    ///
+    /// Enabling this forfeits the canonical-form guarantee of BCS: several inputs decode to the
+    /// same value, so decoded values must not be assumed to re-encode to the original bytes,
+    /// e.g. when comparing hashes or signatures. Integers must still fit in a `u32`, in at most
+    /// 5 bytes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bcs::{from_bytes, from_bytes_with_config, to_bytes, Config, Error};
+    ///
+    /// let config = Config::new().allow_non_canonical_uleb128(true);
+    /// let bytes = vec![0x81, 0x00, 7];
+    ///
+    /// let value: Vec<u8> = from_bytes_with_config(&bytes, &config).unwrap();
+    /// assert_eq!(value, vec![7]);
+    /// assert_ne!(to_bytes(&value).unwrap(), bytes);
+    /// assert_eq!(from_bytes::<Vec<u8>>(&bytes), Err(Error::NonCanonicalUleb128Encoding));
+    /// ```
+    pub const fn allow_non_canonical_uleb128(mut self, allow: bool) -> Self {
+        self.allow_non_canonical_uleb128 = allow;
+        self
+    }
+
+    /// Bounds the total size of the decoded data, which is unlimited by default.
+    ///
+    /// Strings and byte arrays decoded into owned values count their length, and sequences and maps
+    /// count the in-memory size of their elements, e.g. 8 bytes per `u64`, or 24 bytes per `String`
+    /// on 64-bit targets on top of the bytes of the string. The total is added up during decoding,
+    /// and decoding fails with `Error::ExceededMaterializationBudget` as soon as it exceeds
+    /// `limit`: before a string or byte array is allocated, and before the first element of a
+    /// sequence or map is decoded for all of its elements. Strings and byte arrays borrowed from
+    /// the input, such as `&str` fields decoded with `from_bytes_with_config`, are not counted, as
+    /// they allocate nothing. This caps the memory used by the decoded value even when the input
+    /// length is not known in advance, as with `from_reader_with_config`, or when many small
+    /// containers expand into a much larger in-memory structure. Memory held indirectly, e.g. by
+    /// the nodes of a `BTreeMap` or by `Box`, is not counted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bcs::{from_bytes_with_config, to_bytes, Config, Error};
+    ///
+    /// // 4 bytes for the string, then 4 bytes for each of the 3 integers.
+    /// let bytes = to_bytes(&("four", vec![1u32, 2, 3])).unwrap();
+    ///
+    /// let config = Config::new().max_materialized_bytes(16);
+    /// assert!(from_bytes_with_config::<(String, Vec<u32>)>(&bytes, &config).is_ok());
+    /// let config = Config::new().max_materialized_bytes(15);
+    /// assert_eq!(
+    ///     from_bytes_with_config::<(String, Vec<u32>)>(&bytes, &config),
+    ///     Err(Error::ExceededMaterializationBudget { limit: 15 }),
+    /// );
+    /// ```
+    pub const fn max_materialized_bytes(mut self, limit: usize) -> Self {
+        self.max_materialized_bytes = limit;
+        self
+    }
+
+    /// Wraps decoding errors in `Error::WithHexContext`, whose message shows where decoding
+    /// stopped and the bytes around it, up to 16 on each side. This is meant for tests and
+    /// debugging, and is disabled by default.
+    ///
+    /// The byte that caused the error, if any, is usually the one right before the marked
+    /// position. Readers are not buffered, so only the bytes already read are shown for them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bcs::{from_bytes_with_config, Config};
+    ///
+    /// let config = Config::new().hex_context_on_error(true);
+    /// let err = from_bytes_with_config::<(u16, bool, u8)>(&[1, 0, 2, 7], &config).unwrap_err();
+    /// assert_eq!(
+    ///     err.to_string(),
+    ///     "expected boolean, found byte 0x02 (at byte 3: 01 00 02 | 07)",
+    /// );
+    /// ```
+    pub const fn hex_context_on_error(mut self, enabled: bool) -> Self {
+        self.hex_context_on_error = enabled;
+        self
+    }
+
+    /// Decodes the variant indices of the given enums through their [`VariantMap`]s, instead
+    /// of as positions in the current declaration. This is meant for reading data encoded with
+    /// an older version of an enum, after variants were inserted or reordered.
+    ///
+    /// Enums without a map, and all enums when serializing, still use positional indices.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bcs::{from_bytes_with_config, Config, VariantMap};
+    /// use serde::Deserialize;
+    ///
+    /// // Version 1 of the protocol had `Ping` and `Pong`. Version 2 inserted `Hello` first.
+    /// #[derive(Debug, Deserialize, PartialEq)]
+    /// enum Message {
+    ///     Hello(u8),
+    ///     Ping,
+    ///     Pong,
+    /// }
+    ///
+    /// const V1: &[VariantMap] = &[VariantMap {
+    ///     enum_name: "Message",
+    ///     variants: &[("Ping", 0), ("Pong", 1)],
+    /// }];
+    /// let config = Config::new().variant_maps(V1);
+    /// let pong_v1 = [1];
+    /// assert_eq!(from_bytes_with_config::<Message>(&pong_v1, &config).unwrap(), Message::Pong);
+    /// assert!(from_bytes_with_config::<Message>(&[2], &config).is_err());
+    /// ```
+    pub const fn variant_maps(mut self, maps: &'static [VariantMap]) -> Self {
+        self.variant_maps = maps;
+        self
+    }
+
+    /// Returns the rules this configuration enforces, to be compared with those of a peer.
+    pub fn format_profile(&self) -> FormatProfile {
+        FormatProfile {
+            format_version: crate::version(),
+            max_container_depth: core::cmp::min(
+                self.max_container_depth,
+                crate::MAX_CONTAINER_DEPTH,
+            ),
+            max_map_entries: self.max_map_entries,
+            max_materialized_bytes: self.max_materialized_bytes,
+            max_collection_nesting: self.max_collection_nesting,
+            canonical_map_key_order: self.map_key_order.is_none(),
+            canonical_uleb128: !self.allow_non_canonical_uleb128,
+            positional_variant_indices: self.variant_maps.is_empty(),
+        }
+    }
+}
+
+impl PartialEq for Config {
+    fn eq(&self, other: &Self) -> bool {
+        self.max_container_depth == other.max_container_depth
+            && self.max_map_entries == other.max_map_entries
+            && self.map_key_order.is_some() == other.map_key_order.is_some()
+            && self.allow_non_canonical_uleb128 == other.allow_non_canonical_uleb128
+            && self.max_materialized_bytes == other.max_materialized_bytes
+            && self.hex_context_on_error == other.hex_context_on_error
+            && self.variant_maps == other.variant_maps
+            && self.max_collection_nesting == other.max_collection_nesting
+    }
+}
+
+impl Eq for Config {}
+
+/// The rules enforced by a [`Config`] that decide which inputs are accepted, returned by
+/// [`Config::format_profile`].
+///
+/// Nodes that must agree on the validity of the same data, e.g. replicas of a ledger, should
+/// decode with identical profiles: otherwise a lenient node may accept data that a strict one
+/// rejects, and their states diverge. Profiles implement `Serialize` and `Deserialize`, so they
+/// can be exchanged during a handshake and compared with `==`. Options that only change how
+/// errors are reported, such as `Config::hex_context_on_error`, are not part of the profile.
+///
+/// A custom `Config::map_key_order` is only recorded as non-canonical: two profiles with
+/// different custom orders compare equal. The profile also records the version of the format,
+/// [`version`](crate::version), so that peers built against incompatible releases of the
+/// format do not agree by accident.
+///
+/// # Examples
+///
+/// ```
+/// use bcs::{from_bytes, to_bytes, Config, FormatProfile};
+///
+/// let ours = Config::new().max_map_entries(1000).format_profile();
+/// assert!(ours.is_canonical());
+///
+/// // The profile received from a peer that accepts non-minimal ULEB128 integers.
+/// let lenient = Config::new().allow_non_canonical_uleb128(true);
+/// let bytes = to_bytes(&lenient.format_profile()).unwrap();
+/// let theirs: FormatProfile = from_bytes(&bytes).unwrap();
+/// assert!(!theirs.is_canonical());
+/// assert_ne!(ours, theirs);
+/// ```
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct FormatProfile {
+    /// The version of the BCS format, see [`version`](crate::version).
+    pub format_version: u32,
+    /// The maximal container depth.
+    pub max_container_depth: usize,
+    /// The maximal number of entries of a map.
+    pub max_map_entries: usize,
+    /// The maximal size of the decoded data, see `Config::max_materialized_bytes`.
+    pub max_materialized_bytes: usize,
+    /// The maximal nesting of sequences, maps, and options.
+    pub max_collection_nesting: usize,
+    /// Whether map keys must be in the canonical order of BCS, [`canonical_key_cmp`].
+    pub canonical_map_key_order: bool,
+    /// Whether ULEB128 integers must be minimally encoded.
+    pub canonical_uleb128: bool,
+    /// Whether enum variants are decoded from their position in the enum, i.e. no
+    /// `Config::variant_maps` are set. Which maps are set is not recorded.
+    pub positional_variant_indices: bool,
+}
+
+impl FormatProfile {
+    /// Returns whether the profile enforces the canonical encoding of BCS, i.e. every accepted
+    /// value has a single encoding, the one `to_bytes` produces. Limits do not matter here: they
+    /// reject some inputs, but never accept a non-canonical one.
+    pub fn is_canonical(&self) -> bool {
+        self.canonical_map_key_order && self.canonical_uleb128 && self.positional_variant_indices
+    }
+}
+
+/// The variant indices of an enum as encoded by some version of a schema, for use with
+/// [`Config::variant_maps`].
+///
+/// `variants` lists the name and encoded index of each variant. Decoding an index that is not
+/// listed fails, as does a listed variant that the enum no longer declares.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct VariantMap {
+    /// The name of the enum, as given to serde (by default, its Rust name).
+    pub enum_name: &'static str,
+    /// The name of each variant, with its encoded index.
+    pub variants: &'static [(&'static str, u32)],
+}
+
+/// Compares the serialized bytes of two map keys in the canonical BCS order, the lexicographic
+/// order of bytes.
+///
+/// The keys of a BCS map must be strictly increasing under this order, so this is the order in
+/// which to write the entries of a map built without serde. It is not the `Ord` order of the
+/// keys in general: integers wider than one byte are encoded in little endian, and strings and
+/// sequences start with their length.
+///
+/// # Examples
+///
+/// ```
+/// use bcs::{canonical_key_cmp, from_bytes, to_bytes};
+/// use std::collections::BTreeMap;
+///
+/// // The entries of a `BTreeMap<u16, bool>`, encoded by hand.
+/// let mut entries = vec![(to_bytes(&1u16).unwrap(), true), (to_bytes(&256u16).unwrap(), false)];
+/// entries.sort_by(|(a, _), (b, _)| canonical_key_cmp(a, b));
+///
+/// let mut bytes = vec![entries.len() as u8];
+/// for (key, value) in &entries {
+///     bytes.extend_from_slice(key);
+///     bytes.push(u8::from(*value));
+/// }
+/// // 256 is encoded as [0, 1], which comes before 1, encoded as [1, 0].
+/// assert_eq!(bytes, [2, 0, 1, 0, 1, 0, 1]);
+/// assert_eq!(from_bytes::<BTreeMap<u16, bool>>(&bytes).unwrap(), [(1, true), (256, false)].into());
+/// ```
+pub fn canonical_key_cmp(a: &[u8], b: &[u8]) -> Ordering {
+    a.cmp(b)
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Limits enforced while serializing, for use with `to_bytes_with_config`.
+///
+/// Enforcing the same limits as the deserializer guarantees that whatever is serialized can be
+/// read back with the corresponding [`Config`]. The default configuration matches `to_bytes`.
+///
+/// # Examples
+///
+/// ```
+/// use bcs::{to_bytes_with_config, Error, SerializerConfig};
+///
+/// let config = SerializerConfig::new().max_sequence_length(4);
+/// assert!(to_bytes_with_config(&"four", &config).is_ok());
+/// assert_eq!(
+///     to_bytes_with_config(&vec![0u8; 5], &config),
+///     Err(Error::ExceededMaxLen { len: 5, max: 4 }),
+/// );
+/// ```
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SerializerConfig {
+    pub(crate) max_container_depth: usize,
+    pub(crate) max_sequence_length: usize,
+}
+
+impl SerializerConfig {
+    /// Creates the default configuration.
+    pub const fn new() -> Self {
+        Self {
+            max_container_depth: crate::MAX_CONTAINER_DEPTH,
+            max_sequence_length: crate::MAX_SEQUENCE_LENGTH,
+        }
+    }
+
+    /// Sets the maximal container depth. It cannot exceed `MAX_CONTAINER_DEPTH`.
+    pub const fn max_container_depth(mut self, limit: usize) -> Self {
+        self.max_container_depth = limit;
+        self
+    }
+
+    /// Sets the maximal length of sequences, strings, byte arrays, and maps. It cannot exceed
+    /// `MAX_SEQUENCE_LENGTH`.
+    pub const fn max_sequence_length(mut self, limit: usize) -> Self {
+        self.max_sequence_length = limit;
+        self
+    }
+}
+
+impl Default for SerializerConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,150 @@
+// Copyright (c) The Diem Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use core::{fmt, marker::PhantomData};
+use serde::de::{DeserializeSeed, Deserializer, SeqAccess, Visitor};
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+/// Types decoded with access to a mutable context, such as an arena or an interner, shared by
+/// the whole value.
+///
+/// This is the `Deserialize` counterpart of types that cannot be decoded on their own, e.g.
+/// handles into an arena owned by the caller. Implementations decode their fields through
+/// [`ContextSeed`]s borrowing the same context, typically created with [`ContextSeed::seed`],
+/// and are decoded with [`from_bytes_with_context`] or any function taking a `DeserializeSeed`.
+///
+/// `Vec<T>` implements this trait when `T` does, decoding every element with the context.
+///
+/// # Examples
+///
+/// ```
+/// use bcs::{from_bytes_with_context, to_bytes, DeserializeWithContext};
+/// use serde::{Deserialize, Deserializer};
+///
+/// /// The index of a string in an arena.
+/// #[derive(Debug, PartialEq)]
+/// struct Symbol(usize);
+///
+/// impl<'de> DeserializeWithContext<'de, Vec<String>> for Symbol {
+///     fn deserialize_with_context<D: Deserializer<'de>>(
+///         deserializer: D,
+///         arena: &mut Vec<String>,
+///     ) -> Result<Self, D::Error> {
+///         let name = String::deserialize(deserializer)?;
+///         let index = arena.iter().position(|s| *s == name).unwrap_or_else(|| {
+///             arena.push(name);
+///             arena.len() - 1
+///         });
+///         Ok(Symbol(index))
+///     }
+/// }
+///
+/// let bytes = to_bytes(&vec!["x", "y", "x"]).unwrap();
+/// let mut arena = Vec::new();
+/// let symbols: Vec<Symbol> = from_bytes_with_context(&bytes, &mut arena).unwrap();
+/// assert_eq!(symbols, [Symbol(0), Symbol(1), Symbol(0)]);
+/// assert_eq!(arena, ["x", "y"]);
+/// ```
+pub trait DeserializeWithContext<'de, Ctx: ?Sized>: Sized {
+    /// Decodes a value from `deserializer`, using and updating `ctx`.
+    fn deserialize_with_context<D>(deserializer: D, ctx: &mut Ctx) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>;
+}
+
+/// A `DeserializeSeed` decoding a `T` with a mutable borrow of a context.
+///
+/// The seed only holds the borrow, so a fresh seed for each field or element can be created
+/// from it with [`seed`](ContextSeed::seed) without giving up the context, which is how the
+/// context is threaded through the whole decoding tree.
+pub struct ContextSeed<'c, Ctx: ?Sized, T> {
+    ctx: &'c mut Ctx,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<'c, Ctx: ?Sized, T> ContextSeed<'c, Ctx, T> {
+    /// Creates a seed decoding a `T` with `ctx`.
+    pub fn new(ctx: &'c mut Ctx) -> Self {
+        Self {
+            ctx,
+            marker: PhantomData,
+        }
+    }
+
+    /// Returns the context.
+    pub fn ctx(&mut self) -> &mut Ctx {
+        self.ctx
+    }
+
+    /// Returns a seed decoding a `U` with the same context, e.g. to pass to
+    /// `SeqAccess::next_element_seed`.
+    pub fn seed<U>(&mut self) -> ContextSeed<'_, Ctx, U> {
+        ContextSeed::new(self.ctx)
+    }
+}
+
+impl<'de, Ctx: ?Sized, T> DeserializeSeed<'de> for ContextSeed<'_, Ctx, T>
+where
+    T: DeserializeWithContext<'de, Ctx>,
+{
+    type Value = T;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<T, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        T::deserialize_with_context(deserializer, self.ctx)
+    }
+}
+
+impl<'de, Ctx: ?Sized, T> DeserializeWithContext<'de, Ctx> for Vec<T>
+where
+    T: DeserializeWithContext<'de, Ctx>,
+{
+    fn deserialize_with_context<D>(deserializer: D, ctx: &mut Ctx) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct VecVisitor<'c, Ctx: ?Sized, T>(ContextSeed<'c, Ctx, T>);
+
+        impl<'de, Ctx: ?Sized, T> Visitor<'de> for VecVisitor<'_, Ctx, T>
+        where
+            T: DeserializeWithContext<'de, Ctx>,
+        {
+            type Value = Vec<T>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+                formatter.write_str("a sequence")
+            }
+
+            fn visit_seq<A>(mut self, mut seq: A) -> Result<Vec<T>, A::Error>
+            where
+                A: SeqAccess<'de>,
+            {
+                // The length comes from the input: do not trust it for more than a page.
+                let mut values = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(4096));
+                while let Some(value) = seq.next_element_seed(self.0.seed::<T>())? {
+                    values.push(value);
+                }
+                Ok(values)
+            }
+        }
+
+        deserializer.deserialize_seq(VecVisitor(ContextSeed::new(ctx)))
+    }
+}
+
+/// Deserialize a type implementing [`DeserializeWithContext`] from a `&[u8]`, with `ctx` as
+/// its context.
+///
+/// This is `from_bytes_seed(ContextSeed::new(ctx), bytes)`: the context stays borrowed only
+/// for the duration of the call, and keeps whatever was added to it even if decoding fails.
+pub fn from_bytes_with_context<'a, T, Ctx>(bytes: &'a [u8], ctx: &mut Ctx) -> crate::Result<T>
+where
+    T: DeserializeWithContext<'a, Ctx>,
+    Ctx: ?Sized,
+{
+    crate::from_bytes_seed(ContextSeed::new(ctx), bytes)
+}
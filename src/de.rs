@@ -2,14 +2,22 @@
 // SPDX-License-Identifier: Apache-2.0
 
 #[cfg(not(feature = "std"))]
-use alloc::{str, string::String, vec, vec::Vec};
+use alloc::{
+    boxed::Box,
+    format, str,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
 
 #[cfg(feature = "std")]
 use std::str;
 
+use crate::config::{canonical_key_cmp, Config, VariantMap};
 use crate::error::{Error, Result};
 use crate::io::Read;
-use core::convert::TryFrom;
+use crate::layout::{Layout, Skip};
+use core::{cmp::Ordering, convert::TryFrom, fmt, marker::PhantomData};
 use serde::de::{self, Deserialize, DeserializeOwned, DeserializeSeed, IntoDeserializer, Visitor};
 
 /// Deserializes a `&[u8]` into a type.
@@ -41,6 +49,62 @@ use serde::de::{self, Deserialize, DeserializeOwned, DeserializeSeed, IntoDeseri
 /// assert_eq!(socket_addr.ip.0, [127, 0, 0, 1]);
 /// assert_eq!(socket_addr.port.0, 8001);
 /// ```
+///
+/// # Zero-copy decoding
+///
+/// Strings and byte arrays are handed to `T` as borrowed slices of `bytes`, so `&str`, `&[u8]`,
+/// and `Cow` fields marked with `#[serde(borrow)]` point into the input without allocating.
+/// Without `#[serde(borrow)]`, serde always deserializes a `Cow` as `Cow::Owned`, which is also
+/// what the reader functions such as `from_reader` produce since they cannot lend their input.
+/// Map keys are decoded the same way, so maps such as `HashMap<&[u8], V>` or `BTreeMap<&str, V>`
+/// borrow their keys too.
+///
+/// ```
+/// use bcs::{from_bytes, to_bytes};
+/// use serde::Deserialize;
+/// use std::borrow::Cow;
+///
+/// #[derive(Deserialize)]
+/// struct Record<'a> {
+///     #[serde(borrow)]
+///     name: Cow<'a, str>,
+/// }
+///
+/// let bytes = to_bytes(&"zero-copy").unwrap();
+/// let record: Record = from_bytes(&bytes).unwrap();
+/// assert!(matches!(record.name, Cow::Borrowed("zero-copy")));
+/// ```
+///
+/// # Size hints
+///
+/// BCS sequences and maps are prefixed with their length, so the `size_hint` of the
+/// `SeqAccess` and `MapAccess` given to visitors is always `Some`, and always exact: it is the
+/// number of elements or entries not yet decoded, before the first one and after each of them.
+/// This holds for every decoding function, from a slice or a reader, and for tuples, structs
+/// and fixed-size arrays as well. Visitors can rely on it to allocate exactly once.
+///
+/// The hint comes from the input, though. Before the first element is decoded, it is only
+/// bounded by `MAX_SEQUENCE_LENGTH` (or the map entry limit of a [`Config`](crate::Config)), so
+/// visitors handling untrusted input should cap the capacity they reserve up front, as serde's
+/// own implementations do.
+///
+/// # Fixed-capacity sequences
+///
+/// Thanks to exact size hints, sequences can be decoded into stack-backed collections such as
+/// `arrayvec::ArrayVec` or `heapless::Vec`. A declared length exceeding the capacity fails with
+/// the collection's own error, as soon as the first extra element is decoded.
+///
+/// ```
+/// use arrayvec::ArrayVec;
+/// use bcs::{from_bytes, to_bytes};
+///
+/// let bytes = to_bytes(&vec![1u16, 2, 3]).unwrap();
+/// let small: ArrayVec<u16, 4> = from_bytes(&bytes).unwrap();
+/// assert_eq!(small.as_slice(), &[1, 2, 3]);
+///
+/// let err = from_bytes::<ArrayVec<u16, 2>>(&bytes).unwrap_err();
+/// assert_eq!(err.to_string(), "invalid length 3, expected an array with no more than 2 items");
+/// ```
 pub fn from_bytes<'a, T>(bytes: &'a [u8]) -> Result<T>
 where
     T: Deserialize<'a>,
@@ -51,6 +115,122 @@ where
     Ok(t)
 }
 
+/// Statistics about a decoded input, returned by `from_bytes_with_stats`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct DecodeStats {
+    /// The deepest nesting of containers (structs and enums) that was reached.
+    pub max_depth: usize,
+    /// The number of bytes that were decoded.
+    pub bytes_read: usize,
+    /// The total number of entries of all maps.
+    pub map_entries: usize,
+    /// The total number of elements of all variable-length sequences. Strings and byte arrays
+    /// decoded in bulk are not included.
+    pub seq_elements: usize,
+}
+
+/// Same as `from_bytes`, but also returns statistics about the input, e.g. to monitor how
+/// close incoming messages get to the configured limits.
+///
+/// Collecting these statistics has a small cost, which the other functions don't pay.
+///
+/// # Examples
+///
+/// ```
+/// use bcs::{from_bytes_with_stats, to_bytes, DecodeStats};
+/// use std::collections::BTreeMap;
+///
+/// #[derive(serde::Deserialize, serde::Serialize)]
+/// struct Batch {
+///     items: Vec<Option<Box<Batch>>>,
+///     index: BTreeMap<u8, u8>,
+/// }
+///
+/// let inner = Batch { items: vec![None, None], index: BTreeMap::new() };
+/// let outer = Batch { items: vec![Some(Box::new(inner))], index: [(1, 2)].into() };
+/// let bytes = to_bytes(&outer).unwrap();
+///
+/// let (_, stats): (Batch, _) = from_bytes_with_stats(&bytes).unwrap();
+/// assert_eq!(
+///     stats,
+///     DecodeStats { max_depth: 2, bytes_read: bytes.len(), map_entries: 1, seq_elements: 3 }
+/// );
+/// ```
+pub fn from_bytes_with_stats<'a, T>(bytes: &'a [u8]) -> Result<(T, DecodeStats)>
+where
+    T: Deserialize<'a>,
+{
+    let mut deserializer = Deserializer::new(bytes, crate::MAX_CONTAINER_DEPTH);
+    deserializer.stats = Some(DecodeStats::default());
+    let t = T::deserialize(&mut deserializer)?;
+    deserializer.end()?;
+    let mut stats = deserializer.stats.unwrap_or_default();
+    stats.bytes_read = bytes.len();
+    Ok((t, stats))
+}
+
+/// Same as `from_bytes` for an enum, but also returns the name of the decoded variant, e.g. for
+/// logging.
+///
+/// Only the variant of `T` itself is returned, not those of nested enums. Types other than
+/// enums fail with `Error::NotSupported`. A variant index out of the range of `T`'s variants is
+/// rejected before `T` sees it.
+///
+/// # Examples
+///
+/// ```
+/// use bcs::{from_bytes_with_variant, to_bytes};
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Debug, Deserialize, PartialEq, Serialize)]
+/// enum Message {
+///     Ping,
+///     Transfer { amount: u64 },
+/// }
+///
+/// let bytes = to_bytes(&Message::Transfer { amount: 5 }).unwrap();
+/// let (message, variant) = from_bytes_with_variant::<Message>(&bytes).unwrap();
+/// assert_eq!(message, Message::Transfer { amount: 5 });
+/// assert_eq!(variant, "Transfer");
+/// ```
+pub fn from_bytes_with_variant<'a, T>(bytes: &'a [u8]) -> Result<(T, &'static str)>
+where
+    T: Deserialize<'a>,
+{
+    let mut deserializer = Deserializer::new(bytes, crate::MAX_CONTAINER_DEPTH);
+    let t = T::deserialize(OuterEnumDeserializer(&mut deserializer))?;
+    deserializer.end()?;
+    match deserializer.outer_variant {
+        Some(Some(variant)) => Ok((t, variant)),
+        _ => Err(Error::NotSupported(
+            "from_bytes_with_variant: the decoded type is not an enum",
+        )),
+    }
+}
+
+/// Same as `from_bytes`, for any owner of bytes such as a `Vec<u8>` or a `&Vec<u8>`.
+///
+/// Since `bytes` may be dropped when this function returns, the result cannot borrow from it,
+/// hence the `DeserializeOwned` bound. Use `from_bytes` to borrow from the input.
+///
+/// # Examples
+///
+/// ```
+/// use bcs::{from_bytes_ref, to_bytes};
+///
+/// let bytes: Vec<u8> = to_bytes(&("key", 1u8)).unwrap();
+/// let value: (String, u8) = from_bytes_ref(&bytes).unwrap();
+/// assert_eq!(value, ("key".to_owned(), 1));
+/// let value: (String, u8) = from_bytes_ref(bytes).unwrap();
+/// assert_eq!(value.1, 1);
+/// ```
+pub fn from_bytes_ref<T>(bytes: impl AsRef<[u8]>) -> Result<T>
+where
+    T: DeserializeOwned,
+{
+    from_bytes(bytes.as_ref())
+}
+
 /// Same as `from_bytes` but use `limit` as max container depth instead of MAX_CONTAINER_DEPTH`
 /// Note that `limit` has to be lower than MAX_CONTAINER_DEPTH
 pub fn from_bytes_with_limit<'a, T>(bytes: &'a [u8], limit: usize) -> Result<T>
@@ -66,6 +246,98 @@ where
     Ok(t)
 }
 
+/// Same as `from_bytes_with_limit`, with the max container depth fixed at compile time.
+///
+/// A `DEPTH` greater than `MAX_CONTAINER_DEPTH` is rejected when the function is instantiated,
+/// instead of returning an error at runtime.
+///
+/// # Examples
+///
+/// ```
+/// use bcs::{from_bytes_const, to_bytes};
+///
+/// let bytes = to_bytes(&Some(Some(1u8))).unwrap();
+/// let value: Option<Option<u8>> = from_bytes_const::<_, 4>(&bytes).unwrap();
+/// assert_eq!(value, Some(Some(1)));
+/// ```
+///
+/// ```compile_fail
+/// let _ = bcs::from_bytes_const::<u8, { bcs::MAX_CONTAINER_DEPTH + 1 }>(&[0]);
+/// ```
+pub fn from_bytes_const<'a, T, const DEPTH: usize>(bytes: &'a [u8]) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    #[allow(clippy::let_unit_value)]
+    let () = ConstDepth::<DEPTH>::VALID;
+    let mut deserializer = Deserializer::new(bytes, DEPTH);
+    let t = T::deserialize(&mut deserializer)?;
+    deserializer.end()?;
+    Ok(t)
+}
+
+/// Checks a const generic container depth when it is monomorphized.
+struct ConstDepth<const DEPTH: usize>;
+
+impl<const DEPTH: usize> ConstDepth<DEPTH> {
+    const VALID: () = assert!(
+        DEPTH <= crate::MAX_CONTAINER_DEPTH,
+        "DEPTH exceeds the max allowed depth"
+    );
+}
+
+/// Same as `from_bytes` but enforce the limits of the given [`Config`].
+pub fn from_bytes_with_config<'a, T>(bytes: &'a [u8], config: &Config) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    let mut deserializer = Deserializer::new(bytes, config.max_container_depth);
+    deserializer.configure(config)?;
+    let result = T::deserialize(&mut deserializer).and_then(|t| deserializer.end().map(|()| t));
+    if !config.hex_context_on_error {
+        return result;
+    }
+    result.map_err(|error| {
+        let offset = bytes.len() - deserializer.input.len();
+        let (before, after) = bytes.split_at(offset);
+        with_hex_context(error, offset, before, after)
+    })
+}
+
+/// Deserializes a memory-mapped region into a type, with the same semantics as `from_bytes`.
+///
+/// Borrowed fields of `T` (such as `&str` or `&[u8]`) point directly into the mapping, so large
+/// files can be decoded without copying their contents. The borrow checker ties them to the
+/// lifetime of `mmap`: they remain valid only while the mapping is alive. As with any use of
+/// `memmap2`, the underlying file must not be modified while it is mapped.
+///
+/// # Examples
+///
+/// ```no_run
+/// use memmap2::Mmap;
+/// use serde::Deserialize;
+/// use std::fs::File;
+///
+/// #[derive(Deserialize)]
+/// struct Dataset<'a> {
+///     name: &'a str,
+///     payload: &'a [u8],
+/// }
+///
+/// let file = File::open("dataset.bcs").unwrap();
+/// // Safety: the file is not modified while it is mapped.
+/// let mmap = unsafe { Mmap::map(&file) }.unwrap();
+/// let dataset: Dataset = bcs::from_mmap(&mmap).unwrap();
+/// println!("{}: {} bytes", dataset.name, dataset.payload.len());
+/// ```
+#[cfg(feature = "mmap")]
+pub fn from_mmap<'a, T>(mmap: &'a memmap2::Mmap) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    from_bytes(&mmap[..])
+}
+
 /// Perform a stateful deserialization from a `&[u8]` using the provided `seed`.
 pub fn from_bytes_seed<'a, T>(seed: T, bytes: &'a [u8]) -> Result<T::Value>
 where
@@ -92,6 +364,509 @@ where
     Ok(t)
 }
 
+/// Deserializes a `&[u8]` into an existing value of type `T`, overwriting it.
+///
+/// This goes through serde's `deserialize_in_place`, which lets types such as `Vec<T>` and
+/// `String` reuse the allocations already owned by `place` instead of allocating fresh ones.
+/// Structs only reuse the buffers of their fields when `serde_derive` is built with its
+/// `deserialize_in_place` feature.
+///
+/// On error, `place` is left in an unspecified but valid state.
+///
+/// # Examples
+///
+/// ```
+/// use bcs::{from_bytes_in_place, to_bytes};
+///
+/// let mut records: Vec<u32> = Vec::with_capacity(16);
+/// let capacity = records.capacity();
+///
+/// from_bytes_in_place(&to_bytes(&vec![1u32, 2, 3]).unwrap(), &mut records).unwrap();
+/// assert_eq!(records, vec![1, 2, 3]);
+/// assert_eq!(records.capacity(), capacity);
+/// ```
+pub fn from_bytes_in_place<'a, T>(bytes: &'a [u8], place: &mut T) -> Result<()>
+where
+    T: Deserialize<'a>,
+{
+    let mut deserializer = Deserializer::new(bytes, crate::MAX_CONTAINER_DEPTH);
+    T::deserialize_in_place(&mut deserializer, place)?;
+    deserializer.end()
+}
+
+/// Deserializes a `&[u8]` holding a sequence of `T`, such as an encoded `Vec<T>`, and appends
+/// its elements to `target`.
+///
+/// Decoding many chunks this way reuses the capacity of `target` instead of allocating a
+/// vector per chunk. Room for the elements is reserved from the length prefix, up to a page of
+/// elements at a time, so a forged length does not reserve more than the input can fill. On
+/// error, `target` is truncated back to its original length.
+///
+/// # Examples
+///
+/// ```
+/// use bcs::{append_from_bytes, to_bytes};
+///
+/// let mut records: Vec<u32> = Vec::new();
+/// append_from_bytes(&mut records, &to_bytes(&vec![1u32, 2]).unwrap()).unwrap();
+/// append_from_bytes(&mut records, &to_bytes(&vec![3u32]).unwrap()).unwrap();
+/// assert_eq!(records, vec![1, 2, 3]);
+/// ```
+pub fn append_from_bytes<'a, T>(target: &mut Vec<T>, bytes: &'a [u8]) -> Result<()>
+where
+    T: Deserialize<'a>,
+{
+    let len = target.len();
+    let result = from_bytes_seed(AppendSeed(target), bytes);
+    if result.is_err() {
+        target.truncate(len);
+    }
+    result
+}
+
+/// Appends the elements of a sequence to a vector, for `append_from_bytes`.
+struct AppendSeed<'v, T>(&'v mut Vec<T>);
+
+impl<'de, T> DeserializeSeed<'de> for AppendSeed<'_, T>
+where
+    T: Deserialize<'de>,
+{
+    type Value = ();
+
+    fn deserialize<D>(self, deserializer: D) -> core::result::Result<(), D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, T> Visitor<'de> for AppendSeed<'_, T>
+where
+    T: Deserialize<'de>,
+{
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("a sequence")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> core::result::Result<(), A::Error>
+    where
+        A: de::SeqAccess<'de>,
+    {
+        let mut remaining = seq.size_hint().unwrap_or(0);
+        while let Some(value) = seq.next_element()? {
+            if self.0.len() == self.0.capacity() {
+                self.0.reserve(remaining.clamp(1, 4096));
+            }
+            self.0.push(value);
+            remaining = remaining.saturating_sub(1);
+        }
+        Ok(())
+    }
+}
+
+/// Deserializes one value from the start of `input` and advances `input` past it.
+///
+/// Unlike `from_bytes`, trailing bytes are not an error: they are left in `input` for the next
+/// read. On error, `input` is left unchanged.
+///
+/// # Examples
+///
+/// ```
+/// use bcs::{from_slice_advancing, to_bytes};
+///
+/// let mut bytes = to_bytes(&"header").unwrap();
+/// bytes.push(0xff); // custom marker byte
+/// bytes.extend(to_bytes(&42u32).unwrap());
+///
+/// let mut input = &bytes[..];
+/// let header: &str = from_slice_advancing(&mut input).unwrap();
+/// assert_eq!(header, "header");
+/// assert_eq!(input[0], 0xff);
+/// input = &input[1..];
+/// assert_eq!(from_slice_advancing::<u32>(&mut input).unwrap(), 42);
+/// assert!(input.is_empty());
+/// ```
+pub fn from_slice_advancing<'a, T>(input: &mut &'a [u8]) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    let mut deserializer = Deserializer::new(input, crate::MAX_CONTAINER_DEPTH);
+    let t = T::deserialize(&mut deserializer)?;
+    *input = deserializer.input;
+    Ok(t)
+}
+
+/// Deserializes one value from the start of `bytes`, returning it with the number of bytes it
+/// occupies.
+///
+/// Trailing bytes are not an error. This is the same as `from_slice_advancing`, for callers that
+/// prefer offsets into the original buffer over the rest of the slice.
+///
+/// # Examples
+///
+/// ```
+/// use bcs::{to_bytes, try_from_bytes};
+///
+/// let mut bytes = to_bytes(&"one").unwrap();
+/// bytes.extend(to_bytes(&2u16).unwrap());
+///
+/// let (first, len): (&str, usize) = try_from_bytes(&bytes).unwrap();
+/// assert_eq!((first, len), ("one", 4));
+/// let (second, _): (u16, usize) = try_from_bytes(&bytes[len..]).unwrap();
+/// assert_eq!(second, 2);
+/// ```
+pub fn try_from_bytes<'a, T>(bytes: &'a [u8]) -> Result<(T, usize)>
+where
+    T: Deserialize<'a>,
+{
+    let mut input = bytes;
+    let t = from_slice_advancing(&mut input)?;
+    Ok((t, bytes.len() - input.len()))
+}
+
+/// Deserializes a BCS-encoded map from `bytes`, calling `f` on each entry in order.
+///
+/// Besides the decoded key and value, `f` receives the serialized bytes of the key, borrowed
+/// from `bytes`. This is useful for authenticated data structures that commit to the encoded
+/// keys, as it avoids serializing the keys a second time. Map entries are validated exactly as
+/// when deserializing a map type (canonical ordering, no trailing bytes, etc.), so `f` may be
+/// called on some entries before an error is returned.
+///
+/// # Examples
+///
+/// ```
+/// use bcs::{from_bytes_map_entries, to_bytes};
+/// use std::collections::BTreeMap;
+///
+/// let mut map = BTreeMap::new();
+/// map.insert("a".to_owned(), 1u8);
+/// map.insert("bc".to_owned(), 2u8);
+///
+/// let bytes = to_bytes(&map).unwrap();
+/// let mut leaves = Vec::new();
+/// from_bytes_map_entries(&bytes, |key_bytes: &[u8], _key: String, value: u8| {
+///     leaves.push((key_bytes.to_vec(), value));
+/// })
+/// .unwrap();
+/// assert_eq!(leaves, vec![(vec![1, b'a'], 1), (vec![2, b'b', b'c'], 2)]);
+/// ```
+pub fn from_bytes_map_entries<'a, K, V, F>(bytes: &'a [u8], mut f: F) -> Result<()>
+where
+    K: Deserialize<'a>,
+    V: Deserialize<'a>,
+    F: FnMut(&'a [u8], K, V),
+{
+    let mut deserializer = Deserializer::new(bytes, crate::MAX_CONTAINER_DEPTH);
+    let len = deserializer.parse_map_length()?;
+    let mut map = MapDeserializer::new(&mut deserializer, len);
+    while let Some((key, key_bytes)) = map.next_key_seed_with_bytes(PhantomData::<K>)? {
+        let key_bytes = *key_bytes;
+        let value = de::MapAccess::next_value::<V>(&mut map)?;
+        f(key_bytes, key, value);
+    }
+    deserializer.end()
+}
+
+/// Deserializes a BCS-encoded map from the start of `bytes`, passing each entry to `f` in order,
+/// and returns the rest of the input.
+///
+/// This streams the entries of a map into another data structure, e.g. a database, without
+/// materializing the whole map first. Entries are validated exactly as when deserializing a map
+/// type (canonical ordering, no duplicates), so `f` may be called on some entries before an
+/// error is returned. An error returned by `f` stops the decoding and is returned as is.
+///
+/// # Examples
+///
+/// ```
+/// use bcs::{for_each_map_entry, to_bytes};
+/// use std::collections::BTreeMap;
+///
+/// let map: BTreeMap<u8, String> = (0..3).map(|i| (i, i.to_string())).collect();
+/// let mut bytes = to_bytes(&map).unwrap();
+/// bytes.push(0xff);
+///
+/// let mut total = 0;
+/// let rest = for_each_map_entry(&bytes, |key: u8, value: String| {
+///     total += u32::from(key) * value.len() as u32;
+///     Ok(())
+/// })
+/// .unwrap();
+/// assert_eq!(total, 3);
+/// assert_eq!(rest, &[0xff]);
+/// ```
+pub fn for_each_map_entry<'a, K, V, F>(bytes: &'a [u8], f: F) -> Result<&'a [u8]>
+where
+    K: Deserialize<'a>,
+    V: Deserialize<'a>,
+    F: FnMut(K, V) -> Result<()>,
+{
+    for_each_map_entry_seed(bytes, |_| ValueAction::Decode(PhantomData::<V>), f)
+}
+
+/// What `for_each_map_entry_seed` does with the value of a map entry, chosen from its key.
+#[derive(Clone, Copy, Debug)]
+pub enum ValueAction<'l, S> {
+    /// Decode the value with the seed, and pass it to the callback with the key.
+    Decode(S),
+    /// Skip the value, which has the given layout, without allocating anything for it.
+    Skip(&'l Layout),
+}
+
+/// Same as `for_each_map_entry`, but decodes only the values of the keys that `select` picks,
+/// and skips the others according to their layout.
+///
+/// For each entry, `select` is given the key and returns either a seed to decode the value
+/// with, in which case `f` receives the key and the decoded value, or the layout of the value
+/// to skip it cheaply. This suits forward-compatible formats, where unknown entries must be
+/// tolerated but not decoded. All the keys are still checked for canonical ordering and
+/// uniqueness, and the skipped values are validated as with `skip_value`.
+///
+/// # Examples
+///
+/// ```
+/// use bcs::{for_each_map_entry_seed, to_bytes, Layout, ValueAction};
+/// use std::collections::BTreeMap;
+/// use std::marker::PhantomData;
+///
+/// let mut settings = BTreeMap::new();
+/// settings.insert("primes", vec![2u32, 3, 5, 7]);
+/// settings.insert("unused", vec![0; 10_000]);
+/// let bytes = to_bytes(&settings).unwrap();
+///
+/// let skipped = Layout::Seq(Box::new(Layout::U32));
+/// let mut primes = Vec::new();
+/// for_each_map_entry_seed(
+///     &bytes,
+///     |key: &&str| match *key {
+///         "primes" => ValueAction::Decode(PhantomData::<Vec<u32>>),
+///         _ => ValueAction::Skip(&skipped),
+///     },
+///     |_, value| {
+///         primes = value;
+///         Ok(())
+///     },
+/// )
+/// .unwrap();
+/// assert_eq!(primes, vec![2, 3, 5, 7]);
+/// ```
+pub fn for_each_map_entry_seed<'a, 'l, K, S, G, F>(
+    bytes: &'a [u8],
+    mut select: G,
+    mut f: F,
+) -> Result<&'a [u8]>
+where
+    K: Deserialize<'a>,
+    S: DeserializeSeed<'a>,
+    G: FnMut(&K) -> ValueAction<'l, S>,
+    F: FnMut(K, S::Value) -> Result<()>,
+{
+    let mut deserializer = Deserializer::new(bytes, crate::MAX_CONTAINER_DEPTH);
+    let len = deserializer.parse_map_length()?;
+    let mut map = MapDeserializer::new(&mut deserializer, len);
+    while let Some(key) = de::MapAccess::next_key::<K>(&mut map)? {
+        match select(&key) {
+            ValueAction::Decode(seed) => {
+                let value = de::MapAccess::next_value_seed(&mut map, seed)?;
+                f(key, value)?;
+            }
+            ValueAction::Skip(layout) => de::MapAccess::next_value_seed(&mut map, Skip(layout))?,
+        }
+    }
+    Ok(deserializer.input)
+}
+
+/// Reads the ULEB128-encoded length prefix of a variable-length sequence, string, or map.
+///
+/// The prefix is subject to the same checks as during deserialization, including
+/// `MAX_SEQUENCE_LENGTH`. Returns the length and the rest of `bytes` after the prefix.
+///
+/// # Examples
+///
+/// ```
+/// use bcs::{peek_length, to_bytes};
+///
+/// let bytes = to_bytes(&vec![7u32; 200]).unwrap();
+/// let (len, rest) = peek_length(&bytes).unwrap();
+/// assert_eq!(len, 200);
+/// assert_eq!(rest.len(), len * 4);
+/// ```
+pub fn peek_length(bytes: &[u8]) -> Result<(usize, &[u8])> {
+    let mut deserializer = Deserializer::new(bytes, crate::MAX_CONTAINER_DEPTH);
+    let len = deserializer.parse_length()?;
+    Ok((len, deserializer.input))
+}
+
+/// Consumes exactly one value of the given `layout` from the start of `bytes` and returns the
+/// remaining input.
+///
+/// The skipped value is validated as if it was being deserialized (lengths, option and boolean
+/// bytes, variant indices, UTF-8 strings, canonical map ordering, and container depth), but
+/// nothing is allocated for it.
+///
+/// # Examples
+///
+/// ```
+/// use bcs::{skip_value, to_bytes, Layout};
+///
+/// let bytes = to_bytes(&(vec!["skip", "me"], 42u16)).unwrap();
+/// let rest = skip_value(&bytes, &Layout::Seq(Box::new(Layout::Str))).unwrap();
+/// assert_eq!(rest, &42u16.to_le_bytes());
+/// ```
+pub fn skip_value<'a>(bytes: &'a [u8], layout: &Layout) -> Result<&'a [u8]> {
+    let mut deserializer = Deserializer::new(bytes, crate::MAX_CONTAINER_DEPTH);
+    Skip(layout).deserialize(&mut deserializer)?;
+    Ok(deserializer.input)
+}
+
+/// The differences between two BCS-encoded maps, returned by `map_diff`.
+///
+/// Keys and values are the serialized bytes of the entries, borrowed from the inputs, in the
+/// canonical order of the keys.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct MapDiff<'a> {
+    /// The keys and values of the entries that are only in the second map.
+    pub added: Vec<(&'a [u8], &'a [u8])>,
+    /// The keys and values of the entries that are only in the first map.
+    pub removed: Vec<(&'a [u8], &'a [u8])>,
+    /// The keys in both maps whose values differ, with the value in the first map and the value
+    /// in the second map.
+    pub changed: Vec<(&'a [u8], &'a [u8], &'a [u8])>,
+}
+
+impl<'a> MapDiff<'a> {
+    /// Returns whether the two maps are identical.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Compares two BCS-encoded maps entry by entry, without decoding their values.
+///
+/// Keys are skipped with `key_layout` and values with `value_layout`, and both maps are
+/// validated as with `skip_value` (canonical key order, no trailing bytes). Since the keys of
+/// both maps are sorted, the comparison is a single merge over the entries. Values are compared
+/// by their serialized bytes, which is equivalent to comparing the values thanks to the
+/// canonical encoding.
+///
+/// # Examples
+///
+/// ```
+/// use bcs::{map_diff, to_bytes, Layout};
+/// use std::collections::BTreeMap;
+///
+/// let before: BTreeMap<u8, &str> = [(1, "one"), (2, "two"), (3, "three")].into();
+/// let after: BTreeMap<u8, &str> = [(2, "deux"), (3, "three"), (4, "four")].into();
+/// let (before, after) = (to_bytes(&before).unwrap(), to_bytes(&after).unwrap());
+///
+/// let diff = map_diff(&before, &after, &Layout::U8, &Layout::Str).unwrap();
+/// assert_eq!(diff.added, vec![(&[4][..], &b"\x04four"[..])]);
+/// assert_eq!(diff.removed, vec![(&[1][..], &b"\x03one"[..])]);
+/// assert_eq!(diff.changed, vec![(&[2][..], &b"\x03two"[..], &b"\x04deux"[..])]);
+/// ```
+pub fn map_diff<'a>(
+    a: &'a [u8],
+    b: &'a [u8],
+    key_layout: &Layout,
+    value_layout: &Layout,
+) -> Result<MapDiff<'a>> {
+    let a = map_entry_bytes(a, key_layout, value_layout)?;
+    let b = map_entry_bytes(b, key_layout, value_layout)?;
+    let mut diff = MapDiff::default();
+    let (mut a, mut b) = (a.into_iter().peekable(), b.into_iter().peekable());
+    loop {
+        match (a.peek(), b.peek()) {
+            (None, None) => return Ok(diff),
+            (Some(_), None) => diff.removed.extend(a.by_ref()),
+            (None, Some(_)) => diff.added.extend(b.by_ref()),
+            (Some((a_key, a_value)), Some((b_key, b_value))) => {
+                match canonical_key_cmp(a_key, b_key) {
+                    Ordering::Less => diff.removed.extend(a.next()),
+                    Ordering::Greater => diff.added.extend(b.next()),
+                    Ordering::Equal => {
+                        if a_value != b_value {
+                            diff.changed.push((a_key, a_value, b_value));
+                        }
+                        a.next();
+                        b.next();
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Splits a BCS-encoded map into the serialized bytes of its keys and values.
+fn map_entry_bytes<'a>(
+    bytes: &'a [u8],
+    key_layout: &Layout,
+    value_layout: &Layout,
+) -> Result<Vec<(&'a [u8], &'a [u8])>> {
+    let mut deserializer = Deserializer::new(bytes, crate::MAX_CONTAINER_DEPTH);
+    let len = deserializer.parse_map_length()?;
+    let mut entries = Vec::with_capacity(len);
+    let mut map = MapDeserializer::new(&mut deserializer, len);
+    while let Some(((), key_bytes)) = map.next_key_seed_with_bytes(Skip(key_layout))? {
+        let key_bytes = *key_bytes;
+        let value_start = map.de.input;
+        de::MapAccess::next_value_seed(&mut map, Skip(value_layout))?;
+        let value_len = value_start.len() - map.de.input.len();
+        entries.push((key_bytes, &value_start[..value_len]));
+    }
+    deserializer.end()?;
+    Ok(entries)
+}
+
+/// Same as `from_bytes`, but also checks the input against the expected `layout` of `T`.
+///
+/// BCS tuples, structs, and arrays carry no length, so a `Deserialize` implementation asking
+/// for the wrong number of elements silently decodes the following bytes as the next fields.
+/// This function catches such bugs by requiring that the input is a valid value of `layout`
+/// and that decoding `T` consumes exactly as many bytes as the layout does; otherwise
+/// `Error::LayoutMismatch` is returned. Errors raised while decoding `T` itself, such as
+/// `Error::Eof` for a type reading past the end of the input, are returned as is.
+///
+/// This also catches a `#[serde(skip)]` field that is skipped by only one of the encoder
+/// and the decoder, which shifts all the fields after it.
+///
+/// This is a consistency check, not a proof: a type whose shape differs from `layout` but
+/// happens to consume the same number of bytes (e.g. `(u8, u8)` against a single `U16`) is
+/// not detected.
+///
+/// # Examples
+///
+/// ```
+/// use bcs::{from_bytes_with_layout, to_bytes, Error, Layout};
+///
+/// let layout = Layout::Tuple(vec![Layout::U8, Layout::Str, Layout::U8]);
+/// let bytes = to_bytes(&(1u8, "ab", 2u8)).unwrap();
+///
+/// let value: (u8, String, u8) = from_bytes_with_layout(&bytes, &layout).unwrap();
+/// assert_eq!(value, (1, "ab".to_owned(), 2));
+///
+/// // Reading two elements instead of three stops one byte short of the layout.
+/// assert_eq!(
+///     from_bytes_with_layout::<(u8, String)>(&bytes, &layout),
+///     Err(Error::LayoutMismatch { expected: 5, found: 4 }),
+/// );
+/// ```
+pub fn from_bytes_with_layout<'a, T>(bytes: &'a [u8], layout: &Layout) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    let expected = bytes.len() - skip_value(bytes, layout)?.len();
+    let mut deserializer = Deserializer::new(bytes, crate::MAX_CONTAINER_DEPTH);
+    let t = T::deserialize(&mut deserializer)?;
+    let found = bytes.len() - deserializer.input.len();
+    if found != expected {
+        return Err(Error::LayoutMismatch { expected, found });
+    }
+    deserializer.end()?;
+    Ok(t)
+}
+
 /// Deserialize a type from an implementation of [`Read`].
 pub fn from_reader<T>(mut reader: impl Read) -> Result<T>
 where
@@ -103,6 +878,262 @@ where
     Ok(t)
 }
 
+/// Same as `from_reader`, but reads through a trait object.
+///
+/// `from_reader` is generic over the reader, so the whole decoder for `T` is compiled again for
+/// every reader type it is called with. This function is instantiated once per `T`, whatever
+/// the reader is, at the cost of a dynamic call for each read. Prefer it when binary size
+/// matters more than decoding speed, e.g. in firmware reading from several peripherals.
+///
+/// # Examples
+///
+/// ```
+/// use bcs::{from_dyn_reader, io::Read, to_bytes};
+///
+/// let bytes = to_bytes(&(1u8, vec![2u16, 3])).unwrap();
+/// let mut slice = &bytes[..];
+/// let mut cursor = std::io::Cursor::new(bytes.clone());
+/// let readers: [&mut dyn Read; 2] = [&mut slice, &mut cursor];
+/// for reader in readers {
+///     let value: (u8, Vec<u16>) = from_dyn_reader(reader).unwrap();
+///     assert_eq!(value, (1, vec![2, 3]));
+/// }
+/// ```
+pub fn from_dyn_reader<T>(reader: &mut dyn Read) -> Result<T>
+where
+    T: DeserializeOwned,
+{
+    let mut deserializer = Deserializer::from_reader(reader, crate::MAX_CONTAINER_DEPTH);
+    let t = T::deserialize(&mut deserializer)?;
+    deserializer.end()?;
+    Ok(t)
+}
+
+/// Deserialize a type from a byte slice used as a reader, borrowing from it where `T` allows.
+///
+/// `&[u8]` implements [`Read`], so generic code often holds a `&mut &[u8]` where `from_reader`
+/// would take any reader. `from_reader` cannot lend out its input and therefore requires
+/// `T: DeserializeOwned`; this function accepts the same reader shape but decodes straight from
+/// the slice, so `T` only needs to implement `Deserialize<'a>`. Borrowed fields such as `&'a str`
+/// or [`Bytes<'a>`](crate::Bytes) then point into the slice `*reader` refers to, not into
+/// `reader` itself: the result may outlive the `&mut` borrow, but not the underlying buffer.
+///
+/// As with `from_reader`, the value must span the whole input, otherwise
+/// `Error::RemainingInput` is returned. On success `*reader` is left empty, exactly as if the
+/// bytes had been read; on error it is left unchanged.
+///
+/// # Examples
+///
+/// ```
+/// use bcs::{from_slice_reader, to_bytes};
+///
+/// let bytes = to_bytes(&(7u8, "borrowed")).unwrap();
+/// let mut reader = &bytes[..];
+/// let (tag, name): (u8, &str) = from_slice_reader(&mut reader).unwrap();
+/// assert_eq!((tag, name), (7, "borrowed"));
+/// assert!(reader.is_empty());
+/// ```
+pub fn from_slice_reader<'a, T>(reader: &mut &'a [u8]) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    let t = from_bytes(reader)?;
+    *reader = &reader[reader.len()..];
+    Ok(t)
+}
+
+/// Deserialize a type from an implementation of [`Read`], also returning the number of bytes
+/// that were consumed.
+///
+/// Unlike `from_reader`, this does not check that the reader is exhausted afterwards, so the
+/// value can be followed by other data. No byte past the end of the value is read.
+///
+/// # Examples
+///
+/// ```
+/// use bcs::{from_reader_counted, to_bytes};
+///
+/// let mut bytes = to_bytes(&(1u16, "hello")).unwrap();
+/// bytes.extend_from_slice(b"next frame");
+///
+/// let mut reader = &bytes[..];
+/// let (value, len): ((u16, String), usize) = from_reader_counted(&mut reader).unwrap();
+/// assert_eq!(value, (1, "hello".to_owned()));
+/// assert_eq!(len, 8);
+/// assert_eq!(reader, b"next frame");
+/// ```
+pub fn from_reader_counted<T>(mut reader: impl Read) -> Result<(T, usize)>
+where
+    T: DeserializeOwned,
+{
+    let mut deserializer = Deserializer::from_reader(&mut reader, crate::MAX_CONTAINER_DEPTH);
+    let t = T::deserialize(&mut deserializer)?;
+    Ok((t, deserializer.input.bytes_read))
+}
+
+/// Deserialize a type from an iterator of bytes, such as a UART or a ring buffer, without
+/// collecting the whole input first.
+///
+/// This is available without `std` and behaves exactly like `from_reader`: the iterator must
+/// end right after the value, otherwise `Error::RemainingInput` is returned.
+///
+/// # Examples
+///
+/// ```
+/// use bcs::{from_byte_iter, to_bytes};
+///
+/// let bytes = to_bytes(&(7u32, vec![1u8, 2, 3])).unwrap();
+/// let value: (u32, Vec<u8>) = from_byte_iter(bytes.into_iter()).unwrap();
+/// assert_eq!(value, (7, vec![1, 2, 3]));
+/// ```
+pub fn from_byte_iter<T, I>(iter: I) -> Result<T>
+where
+    T: DeserializeOwned,
+    I: Iterator<Item = u8>,
+{
+    from_reader(ByteIterReader(iter))
+}
+
+/// Deserialize a type from the concatenation of several byte slices, such as the buffers of a
+/// scatter-gather read, without joining them first.
+///
+/// The chunks are read in order as a single input, and values may span any number of chunk
+/// boundaries; empty chunks are ignored. As with `from_reader`, the value must span the whole
+/// input, otherwise `Error::RemainingInput` is returned.
+///
+/// `T` must implement `DeserializeOwned`: strings and byte arrays are copied into the decoded
+/// value, whether they span a boundary or lie within a single chunk. Only a field spanning a
+/// boundary would need a copy in any case, so when the whole value lies in one chunk, decode
+/// that chunk with `from_bytes` instead to borrow from it.
+///
+/// # Examples
+///
+/// ```
+/// use bcs::{from_chunks, to_bytes};
+///
+/// let bytes = to_bytes(&(7u32, "split")).unwrap();
+/// let (header, body) = bytes.split_at(6);
+/// let value: (u32, String) = from_chunks(&[header, &[], body]).unwrap();
+/// assert_eq!(value, (7, "split".to_owned()));
+/// ```
+pub fn from_chunks<T>(chunks: &[&[u8]]) -> Result<T>
+where
+    T: DeserializeOwned,
+{
+    from_reader(ChunksReader {
+        current: &[],
+        rest: chunks,
+    })
+}
+
+/// An iterator decoding successive values of type `T` from a reader, e.g. all the records of a
+/// file, until the reader is exhausted.
+///
+/// Before each value, one byte is read: if the reader is already at its end, the stream ends
+/// and `next` returns `None`. Otherwise the value is decoded starting with that byte, and a
+/// reader ending in the middle of it yields `Error::Eof` (possibly within
+/// `Error::EofInOption`). No byte past a value is read before the next call to `next`, and
+/// values are not required to end the input, so the stream can be interleaved with other reads.
+///
+/// The stream ends after the first error. Values of types encoded as zero bytes, such as `()`,
+/// cannot be delimited and fail with `Error::NotSupported`.
+///
+/// # Examples
+///
+/// ```
+/// use bcs::{to_bytes, Error, ReadStreamDeserializer};
+///
+/// let mut file = Vec::new();
+/// for record in [(1u32, "one"), (2, "two"), (3, "three")] {
+///     file.extend(to_bytes(&record).unwrap());
+/// }
+///
+/// let records: Vec<(u32, String)> = ReadStreamDeserializer::new(&file[..])
+///     .collect::<Result<_, _>>()
+///     .unwrap();
+/// assert_eq!(records.len(), 3);
+/// assert_eq!(records[2], (3, "three".to_owned()));
+///
+/// // A partial record at the end is an error, not the end of the stream.
+/// let mut stream = ReadStreamDeserializer::<_, (u32, String)>::new(&file[..file.len() - 1]);
+/// assert!(stream.next().unwrap().is_ok());
+/// assert!(stream.next().unwrap().is_ok());
+/// assert!(matches!(stream.next(), Some(Err(Error::Eof { .. }))));
+/// assert!(stream.next().is_none());
+/// ```
+pub struct ReadStreamDeserializer<R, T> {
+    reader: R,
+    done: bool,
+    value: PhantomData<fn() -> T>,
+}
+
+impl<R: Read, T> ReadStreamDeserializer<R, T> {
+    /// Creates a stream of the values of type `T` read from `reader`.
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            done: false,
+            value: PhantomData,
+        }
+    }
+
+    /// Returns the underlying reader, positioned right after the last decoded value.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Reads the first byte of the next value, or `None` at the end of the input.
+    fn read_first_byte(&mut self) -> Result<Option<u8>> {
+        let mut byte = [0u8; 1];
+        loop {
+            match self.reader.read(&mut byte) {
+                Ok(0) => return Ok(None),
+                Ok(_) => return Ok(Some(byte[0])),
+                #[cfg(feature = "std")]
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+impl<R: Read, T: DeserializeOwned> ReadStreamDeserializer<R, T> {
+    fn decode(&mut self, first_byte: u8) -> Result<T> {
+        let mut reader = PrefixedReader {
+            first_byte: Some(first_byte),
+            reader: &mut self.reader,
+        };
+        let mut deserializer = Deserializer::from_reader(&mut reader, crate::MAX_CONTAINER_DEPTH);
+        let value = T::deserialize(&mut deserializer)?;
+        if reader.first_byte.is_some() {
+            return Err(Error::NotSupported(
+                "values encoded as zero bytes cannot be read from a stream",
+            ));
+        }
+        Ok(value)
+    }
+}
+
+impl<R: Read, T: DeserializeOwned> Iterator for ReadStreamDeserializer<R, T> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Result<T>> {
+        if self.done {
+            return None;
+        }
+        let result = match self.read_first_byte() {
+            Ok(None) => {
+                self.done = true;
+                return None;
+            }
+            Ok(Some(first_byte)) => self.decode(first_byte),
+            Err(e) => Err(e),
+        };
+        self.done = result.is_err();
+        Some(result)
+    }
+}
+
 /// Same as `from_reader_seed` but use `limit` as max container depth instead of MAX_CONTAINER_DEPTH`
 /// Note that `limit` has to be lower than MAX_CONTAINER_DEPTH
 pub fn from_reader_with_limit<T>(mut reader: impl Read, limit: usize) -> Result<T>
@@ -118,6 +1149,69 @@ where
     Ok(t)
 }
 
+/// Same as `from_reader` but enforce the limits of the given [`Config`].
+pub fn from_reader_with_config<T>(mut reader: impl Read, config: &Config) -> Result<T>
+where
+    T: DeserializeOwned,
+{
+    let mut deserializer = Deserializer::from_reader(&mut reader, config.max_container_depth);
+    deserializer.configure(config)?;
+    if !config.hex_context_on_error {
+        let t = T::deserialize(&mut deserializer)?;
+        deserializer.end()?;
+        return Ok(t);
+    }
+    deserializer.input.recent = Some(Vec::new());
+    let result = T::deserialize(&mut deserializer).and_then(|t| deserializer.end().map(|()| t));
+    result.map_err(|error| {
+        let input = &deserializer.input;
+        let before = input.recent.as_deref().unwrap_or_default();
+        with_hex_context(error, input.bytes_read, before, &[])
+    })
+}
+
+/// The number of bytes shown on each side of the position of an error by
+/// `Config::hex_context_on_error`.
+const HEX_CONTEXT_LEN: usize = 16;
+
+/// Wraps `error`, raised at byte `offset` of the input, with the end of the bytes `before` that
+/// position and the start of those `after` it.
+#[cold]
+fn with_hex_context(error: Error, offset: usize, before: &[u8], after: &[u8]) -> Error {
+    use core::fmt::Write;
+
+    let shown_before = &before[before.len().saturating_sub(HEX_CONTEXT_LEN)..];
+    let shown_after = &after[..core::cmp::min(after.len(), HEX_CONTEXT_LEN)];
+    let mut context = format!("at byte {}: ", offset);
+    if shown_before.len() < offset {
+        context.push_str(".. ");
+    }
+    for byte in shown_before {
+        let _ = write!(context, "{:02x} ", byte);
+    }
+    context.push('|');
+    for byte in shown_after {
+        let _ = write!(context, " {:02x}", byte);
+    }
+    if shown_after.len() < after.len() {
+        context.push_str(" ..");
+    }
+    Error::WithHexContext {
+        error: Box::new(error),
+        context: context.into_boxed_str(),
+    }
+}
+
+/// Same as `from_bytes_in_place` but read the input from an implementation of [`Read`].
+pub fn from_reader_in_place<T>(mut reader: impl Read, place: &mut T) -> Result<()>
+where
+    T: DeserializeOwned,
+{
+    let mut deserializer = Deserializer::from_reader(&mut reader, crate::MAX_CONTAINER_DEPTH);
+    T::deserialize_in_place(&mut deserializer, place)?;
+    deserializer.end()
+}
+
 /// Deserialize a type from an implementation of [`Read`] using the provided seed
 pub fn from_reader_seed<T, V>(seed: T, mut reader: impl Read) -> Result<V>
 where
@@ -144,57 +1238,254 @@ where
     Ok(t)
 }
 
-/// Deserialization implementation for BCS
-struct Deserializer<R> {
+/// Deserialization implementation for BCS.
+///
+/// The functions of this crate create a `Deserializer` for each call. A `Deserializer` over a
+/// slice can also be created directly, e.g. to try decoding the same input as several types in
+/// turn with [`reset`](Deserializer::reset), or to decode values one after the other and
+/// track the position in the input.
+///
+/// # Examples
+///
+/// ```
+/// use bcs::{Deserializer, Error, MAX_CONTAINER_DEPTH};
+/// use serde::Deserialize;
+///
+/// let bytes = [2, b'h', b'i'];
+/// let mut deserializer = Deserializer::new(&bytes, MAX_CONTAINER_DEPTH);
+///
+/// // Not a pair of `u16`s...
+/// assert!(<(u16, u16)>::deserialize(&mut deserializer).is_err());
+///
+/// // ...but a string.
+/// deserializer.reset(&bytes);
+/// assert_eq!(String::deserialize(&mut deserializer).unwrap(), "hi");
+/// assert!(deserializer.remaining().is_empty());
+/// assert_eq!(deserializer.end(), Ok(()));
+/// ```
+pub struct Deserializer<R> {
     input: R,
+    /// The length of the input when it is a slice, to tell positions in it.
+    input_len: usize,
     max_remaining_depth: usize,
+    /// The container depth limit this deserializer was created with, kept for error reporting.
+    container_depth_limit: usize,
+    /// How many more sequences and maps may be nested. Unlike named containers, they are always
+    /// bounded by `MAX_CONTAINER_DEPTH`, regardless of the configured limit.
+    max_remaining_sequence_depth: usize,
+    /// How many more sequences, maps, and options may be nested, see
+    /// [`Config::max_collection_nesting`], and the configured limit.
+    max_remaining_collection_nesting: usize,
+    collection_nesting_limit: usize,
+    max_map_entries: usize,
+    /// The order that keys of maps must follow.
+    map_key_order: fn(&[u8], &[u8]) -> Ordering,
+    /// Whether ULEB128 integers may have trailing zero digits.
+    allow_non_canonical_uleb128: bool,
+    /// The limit and running total of the bytes taken in memory by decoded strings, byte
+    /// arrays, sequences, and maps, see [`Config::max_materialized_bytes`].
+    max_materialized_bytes: usize,
+    materialized_bytes: usize,
+    /// Statistics about the input, only collected when requested.
+    stats: Option<DecodeStats>,
+    /// The variant of the outermost enum, only recorded when requested by
+    /// `OuterEnumDeserializer`.
+    outer_variant: Option<Option<&'static str>>,
+    /// The encoded variant indices of some enums, see [`Config::variant_maps`].
+    variant_maps: &'static [VariantMap],
+    /// Whether a field name was decoded, which serde only asks for when a struct with
+    /// `#[serde(flatten)]` fields is decoded as a map.
+    decoded_field_name: bool,
 }
 
-impl<'de, R: Read> Deserializer<TeeReader<'de, R>> {
+impl<'de, R: ?Sized + Read> Deserializer<TeeReader<'de, R>> {
     fn from_reader(input: &'de mut R, max_remaining_depth: usize) -> Self {
         Deserializer {
             input: TeeReader::new(input),
+            input_len: 0,
             max_remaining_depth,
+            container_depth_limit: max_remaining_depth,
+            max_remaining_sequence_depth: crate::MAX_CONTAINER_DEPTH,
+            max_remaining_collection_nesting: usize::MAX,
+            collection_nesting_limit: usize::MAX,
+            max_map_entries: crate::MAX_SEQUENCE_LENGTH,
+            map_key_order: canonical_key_cmp,
+            allow_non_canonical_uleb128: false,
+            max_materialized_bytes: usize::MAX,
+            materialized_bytes: 0,
+            stats: None,
+            outer_variant: None,
+            variant_maps: &[],
+            decoded_field_name: false,
         }
     }
 }
 
 impl<'de> Deserializer<&'de [u8]> {
     /// Creates a new `Deserializer` which will be deserializing the provided
-    /// input.
-    fn new(input: &'de [u8], max_remaining_depth: usize) -> Self {
+    /// input. `max_container_depth` is capped at `MAX_CONTAINER_DEPTH`.
+    pub fn new(input: &'de [u8], max_container_depth: usize) -> Self {
+        let max_remaining_depth = core::cmp::min(max_container_depth, crate::MAX_CONTAINER_DEPTH);
         Deserializer {
             input,
+            input_len: input.len(),
             max_remaining_depth,
+            container_depth_limit: max_remaining_depth,
+            max_remaining_sequence_depth: crate::MAX_CONTAINER_DEPTH,
+            max_remaining_collection_nesting: usize::MAX,
+            collection_nesting_limit: usize::MAX,
+            max_map_entries: crate::MAX_SEQUENCE_LENGTH,
+            map_key_order: canonical_key_cmp,
+            allow_non_canonical_uleb128: false,
+            max_materialized_bytes: usize::MAX,
+            materialized_bytes: 0,
+            stats: None,
+            outer_variant: None,
+            variant_maps: &[],
+            decoded_field_name: false,
+        }
+    }
+
+    /// Rebinds the deserializer to `input`, and restores its container depth limit. Other
+    /// settings are kept.
+    pub fn reset(&mut self, input: &'de [u8]) {
+        self.input = input;
+        self.input_len = input.len();
+        self.max_remaining_depth = self.container_depth_limit;
+        self.max_remaining_sequence_depth = crate::MAX_CONTAINER_DEPTH;
+        self.max_remaining_collection_nesting = self.collection_nesting_limit;
+        self.materialized_bytes = 0;
+        self.decoded_field_name = false;
+    }
+
+    /// Returns the part of the input that has not been decoded yet.
+    ///
+    /// This is the way to decode a value followed by other data, e.g. an application-specific
+    /// suffix: decode the value, then read the rest of the input from here instead of calling
+    /// [`end`](Deserializer::end). The returned slice borrows from the input, not from the
+    /// deserializer, so it can be kept after the deserializer is dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bcs::{to_bytes, Deserializer};
+    /// use serde::Deserialize;
+    ///
+    /// let mut bytes = to_bytes(&(3u8, "payload")).unwrap();
+    /// bytes.extend_from_slice(b"\r\n");
+    ///
+    /// let mut deserializer = Deserializer::new(&bytes, bcs::MAX_CONTAINER_DEPTH);
+    /// let value = <(u8, &str)>::deserialize(&mut deserializer).unwrap();
+    /// assert_eq!(value, (3, "payload"));
+    /// assert_eq!(deserializer.remaining(), b"\r\n");
+    /// ```
+    pub fn remaining(&self) -> &'de [u8] {
+        self.input
+    }
+
+    /// Checks that the whole input was decoded, failing with `Error::RemainingInput` otherwise.
+    pub fn end(&mut self) -> Result<()> {
+        BcsDeserializer::end(self)
+    }
+}
+
+/// A reader that can optionally capture all bytes from an underlying [`Read`]er
+struct TeeReader<'de, R: ?Sized> {
+    /// the underlying reader
+    reader: &'de mut R,
+    /// If non-empty, all bytes read from the underlying reader will be captured in the last entry here.
+    /// Each entry holds the bytes of a value being captured, such as a map key.
+    captures: Vec<Vec<u8>>,
+    /// The total number of bytes read from the underlying reader so far.
+    bytes_read: usize,
+    /// If set, the last bytes read from the underlying reader, for `Config::hex_context_on_error`.
+    recent: Option<Vec<u8>>,
+}
+
+impl<'de, R: ?Sized> TeeReader<'de, R> {
+    /// Wraps the provided reader in a new [`TeeReader`].
+    pub fn new(reader: &'de mut R) -> Self {
+        Self {
+            reader,
+            captures: Vec::new(),
+            bytes_read: 0,
+            recent: None,
         }
     }
 }
 
-/// A reader that can optionally capture all bytes from an underlying [`Read`]er
-struct TeeReader<'de, R> {
-    /// the underlying reader
-    reader: &'de mut R,
-    /// If non-empty, all bytes read from the underlying reader will be captured in the last entry here.
-    captured_keys: Vec<Vec<u8>>,
+impl<'de, R: ?Sized + Read> Read for TeeReader<'de, R> {
+    fn read(&mut self, buf: &mut [u8]) -> crate::io::Result<usize> {
+        let bytes_read = self.reader.read(buf)?;
+        if let Some(buffer) = self.captures.last_mut() {
+            buffer.extend_from_slice(&buf[..bytes_read]);
+        }
+        self.bytes_read += bytes_read;
+        if let Some(recent) = &mut self.recent {
+            recent.extend_from_slice(&buf[bytes_read.saturating_sub(HEX_CONTEXT_LEN)..bytes_read]);
+            if recent.len() > 2 * HEX_CONTEXT_LEN {
+                recent.drain(..recent.len() - HEX_CONTEXT_LEN);
+            }
+        }
+        Ok(bytes_read)
+    }
+}
+
+/// Adapts an iterator of bytes to [`Read`].
+struct ByteIterReader<I>(I);
+
+impl<I: Iterator<Item = u8>> Read for ByteIterReader<I> {
+    fn read(&mut self, buf: &mut [u8]) -> crate::io::Result<usize> {
+        let mut len = 0;
+        for (slot, byte) in buf.iter_mut().zip(&mut self.0) {
+            *slot = byte;
+            len += 1;
+        }
+        Ok(len)
+    }
+}
+
+/// Reads a list of byte slices one after the other.
+struct ChunksReader<'a, 'b> {
+    current: &'b [u8],
+    rest: &'a [&'b [u8]],
 }
 
-impl<'de, R> TeeReader<'de, R> {
-    /// Wraps the provided reader in a new [`TeeReader`].
-    pub fn new(reader: &'de mut R) -> Self {
-        Self {
-            reader,
-            captured_keys: Vec::new(),
+impl Read for ChunksReader<'_, '_> {
+    fn read(&mut self, buf: &mut [u8]) -> crate::io::Result<usize> {
+        let mut len = 0;
+        while len < buf.len() {
+            if self.current.is_empty() {
+                match self.rest.split_first() {
+                    Some((chunk, rest)) => (self.current, self.rest) = (chunk, rest),
+                    None => break,
+                }
+            }
+            let copied = self.current.len().min(buf.len() - len);
+            buf[len..len + copied].copy_from_slice(&self.current[..copied]);
+            self.current = &self.current[copied..];
+            len += copied;
         }
+        Ok(len)
     }
 }
 
-impl<'de, R: Read> Read for TeeReader<'de, R> {
+/// Puts back a byte in front of a reader.
+struct PrefixedReader<'a, R> {
+    first_byte: Option<u8>,
+    reader: &'a mut R,
+}
+
+impl<R: Read> Read for PrefixedReader<'_, R> {
     fn read(&mut self, buf: &mut [u8]) -> crate::io::Result<usize> {
-        let bytes_read = self.reader.read(buf)?;
-        if let Some(buffer) = self.captured_keys.last_mut() {
-            buffer.extend_from_slice(&buf[..bytes_read]);
+        match (self.first_byte, buf.first_mut()) {
+            (Some(byte), Some(slot)) => {
+                *slot = byte;
+                self.first_byte = None;
+                Ok(1)
+            }
+            _ => self.reader.read(buf),
         }
-        Ok(bytes_read)
     }
 }
 
@@ -211,7 +1502,24 @@ trait BcsDeserializer<'de> {
     where
         V: Visitor<'de>;
 
-    fn next_key_seed<V: DeserializeSeed<'de>>(
+    /// Same as `parse_and_visit_str`, for a visitor that owns the string it decodes, which is
+    /// thus charged to the materialization budget even when it could be borrowed.
+    fn parse_and_visit_string<V>(&mut self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>;
+
+    /// Same as `parse_and_visit_bytes`, for a visitor that owns the bytes it decodes.
+    fn parse_and_visit_byte_buf<V>(&mut self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>;
+
+    /// Reads exactly `len` bytes, without length prefix, and visits them as bytes.
+    fn parse_and_visit_fixed_bytes<V>(&mut self, len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>;
+
+    /// Deserializes a value, such as a map key, and also returns its serialized bytes.
+    fn capture_seed<V: DeserializeSeed<'de>>(
         &mut self,
         seed: V,
     ) -> Result<(V::Value, Self::MaybeBorrowedBytes)>;
@@ -221,13 +1529,22 @@ trait BcsDeserializer<'de> {
     /// the there are no more bytes remaining in the input stream.
     fn end(&mut self) -> Result<()>;
 
+    /// The number of bytes left in the input, if known in advance.
+    fn remaining_input_len(&self) -> Option<usize>;
+
+    /// The number of bytes of the input consumed so far.
+    fn offset(&self) -> usize;
+
+    /// Whether non-minimal ULEB128 encodings are accepted, see [`Config`].
+    fn allows_non_canonical_uleb128(&self) -> bool;
+
     fn parse_bool(&mut self) -> Result<bool> {
         let byte = self.next()?;
 
         match byte {
             0 => Ok(false),
             1 => Ok(true),
-            _ => Err(Error::ExpectedBoolean),
+            _ => Err(Error::ExpectedBoolean(byte)),
         }
     }
 
@@ -273,7 +1590,7 @@ trait BcsDeserializer<'de> {
             value |= u64::from(digit) << shift;
             // If the highest bit of `byte` is 0, return the final value.
             if digit == byte {
-                if shift > 0 && digit == 0 {
+                if shift > 0 && digit == 0 && !self.allows_non_canonical_uleb128() {
                     // We only accept canonical ULEB128 encodings, therefore the
                     // heaviest (and last) base-128 digit must be non-zero.
                     return Err(Error::NonCanonicalUleb128Encoding);
@@ -288,33 +1605,107 @@ trait BcsDeserializer<'de> {
     }
 
     fn parse_length(&mut self) -> Result<usize> {
-        let len = self.parse_u32_from_uleb128()? as usize;
-        if len > crate::MAX_SEQUENCE_LENGTH {
-            return Err(Error::ExceededMaxLen(len));
+        let len = self.parse_u32_from_uleb128()?;
+        // Converted without `as`, which would truncate lengths on targets with a 16-bit `usize`.
+        match usize::try_from(len) {
+            Ok(len) if len <= crate::MAX_SEQUENCE_LENGTH => Ok(len),
+            converted => Err(Error::ExceededMaxLen {
+                len: converted.unwrap_or(usize::MAX),
+                max: crate::MAX_SEQUENCE_LENGTH,
+            }),
         }
-        Ok(len)
     }
 }
 
-impl<'de, R: Read> Deserializer<TeeReader<'de, R>> {
+/// The size of the first chunk read for strings and byte arrays from a reader. Each following
+/// chunk doubles the buffer.
+const READ_CHUNK_LEN: usize = 64 * 1024;
+
+impl<'de, R: ?Sized + Read> Deserializer<TeeReader<'de, R>> {
     fn parse_vec(&mut self) -> Result<Vec<u8>> {
+        self.parse_vec_checked(|_| Ok(()))
+    }
+
+    /// Same as `parse_vec`, but calls `check` with the bytes read so far after each chunk but the
+    /// last, so that invalid data can be rejected before the rest is read.
+    fn parse_vec_checked(&mut self, mut check: impl FnMut(&[u8]) -> Result<()>) -> Result<Vec<u8>> {
         let len = self.parse_length()?;
-        let mut output = vec![0; len];
-        self.fill_slice(&mut output)?;
+        self.materialize(len)?;
+        // The buffer grows as the bytes arrive instead of being allocated upfront, so that a
+        // forged length cannot request up to `MAX_SEQUENCE_LENGTH` bytes from the allocator,
+        // which may abort, especially on 32-bit targets.
+        // The capacity is kept exact, so that the result converts into a `Box<[u8]>` in place.
+        let mut output = Vec::with_capacity(core::cmp::min(len, READ_CHUNK_LEN));
+        while output.len() < len {
+            let filled = output.len();
+            let end = core::cmp::min(len, filled.saturating_add(filled.max(READ_CHUNK_LEN)));
+            output.reserve_exact(end - filled);
+            output.resize(end, 0);
+            self.fill_slice(&mut output[filled..])
+                .map_err(|err| match err {
+                    Error::Eof { available, .. } => Error::Eof {
+                        expected: len,
+                        available: filled + available,
+                    },
+                    err => err,
+                })?;
+            if output.len() < len {
+                check(&output)?;
+            }
+        }
         Ok(output)
     }
 
+    /// Validates the string as its chunks arrive, so that a corrupt string is rejected without
+    /// reading the rest of its declared length.
     fn parse_string(&mut self) -> Result<String> {
-        let vec = self.parse_vec()?;
-        String::from_utf8(vec).map_err(|_| Error::Utf8)
+        let mut valid_up_to = 0;
+        let vec = self.parse_vec_checked(|bytes| match str::from_utf8(&bytes[valid_up_to..]) {
+            Ok(_) => {
+                valid_up_to = bytes.len();
+                Ok(())
+            }
+            // A character split across chunks is checked again with the next one.
+            Err(e) if e.error_len().is_none() => {
+                valid_up_to += e.valid_up_to();
+                Ok(())
+            }
+            Err(e) => Err(Error::InvalidUtf8 {
+                offset: valid_up_to + e.valid_up_to(),
+            }),
+        })?;
+        // The last chunk is only validated here, as part of the whole string, since the conversion
+        // cannot be done without `unsafe` otherwise. This also rejects a string ending in the
+        // middle of a character.
+        String::from_utf8(vec).map_err(|e| Error::InvalidUtf8 {
+            offset: e.utf8_error().valid_up_to(),
+        })
     }
 }
 
-impl<'de, R: Read> BcsDeserializer<'de> for Deserializer<TeeReader<'de, R>> {
+impl<'de, R: ?Sized + Read> BcsDeserializer<'de> for Deserializer<TeeReader<'de, R>> {
     type MaybeBorrowedBytes = Vec<u8>;
 
+    /// Keeps reading until `slice` is full, so readers may deliver the bytes of a single value
+    /// (e.g. a `u128`) in as many chunks as they like. Only a read returning no bytes at all ends
+    /// the input.
     fn fill_slice(&mut self, slice: &mut [u8]) -> Result<()> {
-        Ok(self.input.read_exact(slice)?)
+        let mut filled = 0;
+        while filled < slice.len() {
+            match self.input.read(&mut slice[filled..]) {
+                Ok(0) => {
+                    return Err(Error::Eof {
+                        expected: slice.len(),
+                        available: filled,
+                    })
+                }
+                Ok(len) => filled += len,
+                #[cfg(feature = "std")]
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(())
     }
 
     fn parse_and_visit_str<V>(&mut self, visitor: V) -> Result<V::Value>
@@ -331,41 +1722,82 @@ impl<'de, R: Read> BcsDeserializer<'de> for Deserializer<TeeReader<'de, R>> {
         visitor.visit_byte_buf(self.parse_vec()?)
     }
 
-    fn next_key_seed<V: DeserializeSeed<'de>>(
+    fn parse_and_visit_string<V>(&mut self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.parse_and_visit_str(visitor)
+    }
+
+    fn parse_and_visit_byte_buf<V>(&mut self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.parse_and_visit_bytes(visitor)
+    }
+
+    fn parse_and_visit_fixed_bytes<V>(&mut self, len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let mut output = vec![0; len];
+        self.fill_slice(&mut output)?;
+        visitor.visit_byte_buf(output)
+    }
+
+    fn capture_seed<V: DeserializeSeed<'de>>(
         &mut self,
         seed: V,
     ) -> Result<(V::Value, Self::MaybeBorrowedBytes)> {
-        self.input.captured_keys.push(Vec::new());
-        let key_value = seed.deserialize(&mut *self)?;
-        let key_bytes = self.input.captured_keys.pop().unwrap();
-        if let Some(previous_key) = self.input.captured_keys.last_mut() {
-            previous_key.extend_from_slice(&key_bytes);
+        self.input.captures.push(Vec::new());
+        let value = seed.deserialize(&mut *self)?;
+        let bytes = self.input.captures.pop().unwrap();
+        // The bytes also belong to the enclosing capture, if any.
+        if let Some(enclosing) = self.input.captures.last_mut() {
+            enclosing.extend_from_slice(&bytes);
         }
-        Ok((key_value, key_bytes))
+        Ok((value, bytes))
     }
 
     fn end(&mut self) -> Result<()> {
         let mut byte = [0u8; 1];
         match self.input.read_exact(&mut byte) {
-            Ok(_) => Err(Error::RemainingInput),
+            // Readers are not drained, so only the first trailing byte is accounted for.
+            Ok(_) => Err(Error::RemainingInput {
+                len: 1,
+                first: byte[0],
+            }),
             Err(e) if e.kind() == crate::io::ErrorKind::UnexpectedEof => Ok(()),
             Err(e) => Err(e.into()),
         }
     }
+
+    fn remaining_input_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn offset(&self) -> usize {
+        self.input.bytes_read
+    }
+
+    fn allows_non_canonical_uleb128(&self) -> bool {
+        self.allow_non_canonical_uleb128
+    }
 }
 
 impl<'de> BcsDeserializer<'de> for Deserializer<&'de [u8]> {
     type MaybeBorrowedBytes = &'de [u8];
     fn next(&mut self) -> Result<u8> {
-        let byte = self.peek()?;
-        self.input = &self.input[1..];
+        let (&byte, rest) = self.input.split_first().ok_or(Error::Eof {
+            expected: 1,
+            available: 0,
+        })?;
+        self.input = rest;
         Ok(byte)
     }
 
     fn fill_slice(&mut self, slice: &mut [u8]) -> Result<()> {
-        for byte in slice {
-            *byte = self.next()?;
-        }
+        slice.copy_from_slice(self.take(slice.len())?);
         Ok(())
     }
 
@@ -383,56 +1815,236 @@ impl<'de> BcsDeserializer<'de> for Deserializer<&'de [u8]> {
         visitor.visit_borrowed_bytes(self.parse_bytes()?)
     }
 
-    fn next_key_seed<V: DeserializeSeed<'de>>(
+    fn parse_and_visit_string<V>(&mut self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let string = self.parse_string()?;
+        self.materialize(string.len())?;
+        visitor.visit_borrowed_str(string)
+    }
+
+    fn parse_and_visit_byte_buf<V>(&mut self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let bytes = self.parse_bytes()?;
+        self.materialize(bytes.len())?;
+        visitor.visit_borrowed_bytes(bytes)
+    }
+
+    fn parse_and_visit_fixed_bytes<V>(&mut self, len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_borrowed_bytes(self.take(len)?)
+    }
+
+    fn capture_seed<V: DeserializeSeed<'de>>(
         &mut self,
         seed: V,
     ) -> Result<(V::Value, Self::MaybeBorrowedBytes)> {
         let previous_input_slice = self.input;
-        let key_value = seed.deserialize(&mut *self)?;
-        let key_len = previous_input_slice.len().saturating_sub(self.input.len());
-        let key_bytes = &previous_input_slice[..key_len];
-        Ok((key_value, key_bytes))
+        let value = seed.deserialize(&mut *self)?;
+        let len = previous_input_slice.len().saturating_sub(self.input.len());
+        let bytes = &previous_input_slice[..len];
+        Ok((value, bytes))
     }
 
     fn end(&mut self) -> Result<()> {
-        if self.input.is_empty() {
-            Ok(())
-        } else {
-            Err(Error::RemainingInput)
+        match self.input.first() {
+            None => Ok(()),
+            Some(first) => Err(Error::RemainingInput {
+                len: self.input.len(),
+                first: *first,
+            }),
         }
     }
+
+    fn remaining_input_len(&self) -> Option<usize> {
+        Some(self.input.len())
+    }
+
+    fn offset(&self) -> usize {
+        self.input_len - self.input.len()
+    }
+
+    fn allows_non_canonical_uleb128(&self) -> bool {
+        self.allow_non_canonical_uleb128
+    }
 }
 
 impl<'de> Deserializer<&'de [u8]> {
-    fn peek(&mut self) -> Result<u8> {
-        self.input.first().copied().ok_or(Error::Eof)
+    /// Consumes the next `len` bytes of the input, failing with `Error::Eof` if there are fewer.
+    ///
+    /// This and `next` are the only places where the input is advanced, and neither indexes
+    /// the input unchecked: crafted inputs cannot make the deserializer panic.
+    fn take(&mut self, len: usize) -> Result<&'de [u8]> {
+        match (self.input.get(..len), self.input.get(len..)) {
+            (Some(bytes), Some(rest)) => {
+                self.input = rest;
+                Ok(bytes)
+            }
+            _ => Err(Error::Eof {
+                expected: len,
+                available: self.input.len(),
+            }),
+        }
     }
 
     fn parse_bytes(&mut self) -> Result<&'de [u8]> {
         let len = self.parse_length()?;
-        let slice = self.input.get(..len).ok_or(Error::Eof)?;
-        self.input = &self.input[len..];
-        Ok(slice)
+        self.take(len)
     }
 
     fn parse_string(&mut self) -> Result<&'de str> {
         let slice = self.parse_bytes()?;
-        str::from_utf8(slice).map_err(|_| Error::Utf8)
+        str::from_utf8(slice).map_err(|e| Error::InvalidUtf8 {
+            offset: e.valid_up_to(),
+        })
     }
 }
 
 impl<R> Deserializer<R> {
+    /// Applies the limits of `config` on top of the container depth given at construction.
+    fn configure(&mut self, config: &Config) -> Result<()> {
+        if config.max_container_depth > crate::MAX_CONTAINER_DEPTH {
+            return Err(Error::NotSupported("limit exceeds the max allowed depth"));
+        }
+        self.max_map_entries = config.max_map_entries;
+        self.map_key_order = config.map_key_order.unwrap_or(canonical_key_cmp);
+        self.allow_non_canonical_uleb128 = config.allow_non_canonical_uleb128;
+        self.max_materialized_bytes = config.max_materialized_bytes;
+        self.variant_maps = config.variant_maps;
+        self.max_remaining_collection_nesting = config.max_collection_nesting;
+        self.collection_nesting_limit = config.max_collection_nesting;
+        Ok(())
+    }
+
+    /// Accounts for the `len` elements of type `T` of a decoded sequence or map, which is
+    /// called once the type is known, i.e. when the first element is decoded.
+    #[inline]
+    fn materialize_elements<T>(&mut self, len: usize) -> Result<()> {
+        self.materialize(len.saturating_mul(core::mem::size_of::<T>()))
+    }
+
+    /// Accounts for `len` more bytes in decoded strings, byte arrays, sequences, or maps.
+    #[inline]
+    fn materialize(&mut self, len: usize) -> Result<()> {
+        self.materialized_bytes = self.materialized_bytes.saturating_add(len);
+        if self.materialized_bytes > self.max_materialized_bytes {
+            return Err(Error::ExceededMaterializationBudget {
+                limit: self.max_materialized_bytes,
+            });
+        }
+        Ok(())
+    }
+
     fn enter_named_container(&mut self, name: &'static str) -> Result<()> {
         if self.max_remaining_depth == 0 {
-            return Err(Error::ExceededContainerDepthLimit(name));
+            return Err(Error::ExceededContainerDepthLimit {
+                name,
+                limit: self.container_depth_limit,
+            });
         }
         self.max_remaining_depth -= 1;
+        if let Some(stats) = &mut self.stats {
+            let depth = self.container_depth_limit - self.max_remaining_depth;
+            stats.max_depth = core::cmp::max(stats.max_depth, depth);
+        }
         Ok(())
     }
 
     fn leave_named_container(&mut self) {
         self.max_remaining_depth += 1;
     }
+
+    fn enter_sequence(&mut self) -> Result<()> {
+        if self.max_remaining_sequence_depth == 0 {
+            return Err(Error::ExceededSequenceDepthLimit {
+                limit: crate::MAX_CONTAINER_DEPTH,
+            });
+        }
+        self.enter_collection()?;
+        self.max_remaining_sequence_depth -= 1;
+        Ok(())
+    }
+
+    fn leave_sequence(&mut self) {
+        self.max_remaining_sequence_depth += 1;
+        self.leave_collection();
+    }
+
+    /// Reads the number of entries of a map, and checks it against the limits and the size of
+    /// the input.
+    fn parse_map_length<'de>(&mut self) -> Result<usize>
+    where
+        Self: BcsDeserializer<'de>,
+    {
+        let len = self.parse_length()?;
+        if len > self.max_map_entries {
+            return Err(Error::ExceededMaxMapEntries {
+                len,
+                max: self.max_map_entries,
+            });
+        }
+        if let Some(stats) = &mut self.stats {
+            stats.map_entries += len;
+        }
+        // Keys are unique, so all entries but one take at least one byte. This is only a lower
+        // bound: once the first entry is decoded, `MapDeserializer` checks the following ones
+        // against its size, i.e. up to two bytes per entry when neither keys nor values are
+        // zero-sized.
+        if let Some(input_len) = self.remaining_input_len() {
+            if len > core::cmp::max(input_len, 1) {
+                return Err(Error::Eof {
+                    expected: len,
+                    available: input_len,
+                });
+            }
+        }
+        Ok(len)
+    }
+
+    /// Visits a value, then its bytes, as a sequence of two elements, for `Captured`.
+    #[inline(never)]
+    fn visit_captured<'de, V>(&mut self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+        Self: BcsDeserializer<'de>,
+    {
+        visitor.visit_seq(CapturedDeserializer {
+            de: self,
+            value_read: false,
+            bytes: None,
+        })
+    }
+
+    #[inline(never)]
+    fn visit_some_nested<'de, V>(&mut self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+        Self: BcsDeserializer<'de>,
+    {
+        self.enter_collection()?;
+        let r = visitor.visit_some(&mut *self).map_err(eof_in_option);
+        self.leave_collection();
+        r
+    }
+
+    fn enter_collection(&mut self) -> Result<()> {
+        if self.max_remaining_collection_nesting == 0 {
+            return Err(Error::ExceededCollectionNesting {
+                limit: self.collection_nesting_limit,
+            });
+        }
+        self.max_remaining_collection_nesting -= 1;
+        Ok(())
+    }
+
+    fn leave_collection(&mut self) {
+        self.max_remaining_collection_nesting += 1;
+    }
 }
 
 impl<'de, 'a, R> de::Deserializer<'de> for &'a mut Deserializer<R>
@@ -446,7 +2058,15 @@ where
     where
         V: Visitor<'de>,
     {
-        Err(Error::NotSupported("deserialize_any"))
+        if self.decoded_field_name {
+            return Err(Error::FlattenUnsupported);
+        }
+        Err(Error::NotSupported(
+            "deserialize_any: BCS is not self-describing, deserialize a concrete type instead; \
+             this is typically caused by a dynamic value such as `serde_json::Value` (use \
+             `bcs::ValueSeed` with its layout), or by an enum with `#[serde(untagged)]` or \
+             `#[serde(tag = \"...\")]` (remove the attribute to use the variant index)",
+        ))
     }
 
     fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value>
@@ -530,21 +2150,27 @@ where
     where
         V: Visitor<'de>,
     {
-        Err(Error::NotSupported("deserialize_f32"))
+        Err(Error::NotSupported(
+            "deserialize_f32: BCS has no floating-point types; use an integer or fixed-point field instead",
+        ))
     }
 
     fn deserialize_f64<V>(self, _visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        Err(Error::NotSupported("deserialize_f64"))
+        Err(Error::NotSupported(
+            "deserialize_f64: BCS has no floating-point types; use an integer or fixed-point field instead",
+        ))
     }
 
     fn deserialize_char<V>(self, _visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        Err(Error::NotSupported("deserialize_char"))
+        Err(Error::NotSupported(
+            "deserialize_char: BCS has no char type; use a `String` or `u32` field instead",
+        ))
     }
 
     fn deserialize_str<V>(self, visitor: V) -> Result<V::Value>
@@ -558,7 +2184,7 @@ where
     where
         V: Visitor<'de>,
     {
-        self.parse_and_visit_str(visitor)
+        self.parse_and_visit_string(visitor)
     }
 
     fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value>
@@ -572,7 +2198,7 @@ where
     where
         V: Visitor<'de>,
     {
-        self.parse_and_visit_bytes(visitor)
+        self.parse_and_visit_byte_buf(visitor)
     }
 
     fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
@@ -583,8 +2209,13 @@ where
 
         match byte {
             0 => visitor.visit_none(),
-            1 => visitor.visit_some(self),
-            _ => Err(Error::ExpectedOption),
+            // Options are on the recursion path of linked lists, so counting them is kept out
+            // of this frame unless requested.
+            1 if self.collection_nesting_limit == usize::MAX => {
+                visitor.visit_some(self).map_err(eof_in_option)
+            }
+            1 => self.visit_some_nested(visitor),
+            _ => Err(Error::ExpectedOption(byte)),
         }
     }
 
@@ -609,6 +2240,10 @@ where
     where
         V: Visitor<'de>,
     {
+        // `Captured` is transparent, and does not count towards the container depth.
+        if name == crate::captured::CAPTURED_NAME {
+            return self.visit_captured(visitor);
+        }
         self.enter_named_container(name)?;
         let r = visitor.visit_newtype_struct(&mut *self);
         self.leave_named_container();
@@ -620,7 +2255,13 @@ where
         V: Visitor<'de>,
     {
         let len = self.parse_length()?;
-        visitor.visit_seq(SeqDeserializer::new(self, len))
+        if let Some(stats) = &mut self.stats {
+            stats.seq_elements += len;
+        }
+        self.enter_sequence()?;
+        let r = visitor.visit_seq(LengthCheckedSeqDeserializer::new(self, len));
+        self.leave_sequence();
+        r
     }
 
     fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value>
@@ -639,6 +2280,14 @@ where
     where
         V: Visitor<'de>,
     {
+        // Arrays of integers from `crate::array` are read in bulk. Like other arrays, they
+        // don't count towards the container depth.
+        if let Some(element_size) = crate::array::element_size(name) {
+            let byte_len = len
+                .checked_mul(element_size)
+                .ok_or(Error::NotSupported("array too large"))?;
+            return self.parse_and_visit_fixed_bytes(byte_len, visitor);
+        }
         self.enter_named_container(name)?;
         let r = visitor.visit_seq(SeqDeserializer::new(self, len));
         self.leave_named_container();
@@ -649,8 +2298,11 @@ where
     where
         V: Visitor<'de>,
     {
-        let len = self.parse_length()?;
-        visitor.visit_map(MapDeserializer::new(self, len))
+        let len = self.parse_map_length()?;
+        self.enter_sequence()?;
+        let r = visitor.visit_map(MapDeserializer::new(self, len));
+        self.leave_sequence();
+        r
     }
 
     fn deserialize_struct<V>(
@@ -671,32 +2323,42 @@ where
     fn deserialize_enum<V>(
         self,
         name: &'static str,
-        _variants: &'static [&'static str],
+        variants: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
         self.enter_named_container(name)?;
-        let r = visitor.visit_enum(&mut *self);
+        let r = visitor.visit_enum(EnumDeserializer {
+            de: self,
+            name,
+            variants,
+        });
         self.leave_named_container();
         r
     }
 
-    // BCS does not utilize identifiers, so throw them away
+    // BCS does not utilize identifiers, so throw them away. They are only decoded for the
+    // fields of a struct with `#[serde(flatten)]` fields, whose values are then decoded with
+    // `deserialize_any`.
     fn deserialize_identifier<V>(self, _visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
+        self.decoded_field_name = true;
         self.deserialize_bytes(_visitor)
     }
 
-    // BCS is not a self-describing format so we can't implement `deserialize_ignored_any`
+    // BCS is not a self-describing format so we can't implement `deserialize_ignored_any`.
+    // Values of a known layout can be skipped with `Skip` instead.
     fn deserialize_ignored_any<V>(self, _visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        Err(Error::NotSupported("deserialize_ignored_any"))
+        Err(Error::NotSupported(
+            "deserialize_ignored_any: BCS is not self-describing, use `bcs::Skip` with the layout of the ignored value",
+        ))
     }
 
     // BCS is not a human readable format
@@ -705,6 +2367,17 @@ where
     }
 }
 
+/// Marks a truncated input (see `Error::is_truncated`) detected while decoding the value of an
+/// `Option`. Only the innermost option is recorded, other errors are returned as is.
+#[cold]
+fn eof_in_option(err: Error) -> Error {
+    match err {
+        Error::EofInOption(_) => err,
+        err if err.is_truncated() => Error::EofInOption(Box::new(err)),
+        err => err,
+    }
+}
+
 struct SeqDeserializer<'a, R> {
     de: &'a mut Deserializer<R>,
     remaining: usize,
@@ -739,52 +2412,176 @@ where
     }
 }
 
+/// Access to the elements of a variable-length sequence, which fails early when the input is
+/// obviously too short for the declared length.
+///
+/// This is a heuristic: elements of zero-sized types such as `()` take no bytes, so the length
+/// cannot be checked before decoding anything. Instead, if the first element takes at least one
+/// byte, so do the others (they have the same type), and the sequence is rejected with
+/// `Error::Eof` as soon as fewer bytes than elements remain. This only applies when the input
+/// length is known, i.e. when deserializing from a slice.
+///
+/// The type of the elements is also only known from the first one, so that is when all of them
+/// are charged to the materialization budget.
+struct LengthCheckedSeqDeserializer<'a, R> {
+    seq: SeqDeserializer<'a, R>,
+    checked: bool,
+}
+
+impl<'a, R> LengthCheckedSeqDeserializer<'a, R> {
+    fn new(de: &'a mut Deserializer<R>, remaining: usize) -> Self {
+        Self {
+            seq: SeqDeserializer::new(de, remaining),
+            checked: false,
+        }
+    }
+}
+
+impl<'a, 'de, R> de::SeqAccess<'de> for LengthCheckedSeqDeserializer<'a, R>
+where
+    Deserializer<R>: BcsDeserializer<'de>,
+{
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        if self.checked {
+            return self.seq.next_element_seed(seed);
+        }
+        self.checked = true;
+        self.seq
+            .de
+            .materialize_elements::<T::Value>(self.seq.remaining)?;
+        let before = self.seq.de.remaining_input_len();
+        let value = self.seq.next_element_seed(seed)?;
+        if let (Some(before), Some(after)) = (before, self.seq.de.remaining_input_len()) {
+            if after < before && self.seq.remaining > after {
+                return Err(Error::Eof {
+                    expected: self.seq.remaining,
+                    available: after,
+                });
+            }
+        }
+        Ok(value)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.seq.size_hint()
+    }
+}
+
+/// Access to the entries of a map, which checks that keys are strictly increasing.
+///
+/// Like `LengthCheckedSeqDeserializer`, this fails early with `Error::Eof` when the input is
+/// too short for the declared length: all keys but one take at least one byte if the first
+/// one does, and all values do if the first one does, so the remaining entries are checked
+/// against that lower bound once the key of the second entry is known to be in order. This
+/// only applies to slices, and assumes that the seeds are driven by the types of the keys and
+/// values, as with `Deserialize` or a `Skip` matching the type: a seed reading nothing for
+/// some values of a type whose other values take input may be wrongly rejected.
 struct MapDeserializer<'a, R, B> {
     de: &'a mut Deserializer<R>,
+    len: usize,
     remaining: usize,
     previous_key_bytes: Option<B>,
+    /// Whether the value of the first entry took any input, and thus all values do.
+    values_take_input: bool,
 }
 
 impl<'a, R, B> MapDeserializer<'a, R, B> {
     fn new(de: &'a mut Deserializer<R>, remaining: usize) -> Self {
         Self {
             de,
+            len: remaining,
             remaining,
             previous_key_bytes: None,
+            values_take_input: false,
         }
     }
 }
 
-impl<'de, 'a, R, B: AsRef<[u8]>> de::MapAccess<'de> for MapDeserializer<'a, R, B>
-where
-    Deserializer<R>: BcsDeserializer<'de, MaybeBorrowedBytes = B>,
-{
-    type Error = Error;
-
-    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+impl<'a, R, B: AsRef<[u8]>> MapDeserializer<'a, R, B> {
+    /// Same as `next_key_seed` but also return the serialized bytes of the key.
+    fn next_key_seed_with_bytes<'de, K>(&mut self, seed: K) -> Result<Option<(K::Value, &B)>>
     where
         K: DeserializeSeed<'de>,
+        Deserializer<R>: BcsDeserializer<'de, MaybeBorrowedBytes = B>,
     {
         match self.remaining.checked_sub(1) {
             None => Ok(None),
             Some(remaining) => {
-                let (key_value, key_bytes) = self.de.next_key_seed(seed)?;
+                if self.remaining == self.len {
+                    self.de.materialize_elements::<K::Value>(self.len)?;
+                }
+                let (key_value, key_bytes) = self.de.capture_seed(seed)?;
                 if let Some(previous_key_bytes) = &self.previous_key_bytes {
-                    if previous_key_bytes.as_ref() >= key_bytes.as_ref() {
-                        return Err(Error::NonCanonicalMap);
+                    let (previous, current) = (previous_key_bytes.as_ref(), key_bytes.as_ref());
+                    if previous == current
+                        || (self.de.map_key_order)(previous, current) != Ordering::Less
+                    {
+                        return Err(Error::NonCanonicalMap {
+                            index: self.len - self.remaining,
+                            offset: self.de.offset() - current.len(),
+                        });
                     }
                 }
                 self.remaining = remaining;
-                self.previous_key_bytes = Some(key_bytes);
-                Ok(Some(key_value))
+                let key_bytes = self.previous_key_bytes.insert(key_bytes);
+                Ok(Some((key_value, key_bytes)))
             }
         }
     }
+}
+
+impl<'de, 'a, R, B: AsRef<[u8]>> de::MapAccess<'de> for MapDeserializer<'a, R, B>
+where
+    Deserializer<R>: BcsDeserializer<'de, MaybeBorrowedBytes = B>,
+{
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        Ok(self
+            .next_key_seed_with_bytes(seed)?
+            .map(|(key_value, _)| key_value))
+    }
 
     fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
     where
         V: DeserializeSeed<'de>,
     {
+        let index = self.len - self.remaining - 1;
+        if index == 0 {
+            self.de.materialize_elements::<V::Value>(self.len)?;
+            let before = self.de.remaining_input_len();
+            let value = seed.deserialize(&mut *self.de)?;
+            self.values_take_input = before != self.de.remaining_input_len();
+            return Ok(value);
+        }
+        if index == 1 {
+            // The sizes of the first entry bound those of this value and the entries after it.
+            if let Some(available) = self.de.remaining_input_len() {
+                let key_len = self
+                    .previous_key_bytes
+                    .as_ref()
+                    .map_or(0, |b| b.as_ref().len());
+                let value_len = usize::from(self.values_take_input);
+                let expected = self
+                    .remaining
+                    .saturating_mul(usize::from(key_len > 0) + value_len)
+                    .saturating_add(value_len);
+                if expected > available {
+                    return Err(Error::Eof {
+                        expected,
+                        available,
+                    });
+                }
+            }
+        }
         seed.deserialize(&mut *self.de)
     }
 
@@ -793,23 +2590,196 @@ where
     }
 }
 
-impl<'de, 'a, R> de::EnumAccess<'de> for &'a mut Deserializer<R>
+/// Access to a value and then to its serialized bytes, for `Captured`.
+struct CapturedDeserializer<'a, R, B> {
+    de: &'a mut Deserializer<R>,
+    value_read: bool,
+    bytes: Option<B>,
+}
+
+impl<'de, 'a, R, B: AsRef<[u8]>> de::SeqAccess<'de> for CapturedDeserializer<'a, R, B>
+where
+    Deserializer<R>: BcsDeserializer<'de, MaybeBorrowedBytes = B>,
+{
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        if !self.value_read {
+            self.value_read = true;
+            let (value, bytes) = self.de.capture_seed(seed)?;
+            self.bytes = Some(bytes);
+            return Ok(Some(value));
+        }
+        match self.bytes.take() {
+            Some(bytes) => {
+                let deserializer = de::value::BytesDeserializer::<Error>::new(bytes.as_ref());
+                seed.deserialize(deserializer).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+/// Forwards to the deserializer, and requests the variant to be recorded if the first call is
+/// for an enum, see [`from_bytes_with_variant`]. Nested enums are not recorded, since the
+/// variant of the outermost enum is read before them, and any other type is read with the
+/// request left unset.
+struct OuterEnumDeserializer<'a, R>(&'a mut Deserializer<R>);
+
+macro_rules! forward_to_deserializer {
+    ($($method:ident($($arg:ident: $ty:ty),*);)*) => {
+        $(
+            fn $method<V>(self, $($arg: $ty,)* visitor: V) -> Result<V::Value>
+            where
+                V: Visitor<'de>,
+            {
+                self.0.$method($($arg,)* visitor)
+            }
+        )*
+    };
+}
+
+impl<'de, 'a, R> de::Deserializer<'de> for OuterEnumDeserializer<'a, R>
+where
+    Deserializer<R>: BcsDeserializer<'de>,
+{
+    type Error = Error;
+
+    fn deserialize_enum<V>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.0.outer_variant = Some(None);
+        self.0.deserialize_enum(name, variants, visitor)
+    }
+
+    forward_to_deserializer! {
+        deserialize_any();
+        deserialize_bool();
+        deserialize_i8();
+        deserialize_i16();
+        deserialize_i32();
+        deserialize_i64();
+        deserialize_i128();
+        deserialize_u8();
+        deserialize_u16();
+        deserialize_u32();
+        deserialize_u64();
+        deserialize_u128();
+        deserialize_f32();
+        deserialize_f64();
+        deserialize_char();
+        deserialize_str();
+        deserialize_string();
+        deserialize_bytes();
+        deserialize_byte_buf();
+        deserialize_option();
+        deserialize_unit();
+        deserialize_unit_struct(name: &'static str);
+        deserialize_newtype_struct(name: &'static str);
+        deserialize_seq();
+        deserialize_tuple(len: usize);
+        deserialize_tuple_struct(name: &'static str, len: usize);
+        deserialize_map();
+        deserialize_struct(name: &'static str, fields: &'static [&'static str]);
+        deserialize_identifier();
+        deserialize_ignored_any();
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+/// Reads the variant index of the enum `name`.
+struct EnumDeserializer<'a, R> {
+    de: &'a mut Deserializer<R>,
+    name: &'static str,
+    variants: &'static [&'static str],
+}
+
+impl<'de, 'a, R> de::EnumAccess<'de> for EnumDeserializer<'a, R>
 where
     Deserializer<R>: BcsDeserializer<'de>,
 {
     type Error = Error;
-    type Variant = Self;
+    type Variant = &'a mut Deserializer<R>;
 
     fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant)>
     where
         V: DeserializeSeed<'de>,
     {
-        let variant_index = self.parse_u32_from_uleb128()?;
+        let variant_index = self.de.parse_u32_from_uleb128().map_err(|e| match e {
+            Error::IntegerOverflowDuringUleb128Decoding => {
+                Error::MalformedVariantIndex { name: self.name }
+            }
+            e => e,
+        })?;
+        let variant_index = if self.de.variant_maps.is_empty() {
+            variant_index
+        } else {
+            remap_variant_index(
+                self.de.variant_maps,
+                self.name,
+                self.variants,
+                variant_index,
+            )?
+        };
+        if self.de.outer_variant == Some(None) {
+            let variant = usize::try_from(variant_index)
+                .ok()
+                .and_then(|index| self.variants.get(index));
+            let variant = variant.ok_or_else(|| {
+                <Error as de::Error>::invalid_value(
+                    de::Unexpected::Unsigned(variant_index.into()),
+                    &"a valid variant index",
+                )
+            })?;
+            self.de.outer_variant = Some(Some(variant));
+        }
         let result: Result<V::Value> = seed.deserialize(variant_index.into_deserializer());
-        Ok((result?, self))
+        Ok((result?, self.de))
     }
 }
 
+/// Translates the encoded index of a variant of the enum `name` into its position in
+/// `variants`, if the enum has a variant map.
+#[inline(never)]
+fn remap_variant_index(
+    maps: &[VariantMap],
+    name: &'static str,
+    variants: &'static [&'static str],
+    index: u32,
+) -> Result<u32> {
+    let map = match maps.iter().find(|map| map.enum_name == name) {
+        Some(map) => map,
+        None => return Ok(index),
+    };
+    let (variant, _) = map
+        .variants
+        .iter()
+        .find(|(_, encoded)| *encoded == index)
+        .ok_or_else(|| {
+            <Error as de::Error>::invalid_value(
+                de::Unexpected::Unsigned(index.into()),
+                &"a variant index of the variant map",
+            )
+        })?;
+    let position = variants
+        .iter()
+        .position(|v| v == variant)
+        .ok_or_else(|| <Error as de::Error>::unknown_variant(variant, variants))?;
+    u32::try_from(position).map_err(|_| Error::MalformedVariantIndex { name })
+}
+
 impl<'de, 'a, R> de::VariantAccess<'de> for &'a mut Deserializer<R>
 where
     Deserializer<R>: BcsDeserializer<'de>,
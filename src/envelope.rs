@@ -0,0 +1,89 @@
+// Copyright (c) The Diem Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! A minimal envelope for persisting BCS values: a format version and a checksum.
+//!
+//! An envelope is laid out as:
+//!
+//! | bytes | content                                          |
+//! |-------|--------------------------------------------------|
+//! | 1     | format version, chosen by the caller             |
+//! | n     | BCS encoding of the value                        |
+//! | 4     | CRC32 (IEEE) of the BCS encoding, little-endian  |
+//!
+//! The version is not interpreted: it is returned by [`decode`] so that callers can migrate
+//! older formats. The checksum only covers the BCS encoding, and is verified before decoding.
+//!
+//! ```rust
+//! # use bcs::{envelope, Error, Result};
+//! # use serde::{Deserialize, Serialize};
+//! # fn main() -> Result<()> {
+//! #[derive(Debug, Deserialize, PartialEq, Serialize)]
+//! struct Checkpoint {
+//!     height: u64,
+//!     root: [u8; 4],
+//! }
+//!
+//! let checkpoint = Checkpoint { height: 7, root: [1, 2, 3, 4] };
+//! let mut bytes = envelope::encode(2, &checkpoint)?;
+//! assert_eq!(envelope::decode::<Checkpoint>(&bytes)?, (2, checkpoint));
+//!
+//! // A flipped bit is detected before the payload is decoded.
+//! bytes[1] ^= 1;
+//! assert!(matches!(
+//!     envelope::decode::<Checkpoint>(&bytes),
+//!     Err(Error::ChecksumMismatch { .. })
+//! ));
+//! # Ok(())}
+//! ```
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+
+/// Size of the envelope around the BCS encoding: the version byte and the checksum.
+const OVERHEAD: usize = 1 + CHECKSUM_LEN;
+
+const CHECKSUM_LEN: usize = 4;
+
+/// Encodes `value` as BCS, wrapped in an envelope with the given format `version`.
+pub fn encode<T>(version: u8, value: &T) -> Result<Vec<u8>>
+where
+    T: ?Sized + Serialize,
+{
+    let mut output = Vec::with_capacity(crate::serialized_size(value)? + OVERHEAD);
+    output.push(version);
+    crate::serialize_into(&mut output, value)?;
+    let checksum = crc32fast::hash(&output[1..]);
+    output.extend_from_slice(&checksum.to_le_bytes());
+    Ok(output)
+}
+
+/// Verifies the checksum of an envelope and decodes its value, returning it with the format
+/// version.
+///
+/// Fails with `Error::Eof` if `bytes` is too short to hold an envelope, and with
+/// `Error::ChecksumMismatch` if the payload does not match its checksum. The payload must then be
+/// a complete BCS encoding of `T`, as for [`from_bytes`](crate::from_bytes).
+pub fn decode<'a, T>(bytes: &'a [u8]) -> Result<(u8, T)>
+where
+    T: Deserialize<'a>,
+{
+    if bytes.len() < OVERHEAD {
+        return Err(Error::Eof {
+            expected: OVERHEAD,
+            available: bytes.len(),
+        });
+    }
+    let (payload, checksum) = bytes[1..].split_at(bytes.len() - OVERHEAD);
+    let mut expected = [0; CHECKSUM_LEN];
+    expected.copy_from_slice(checksum);
+    let expected = u32::from_le_bytes(expected);
+    let found = crc32fast::hash(payload);
+    if found != expected {
+        return Err(Error::ChecksumMismatch { expected, found });
+    }
+    Ok((bytes[0], crate::from_bytes(payload)?))
+}
@@ -5,70 +5,299 @@ use core::fmt;
 use serde::{de, ser};
 use strum::Display;
 
+#[cfg(feature = "std")]
+use std::sync::Arc;
 #[cfg(feature = "std")]
 use thiserror::Error;
 
 #[cfg(not(feature = "std"))]
-use alloc::string::{String, ToString};
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+};
 
 pub type Result<T> = core::result::Result<T, Error>;
 
+/// The error returned by serialization and deserialization.
+///
+/// With the `std` feature, `Error` implements `std::error::Error` and is `Send + Sync +
+/// 'static`, so it can be wrapped in the error type of an application, e.g. with
+/// `#[from] bcs::Error` and `thiserror`, or converted into an `anyhow::Error` with `?`.
+/// Wrapped errors, such as I/O errors, are available through `source`.
+///
+/// # Examples
+///
+/// ```
+/// #[derive(Debug, thiserror::Error)]
+/// enum AppError {
+///     #[error("invalid message: {0}")]
+///     Decode(#[from] bcs::Error),
+/// }
+///
+/// fn parse(bytes: &[u8]) -> Result<u16, AppError> {
+///     Ok(bcs::from_bytes(bytes)?)
+/// }
+///
+/// let err = parse(&[1]).unwrap_err();
+/// assert_eq!(
+///     err.to_string(),
+///     "invalid message: unexpected end of input: expected 2 byte(s), found 1",
+/// );
+/// ```
 #[derive(Clone, Debug, Display, Eq, PartialEq)]
 #[cfg_attr(feature = "std", derive(Error))]
 pub enum Error {
-    #[strum(to_string = "unexpected end of input")]
-    Eof,
+    /// The input ended while `expected` more bytes were needed, and only `available` were left.
+    /// Some checks reject lengths early, in which case `expected` is a lower bound of the bytes
+    /// needed. I/O errors of kind `UnexpectedEof`, e.g. from a reader using `read_exact`, are
+    /// converted to this variant too, with unknown counts reported as 1 and 0.
+    #[strum(to_string = "unexpected end of input: expected {expected} byte(s), found {available}")]
+    Eof { expected: usize, available: usize },
     #[strum(to_string = "I/O error: {0}")]
-    Io(String),
-    #[strum(to_string = "exceeded max sequence length: {0}")]
-    ExceededMaxLen(usize),
-    #[strum(to_string = "exceeded max container depth while entering: {0}")]
-    ExceededContainerDepthLimit(&'static str),
-    #[strum(to_string = "expected boolean")]
-    ExpectedBoolean,
+    Io(#[cfg_attr(feature = "std", source)] IoError),
+    /// The input ended in the middle of the value of an `Option`, after its `01` tag. The
+    /// underlying error, for which `is_truncated` holds, is boxed.
+    #[strum(to_string = "in the value of an option: {0}")]
+    EofInOption(#[cfg_attr(feature = "std", source)] Box<Error>),
+    /// The boxed error, with `context` telling at which byte of the input it was raised and
+    /// showing the bytes around that position in hex, with the position marked by `|`. Only
+    /// returned when enabled with `Config::hex_context_on_error`.
+    #[strum(to_string = "{error} ({context})")]
+    WithHexContext {
+        #[cfg_attr(feature = "std", source)]
+        error: Box<Error>,
+        context: Box<str>,
+    },
+    #[strum(to_string = "exceeded max sequence length: {len} (max: {max})")]
+    ExceededMaxLen { len: usize, max: usize },
+    #[strum(to_string = "exceeded max map entries: {len} (max: {max})")]
+    ExceededMaxMapEntries { len: usize, max: usize },
+    #[strum(to_string = "exceeded max container depth {limit} while entering: {name}")]
+    ExceededContainerDepthLimit { name: &'static str, limit: usize },
+    /// The strings, byte arrays, sequences, and maps decoded so far take more memory than the
+    /// `limit` set with `Config::max_materialized_bytes`.
+    #[strum(to_string = "exceeded materialization budget of {limit} bytes")]
+    ExceededMaterializationBudget { limit: usize },
+    /// Sequences, maps, and options were nested more than the `limit` set with
+    /// `Config::max_collection_nesting`.
+    #[strum(to_string = "exceeded max collection nesting {limit}")]
+    ExceededCollectionNesting { limit: usize },
+    /// Sequences and maps, which have no name, were nested more than `limit` levels deep.
+    #[strum(to_string = "sequence nesting exceeded depth limit {limit}")]
+    ExceededSequenceDepthLimit { limit: usize },
+    /// The checksum of an envelope does not match its payload.
+    #[strum(to_string = "checksum mismatch: expected {expected:#010x}, found {found:#010x}")]
+    ChecksumMismatch { expected: u32, found: u32 },
+    #[strum(to_string = "expected boolean, found byte {0:#04x}")]
+    ExpectedBoolean(u8),
     #[strum(to_string = "expected map key")]
     ExpectedMapKey,
     #[strum(to_string = "expected map value")]
     ExpectedMapValue,
-    #[strum(to_string = "keys of serialized maps must be unique and in increasing order")]
-    NonCanonicalMap,
-    #[strum(to_string = "expected option type")]
-    ExpectedOption,
+    /// The key of the map entry at `index` (counting from zero), which starts at byte `offset`
+    /// of the input (or of the output, when serializing), is not strictly greater than the key
+    /// before it.
+    #[strum(
+        to_string = "keys of serialized maps must be unique and in increasing order, but entry {index} at byte {offset} is not"
+    )]
+    NonCanonicalMap { index: usize, offset: usize },
+    /// Two entries of a map being serialized have keys with the same BCS bytes.
+    #[strum(to_string = "duplicate map key")]
+    DuplicateMapKey,
+    #[strum(to_string = "expected option tag, found byte {0:#04x}")]
+    ExpectedOption(u8),
     #[strum(to_string = "{0}")]
     Custom(String),
     #[strum(to_string = "sequence missing length")]
     MissingLen,
+    /// An `ExactSizeIterator` being serialized yielded a different number of items than the
+    /// length it `reported`, which was already written as the length prefix.
+    #[strum(to_string = "iterator reported a length of {reported} but yielded {yielded} item(s)")]
+    IteratorLengthMismatch { reported: usize, yielded: usize },
     #[strum(to_string = "not supported: {0}")]
     NotSupported(&'static str),
-    #[strum(to_string = "remaining input")]
-    RemainingInput,
-    #[strum(to_string = "malformed utf8")]
-    Utf8,
+    /// A struct with a `#[serde(flatten)]` field was serialized or deserialized. Serde encodes
+    /// such structs as maps keyed by field name, which BCS cannot decode without a schema.
+    #[strum(
+        to_string = "flattened fields (`#[serde(flatten)]`) are not supported: BCS is not self-describing, declare the fields in the parent struct or nest the struct instead"
+    )]
+    FlattenUnsupported,
+    #[strum(to_string = "output buffer too small: {needed} bytes needed")]
+    BufferTooSmall { needed: usize },
+    #[strum(to_string = "layout consumes {expected} bytes but the type consumed {found}")]
+    LayoutMismatch { expected: usize, found: usize },
+    /// The input continues after the value. `first` is the first extra byte, and `len` the
+    /// number of extra bytes when decoding from a slice (readers are not drained, so `len` is
+    /// 1 for them).
+    #[strum(to_string = "remaining input: {len} trailing byte(s), starting with {first:#04x}")]
+    RemainingInput { len: usize, first: u8 },
+    #[strum(to_string = "malformed utf8 at byte {offset} of the string")]
+    InvalidUtf8 { offset: usize },
     #[strum(to_string = "ULEB128 encoding was not minimal in size")]
     NonCanonicalUleb128Encoding,
     #[strum(to_string = "ULEB128-encoded integer did not fit in the target size")]
     IntegerOverflowDuringUleb128Decoding,
+    #[strum(to_string = "variant index of enum {name} does not fit in a u32")]
+    MalformedVariantIndex { name: &'static str },
 }
 
+// `Error` must remain usable as the source of other errors, including across threads.
 #[cfg(feature = "std")]
-impl From<crate::io::Error> for Error {
-    fn from(err: std::io::Error) -> Self {
-        if err.kind() == crate::io::ErrorKind::UnexpectedEof {
-            Error::Eof
-        } else {
-            Error::Io(err.to_string())
+const _: fn() = || {
+    fn assert_error<E: std::error::Error + Send + Sync + 'static>() {}
+    assert_error::<Error>();
+};
+
+impl Error {
+    /// Returns whether the input ended while a value was being decoded: `Error::Eof`, also
+    /// within `Error::EofInOption`.
+    pub fn is_eof(&self) -> bool {
+        match self {
+            Error::Eof { .. } => true,
+            Error::EofInOption(err) | Error::WithHexContext { error: err, .. } => err.is_eof(),
+            _ => false,
+        }
+    }
+
+    /// Returns whether the input is a valid prefix that was cut short, i.e. decoding may succeed
+    /// once more bytes are available. This is currently the same as `is_eof`.
+    ///
+    /// ```
+    /// use bcs::from_bytes;
+    ///
+    /// let bytes = bcs::to_bytes(&(7u32, "partial")).unwrap();
+    /// for len in 0..bytes.len() {
+    ///     let err = from_bytes::<(u32, String)>(&bytes[..len]).unwrap_err();
+    ///     assert!(err.is_truncated() && !err.is_malformed());
+    /// }
+    /// ```
+    pub fn is_truncated(&self) -> bool {
+        match self {
+            Error::WithHexContext { error, .. } => error.is_truncated(),
+            _ => self.is_eof(),
+        }
+    }
+
+    /// Returns whether the underlying reader or writer failed, i.e. an `Error::Io`, such as a
+    /// broken connection. The end of the input is reported as `Error::Eof` instead.
+    ///
+    /// Such errors say nothing about the data: unlike malformed inputs, they are worth retrying.
+    /// The original error is available through `IoError::get_ref`.
+    ///
+    /// ```
+    /// use bcs::{from_reader, Error};
+    /// use std::io::{self, Read};
+    ///
+    /// struct Disconnected;
+    ///
+    /// impl Read for Disconnected {
+    ///     fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+    ///         Err(io::ErrorKind::ConnectionReset.into())
+    ///     }
+    /// }
+    ///
+    /// let err = from_reader::<u64>(Disconnected).unwrap_err();
+    /// assert!(err.is_io() && !err.is_malformed() && !err.is_truncated());
+    /// assert!(!from_reader::<u64>(&[0u8; 3][..]).unwrap_err().is_io());
+    /// ```
+    pub fn is_io(&self) -> bool {
+        match self {
+            Error::Io(_) => true,
+            Error::WithHexContext { error, .. } => error.is_io(),
+            _ => false,
+        }
+    }
+
+    /// Returns whether the input is invalid no matter what follows it: a byte with an
+    /// unexpected value, a non-canonical encoding, an exceeded limit, trailing input, or data
+    /// rejected by a `Deserialize` implementation (`Error::Custom`).
+    ///
+    /// Errors that are neither truncated nor malformed come from the environment (I/O errors),
+    /// from unsupported types, or from serialization.
+    pub fn is_malformed(&self) -> bool {
+        if let Error::WithHexContext { error, .. } = self {
+            return error.is_malformed();
         }
+        matches!(
+            self,
+            Error::ExceededMaxLen { .. }
+                | Error::ExceededMaxMapEntries { .. }
+                | Error::ExceededContainerDepthLimit { .. }
+                | Error::ExceededMaterializationBudget { .. }
+                | Error::ExceededSequenceDepthLimit { .. }
+                | Error::ExceededCollectionNesting { .. }
+                | Error::ChecksumMismatch { .. }
+                | Error::ExpectedBoolean(_)
+                | Error::NonCanonicalMap { .. }
+                | Error::ExpectedOption(_)
+                | Error::Custom(_)
+                | Error::LayoutMismatch { .. }
+                | Error::RemainingInput { .. }
+                | Error::InvalidUtf8 { .. }
+                | Error::NonCanonicalUleb128Encoding
+                | Error::IntegerOverflowDuringUleb128Decoding
+                | Error::MalformedVariantIndex { .. }
+        )
+    }
+}
+
+/// An I/O error reported by the underlying reader or writer.
+///
+/// This is what `Error::source` returns for `Error::Io`, so that error reports keep the root
+/// cause. The original error is available through [`get_ref`](IoError::get_ref), and is also
+/// the `source` of the `IoError` (with the `std` feature), so that it can be found by
+/// downcasting the chain to `std::io::Error`. Two `IoError`s are equal if they have the same
+/// kind and message.
+#[derive(Clone, Debug)]
+pub struct IoError(
+    #[cfg(feature = "std")] Arc<crate::io::Error>,
+    #[cfg(not(feature = "std"))] crate::io::Error,
+);
+
+impl IoError {
+    /// Returns the kind of the underlying error.
+    pub fn kind(&self) -> crate::io::ErrorKind {
+        self.0.kind()
+    }
+
+    /// Returns the underlying error.
+    pub fn get_ref(&self) -> &crate::io::Error {
+        &self.0
+    }
+}
+
+impl PartialEq for IoError {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind() == other.kind() && self.0.to_string() == other.0.to_string()
+    }
+}
+
+impl Eq for IoError {}
+
+impl fmt::Display for IoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0.to_string())
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for IoError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&*self.0)
     }
 }
 
-#[cfg(not(feature = "std"))]
 impl From<crate::io::Error> for Error {
     fn from(err: crate::io::Error) -> Self {
         if err.kind() == crate::io::ErrorKind::UnexpectedEof {
-            Error::Eof
-        } else {
-            Error::Io(err.to_string())
+            return Error::Eof {
+                expected: 1,
+                available: 0,
+            };
         }
+        #[cfg(feature = "std")]
+        let err = Arc::new(err);
+        Error::Io(IoError(err))
     }
 }
 
@@ -0,0 +1,44 @@
+// Copyright (c) The Diem Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Fast (de)serialization of fixed-size byte arrays such as hashes and public keys, to be used
+//! with `#[serde(with = "bcs::fixed_bytes")]` on `[u8; N]` fields.
+//!
+//! The encoding is the same as for a regular `[u8; N]`: the `N` bytes, without any length
+//! prefix. When decoding BCS, however, they are read all at once, straight into the array,
+//! instead of going through serde byte by byte. This is [`bcs::array`](crate::array)
+//! restricted to bytes, and is encoded exactly like `[u8; N]` in other formats as well.
+//!
+//! ```rust
+//! # use bcs::{from_bytes, to_bytes, Result};
+//! # use serde::{Deserialize, Serialize};
+//! # fn main() -> Result<()> {
+//! #[derive(Debug, Deserialize, PartialEq, Serialize)]
+//! struct Account {
+//!     #[serde(with = "bcs::fixed_bytes")]
+//!     public_key: [u8; 32],
+//!     nonce: u64,
+//! }
+//!
+//! let account = Account { public_key: [7; 32], nonce: 1 };
+//! let bytes = to_bytes(&account)?;
+//! assert_eq!(bytes, to_bytes(&([7u8; 32], 1u64))?);
+//! assert_eq!(from_bytes::<Account>(&bytes)?, account);
+//! # Ok(())}
+//! ```
+
+use serde::{Deserializer, Serializer};
+
+pub fn serialize<S, const N: usize>(bytes: &[u8; N], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    crate::array::serialize(bytes, serializer)
+}
+
+pub fn deserialize<'de, D, const N: usize>(deserializer: D) -> Result<[u8; N], D::Error>
+where
+    D: Deserializer<'de>,
+{
+    crate::array::deserialize(deserializer)
+}
@@ -0,0 +1,99 @@
+// Copyright (c) The Diem Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Types whose BCS encoding always takes the same number of bytes.
+//!
+//! Integers, booleans, fixed-size arrays and tuples of such types, and structs made only of
+//! such fields are encoded without any length prefix or tag, so their size is known at compile
+//! time. [`FixedSize::ENCODED_SIZE`] exposes it, e.g. to allocate the output of
+//! [`to_bytes_with_capacity`](crate::to_bytes_with_capacity) exactly or to size a stack buffer
+//! for [`serialize_into_slice`](crate::serialize_into_slice), without walking the value as
+//! [`serialized_size`](crate::serialized_size) does.
+//!
+//! With the `derive` feature, `#[derive(BcsFixedSize)]` implements the trait for structs whose
+//! fields all implement it, and fails to compile otherwise.
+//!
+//! ```rust
+//! # use bcs::{to_bytes_with_capacity, FixedSize, Result};
+//! # fn main() -> Result<()> {
+//! type Signature = ([u8; 32], [u8; 32], u8);
+//!
+//! assert_eq!(Signature::ENCODED_SIZE, 65);
+//! let signature: Signature = ([1; 32], [2; 32], 0);
+//! let bytes = to_bytes_with_capacity(&signature, Signature::ENCODED_SIZE)?;
+//! assert_eq!(bytes.len(), bytes.capacity());
+//! # Ok(())}
+//! ```
+
+use core::marker::PhantomData;
+
+/// A type whose BCS encoding always takes exactly `ENCODED_SIZE` bytes.
+///
+/// It is implemented for the primitive types with a fixed-size encoding, and for structs with
+/// `#[derive(BcsFixedSize)]`, which checks that every field has a fixed size. The trait is not
+/// sealed, but a manual implementation must make sure the size is right: nothing in this crate
+/// verifies `ENCODED_SIZE` against the actual encoding.
+pub trait FixedSize {
+    /// The size of the encoding of any value of this type, in bytes.
+    const ENCODED_SIZE: usize;
+}
+
+macro_rules! fixed_size {
+    ($($ty:ty => $size:expr),* $(,)?) => {
+        $(
+            impl FixedSize for $ty {
+                const ENCODED_SIZE: usize = $size;
+            }
+        )*
+    };
+}
+
+fixed_size!(
+    bool => 1,
+    u8 => 1,
+    u16 => 2,
+    u32 => 4,
+    u64 => 8,
+    u128 => 16,
+    i8 => 1,
+    i16 => 2,
+    i32 => 4,
+    i64 => 8,
+    i128 => 16,
+    crate::int::U256 => crate::int::U256::BYTES,
+    crate::int::U512 => crate::int::U512::BYTES,
+);
+
+impl<T: ?Sized> FixedSize for PhantomData<T> {
+    const ENCODED_SIZE: usize = 0;
+}
+
+impl<T: FixedSize, const N: usize> FixedSize for [T; N] {
+    const ENCODED_SIZE: usize = T::ENCODED_SIZE * N;
+}
+
+macro_rules! fixed_size_tuple {
+    ($($name:ident)*) => {
+        impl<$($name: FixedSize),*> FixedSize for ($($name,)*) {
+            const ENCODED_SIZE: usize = 0 $(+ $name::ENCODED_SIZE)*;
+        }
+    };
+}
+
+fixed_size_tuple!();
+fixed_size_tuple!(T0);
+fixed_size_tuple!(T0 T1);
+fixed_size_tuple!(T0 T1 T2);
+fixed_size_tuple!(T0 T1 T2 T3);
+fixed_size_tuple!(T0 T1 T2 T3 T4);
+fixed_size_tuple!(T0 T1 T2 T3 T4 T5);
+fixed_size_tuple!(T0 T1 T2 T3 T4 T5 T6);
+fixed_size_tuple!(T0 T1 T2 T3 T4 T5 T6 T7);
+fixed_size_tuple!(T0 T1 T2 T3 T4 T5 T6 T7 T8);
+fixed_size_tuple!(T0 T1 T2 T3 T4 T5 T6 T7 T8 T9);
+fixed_size_tuple!(T0 T1 T2 T3 T4 T5 T6 T7 T8 T9 T10);
+fixed_size_tuple!(T0 T1 T2 T3 T4 T5 T6 T7 T8 T9 T10 T11);
+fixed_size_tuple!(T0 T1 T2 T3 T4 T5 T6 T7 T8 T9 T10 T11 T12);
+fixed_size_tuple!(T0 T1 T2 T3 T4 T5 T6 T7 T8 T9 T10 T11 T12 T13);
+fixed_size_tuple!(T0 T1 T2 T3 T4 T5 T6 T7 T8 T9 T10 T11 T12 T13 T14);
+fixed_size_tuple!(T0 T1 T2 T3 T4 T5 T6 T7 T8 T9 T10 T11 T12 T13 T14 T15);
@@ -0,0 +1,83 @@
+// Copyright (c) The Diem Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Helpers for fuzzing code that consumes BCS.
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+use serde::Serialize;
+
+#[cfg(feature = "fuzz")]
+use core::fmt::Debug;
+#[cfg(feature = "fuzz")]
+use serde::de::DeserializeOwned;
+
+/// Generates a valid BCS encoding of an arbitrary value of type `T`.
+///
+/// Seeding a fuzzer with well-formed inputs reaches much deeper into decoding logic (maps,
+/// nested enums, etc.) than random bytes alone. Values that cannot be serialized, for instance
+/// because they exceed the container depth limit, are reported as
+/// `arbitrary::Error::IncorrectFormat`.
+///
+/// # Examples
+///
+/// ```
+/// use arbitrary::Unstructured;
+/// use std::collections::BTreeMap;
+///
+/// let mut u = Unstructured::new(&[3, 1, 4, 1, 5, 9, 2, 6, 5, 3, 5, 8, 9, 7, 9]);
+/// let bytes = bcs::fuzz::arbitrary_bytes::<BTreeMap<u8, Vec<u16>>>(&mut u).unwrap();
+/// bcs::from_bytes::<BTreeMap<u8, Vec<u16>>>(&bytes).unwrap();
+/// ```
+#[cfg(feature = "arbitrary")]
+pub fn arbitrary_bytes<'a, T>(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Vec<u8>>
+where
+    T: arbitrary::Arbitrary<'a> + Serialize,
+{
+    let value = T::arbitrary(u)?;
+    crate::to_bytes(&value).map_err(|_| arbitrary::Error::IncorrectFormat)
+}
+
+/// Checks the canonical round-trip invariant of BCS on arbitrary input, for use in fuzz targets.
+///
+/// If `bytes` decodes into a `T`, then re-encoding the value must reproduce `bytes` exactly,
+/// and decoding again must yield the same value. Any violation panics. Inputs that are not the
+/// canonical encoding of some value, e.g. maps whose keys are not sorted or ULEB128 integers
+/// that are not minimal, must be rejected by the decoder rather than re-encode differently: in
+/// that case, the decoding error is returned.
+///
+/// # Examples
+///
+/// ```
+/// use std::collections::BTreeMap;
+///
+/// // In a fuzz target: `let _ = check_roundtrip::<MyType>(data);`
+/// bcs::fuzz::check_roundtrip::<BTreeMap<u8, u8>>(&[1, 2, 3]).unwrap();
+/// assert_eq!(
+///     bcs::fuzz::check_roundtrip::<BTreeMap<u8, u8>>(&[2, 5, 0, 4, 0]),
+///     Err(bcs::Error::NonCanonicalMap { index: 1, offset: 3 }),
+/// );
+/// ```
+#[cfg(feature = "fuzz")]
+pub fn check_roundtrip<T>(bytes: &[u8]) -> crate::Result<()>
+where
+    T: Serialize + DeserializeOwned + Debug + PartialEq,
+{
+    let value: T = crate::from_bytes(bytes)?;
+    let encoded = crate::to_bytes(&value).expect("a decoded value must be serializable");
+    assert_eq!(
+        encoded, bytes,
+        "re-encoding {:?} does not reproduce the decoded bytes",
+        value
+    );
+    assert_eq!(
+        crate::serialized_size(&value).expect("a decoded value must be serializable"),
+        bytes.len(),
+        "serialized size of {:?} does not match the decoded bytes",
+        value
+    );
+    let decoded_again: T = crate::from_bytes(&encoded)?;
+    assert_eq!(decoded_again, value, "decoding is not deterministic");
+    Ok(())
+}
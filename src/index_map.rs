@@ -0,0 +1,56 @@
+// Copyright (c) The Diem Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Integration with `indexmap::IndexMap`, to be used with `#[serde(with = "bcs::index_map")]`.
+//!
+//! Since the deserializer rejects maps whose keys are not in canonical order (i.e. sorted by
+//! their serialized bytes), decoding a BCS map into an `IndexMap` always yields its entries in
+//! canonical order. This holds with or without this module.
+//!
+//! What this module adds is the serialization side: entries are written in their insertion
+//! order, like [`PreSorted`](crate::PreSorted), instead of being buffered and sorted. The map
+//! must therefore already be in canonical order, which is the case of any map decoded from BCS
+//! as long as no entries are inserted out of order afterwards. In debug builds, the ordering is
+//! checked and violations panic.
+//!
+//! ```rust
+//! # use bcs::{from_bytes, to_bytes, Result};
+//! # use indexmap::IndexMap;
+//! # use serde::{Deserialize, Serialize};
+//! # fn main() -> Result<()> {
+//! #[derive(Debug, Deserialize, PartialEq, Serialize)]
+//! struct Registry {
+//!     #[serde(with = "bcs::index_map")]
+//!     entries: IndexMap<String, u64>,
+//! }
+//!
+//! let bytes = vec![2, 1, b'a', 1, 0, 0, 0, 0, 0, 0, 0, 1, b'b', 2, 0, 0, 0, 0, 0, 0, 0];
+//! let registry: Registry = from_bytes(&bytes)?;
+//! assert_eq!(registry.entries.keys().collect::<Vec<_>>(), vec!["a", "b"]);
+//! assert_eq!(to_bytes(&registry)?, bytes);
+//! # Ok(())}
+//! ```
+
+use crate::PreSorted;
+use ::indexmap::IndexMap;
+use core::hash::{BuildHasher, Hash};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+pub fn serialize<K, V, H, S>(map: &IndexMap<K, V, H>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    K: Serialize,
+    V: Serialize,
+    S: Serializer,
+{
+    PreSorted(map).serialize(serializer)
+}
+
+pub fn deserialize<'de, K, V, H, D>(deserializer: D) -> Result<IndexMap<K, V, H>, D::Error>
+where
+    K: Deserialize<'de> + Eq + Hash,
+    V: Deserialize<'de>,
+    H: BuildHasher + Default,
+    D: Deserializer<'de>,
+{
+    IndexMap::deserialize(deserializer)
+}
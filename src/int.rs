@@ -0,0 +1,115 @@
+// Copyright (c) The Diem Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Fixed-width integers wider than the native `u128`.
+//!
+//! These types only take care of the encoding: like the built-in integers, they are
+//! serialized as little-endian, two's complement bytes without any length prefix.
+//!
+//! ```rust
+//! # use bcs::{from_bytes, int::U256, to_bytes, Result};
+//! # fn main() -> Result<()> {
+//! let mut le_bytes = [0u8; 32];
+//! le_bytes[0] = 0x01;
+//! le_bytes[31] = 0xff;
+//! let value = U256::from_le_bytes(le_bytes);
+//!
+//! let bytes = to_bytes(&value)?;
+//! assert_eq!(bytes, le_bytes);
+//! assert_eq!(from_bytes::<U256>(&bytes)?, value);
+//! # Ok(())}
+//! ```
+
+use core::fmt;
+use serde::{
+    de::{self, Deserialize, Deserializer, SeqAccess, Visitor},
+    ser::{Serialize, SerializeTuple, Serializer},
+};
+
+macro_rules! wide_uint {
+    ($name:ident, $bits:expr, $len:expr) => {
+        #[doc = concat!("A ", stringify!($bits), "-bit unsigned integer, serialized as ", stringify!($len), " little-endian bytes.")]
+        #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+        pub struct $name([u8; $len]);
+
+        impl $name {
+            /// The size of the serialized value, in bytes.
+            pub const BYTES: usize = $len;
+
+            /// The value `0`.
+            pub const ZERO: Self = Self([0; $len]);
+
+            /// Creates a value from its little-endian byte representation.
+            pub const fn from_le_bytes(bytes: [u8; $len]) -> Self {
+                Self(bytes)
+            }
+
+            /// Returns the little-endian byte representation of this value.
+            pub const fn to_le_bytes(self) -> [u8; $len] {
+                self.0
+            }
+        }
+
+        impl Default for $name {
+            fn default() -> Self {
+                Self::ZERO
+            }
+        }
+
+        impl From<u128> for $name {
+            fn from(value: u128) -> Self {
+                let mut bytes = [0; $len];
+                bytes[..16].copy_from_slice(&value.to_le_bytes());
+                Self(bytes)
+            }
+        }
+
+        impl Serialize for $name {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: Serializer,
+            {
+                let mut tuple = serializer.serialize_tuple($len)?;
+                for byte in &self.0 {
+                    tuple.serialize_element(byte)?;
+                }
+                tuple.end()
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+            where
+                D: Deserializer<'de>,
+            {
+                struct LeBytesVisitor;
+
+                impl<'de> Visitor<'de> for LeBytesVisitor {
+                    type Value = $name;
+
+                    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                        write!(formatter, "{} little-endian bytes", $len)
+                    }
+
+                    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+                    where
+                        A: SeqAccess<'de>,
+                    {
+                        let mut bytes = [0u8; $len];
+                        for (i, byte) in bytes.iter_mut().enumerate() {
+                            *byte = seq
+                                .next_element()?
+                                .ok_or_else(|| de::Error::invalid_length(i, &self))?;
+                        }
+                        Ok($name(bytes))
+                    }
+                }
+
+                deserializer.deserialize_tuple($len, LeBytesVisitor)
+            }
+        }
+    };
+}
+
+wide_uint!(U256, 256, 32);
+wide_uint!(U512, 512, 64);
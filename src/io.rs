@@ -1,67 +1,129 @@
-use alloc::{
-    format,
-    string::{String, ToString},
-    vec::Vec,
-};
-
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
-pub enum ErrorKind {
-    UnexpectedEof,
-    WriteZero,
-    Other,
+// Copyright (c) The Diem Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! The I/O traits used by [`from_reader`](crate::from_reader) and
+//! [`serialize_into`](crate::serialize_into).
+//!
+//! With the `std` feature, [`Read`] and [`Write`] are those of `std::io`. Without it, they are
+//! minimal equivalents providing the same `read`, `read_exact`, `write`, `write_all`, and `flush`
+//! methods, so that custom sources and sinks can be plugged in without depending on `std::io`.
+//! Implementations written against `bcs::io` compile in both configurations.
+//!
+//! # Examples
+//!
+//! Decoding from a toy source producing one byte at a time:
+//!
+//! ```
+//! use bcs::io::{Read, Result, SliceReader};
+//!
+//! struct OneByteAtATime<'a>(&'a [u8]);
+//!
+//! impl<'a> Read for OneByteAtATime<'a> {
+//!     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+//!         match (self.0.split_first(), buf.first_mut()) {
+//!             (Some((byte, rest)), Some(slot)) => {
+//!                 *slot = *byte;
+//!                 self.0 = rest;
+//!                 Ok(1)
+//!             }
+//!             _ => Ok(0),
+//!         }
+//!     }
+//! }
+//!
+//! let bytes = bcs::to_bytes(&vec!["one", "two"]).unwrap();
+//! let value: Vec<String> = bcs::from_reader(OneByteAtATime(&bytes)).unwrap();
+//! assert_eq!(value, vec!["one", "two"]);
+//!
+//! let same: Vec<String> = bcs::from_reader(SliceReader::new(&bytes)).unwrap();
+//! assert_eq!(same, value);
+//! ```
+//!
+//! On the serialization side, [`VecWriter`] appends to a vector and [`CountingWriter`] only
+//! counts the bytes, like [`serialized_size`](crate::serialized_size):
+//!
+//! ```
+//! use bcs::io::{CountingWriter, VecWriter};
+//!
+//! let mut bytes = Vec::new();
+//! bcs::serialize_into(&mut VecWriter::new(&mut bytes), &(1u16, "two")).unwrap();
+//!
+//! let mut counter = CountingWriter::new();
+//! bcs::serialize_into(&mut counter, &(1u16, "two")).unwrap();
+//! assert_eq!(counter.count(), bytes.len());
+//! ```
+
+#[cfg(not(feature = "std"))]
+mod core_io;
+
+#[cfg(not(feature = "std"))]
+pub use core_io::{Error, ErrorKind, Read, Result, Write};
+#[cfg(feature = "std")]
+pub use std::io::{Error, ErrorKind, Read, Result, Write};
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+#[cfg(all(test, not(feature = "std")))]
+pub(crate) use core_io::Cursor;
+#[cfg(all(test, feature = "std"))]
+pub(crate) use std::io::Cursor;
+
+/// A [`Read`] implementation over a borrowed byte slice.
+///
+/// Each read consumes bytes from the front of the slice; [`remaining`](SliceReader::remaining)
+/// returns what is left.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct SliceReader<'a> {
+    bytes: &'a [u8],
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
-pub struct Error {
-    kind: ErrorKind,
-    message: String,
-}
-
-impl Error {
-    pub fn new(kind: ErrorKind, message: &str) -> Self {
-        Self {
-            kind,
-            message: message.to_string(),
-        }
+impl<'a> SliceReader<'a> {
+    /// Creates a reader yielding the bytes of `bytes`, in order.
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
     }
 
-    pub fn kind(&self) -> ErrorKind {
-        self.kind
+    /// Returns the bytes that have not been read yet.
+    pub fn remaining(&self) -> &'a [u8] {
+        self.bytes
     }
 }
 
-impl ToString for Error {
-    fn to_string(&self) -> String {
-        format!("IO Error {:?}: {}", self.kind, self.message)
+impl<'a> Read for SliceReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let len = core::cmp::min(buf.len(), self.bytes.len());
+        let (read, rest) = self.bytes.split_at(len);
+        buf[..len].copy_from_slice(read);
+        self.bytes = rest;
+        Ok(len)
     }
 }
 
-pub type Result<T> = core::result::Result<T, Error>;
-
-pub trait Write {
-    fn write(&mut self, buf: &[u8]) -> Result<usize>;
-    fn flush(&mut self) -> Result<()>;
-
-    fn write_all(&mut self, mut buf: &[u8]) -> Result<()> {
-        while !buf.is_empty() {
-            match self.write(buf) {
-                Ok(0) => {
-                    return Err(Error::new(
-                        ErrorKind::WriteZero,
-                        "failed to write whole buffer",
-                    ));
-                }
-                Ok(n) => buf = &buf[n..],
-                Err(e) => return Err(e),
-            }
-        }
-        Ok(())
+/// A [`Write`] implementation appending to a borrowed vector.
+///
+/// `Vec<u8>` implements [`Write`] as well; this wrapper makes the borrow explicit, e.g. to
+/// serialize several values one after the other into the same vector.
+#[derive(Debug, Eq, PartialEq)]
+pub struct VecWriter<'a> {
+    vec: &'a mut Vec<u8>,
+}
+
+impl<'a> VecWriter<'a> {
+    /// Creates a writer appending to `vec`.
+    pub fn new(vec: &'a mut Vec<u8>) -> Self {
+        Self { vec }
+    }
+
+    /// Returns the bytes written so far, after the original content of the vector.
+    pub fn get_ref(&self) -> &[u8] {
+        self.vec
     }
 }
 
-impl Write for Vec<u8> {
+impl<'a> Write for VecWriter<'a> {
     fn write(&mut self, buf: &[u8]) -> Result<usize> {
-        self.extend(buf);
+        self.vec.extend_from_slice(buf);
         Ok(buf.len())
     }
 
@@ -70,48 +132,38 @@ impl Write for Vec<u8> {
     }
 }
 
-pub trait Read {
-    fn read(&mut self, buf: &mut [u8]) -> Result<usize>;
-
-    fn read_exact(&mut self, mut buf: &mut [u8]) -> Result<()> {
-        while !buf.is_empty() {
-            match self.read(buf) {
-                Ok(0) => break,
-                Ok(n) => {
-                    buf = &mut buf[n..];
-                }
-                Err(e) => return Err(e),
-            }
-        }
-
-        if !buf.is_empty() {
-            Err(Error {
-                kind: ErrorKind::UnexpectedEof,
-                message: "failed to fill whole buffer".to_string(),
-            })
-        } else {
-            Ok(())
-        }
-    }
+/// A [`Write`] implementation discarding its input, and only counting the bytes written.
+///
+/// This is exactly what [`serialized_size`](crate::serialized_size) uses. Writing more than
+/// `usize::MAX` bytes in total fails.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct CountingWriter {
+    count: usize,
 }
 
-pub struct Cursor {
-    data: Vec<u8>,
-    pos: usize,
-}
+impl CountingWriter {
+    /// Creates a writer with a count of zero.
+    pub const fn new() -> Self {
+        Self { count: 0 }
+    }
 
-#[cfg(test)]
-impl Cursor {
-    pub fn new(data: Vec<u8>) -> Self {
-        Self { data, pos: 0 }
+    /// Returns the number of bytes written so far.
+    pub const fn count(&self) -> usize {
+        self.count
     }
 }
 
-impl Read for Cursor {
-    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
-        let n = core::cmp::min(buf.len(), self.data.len() - self.pos);
-        buf[..n].copy_from_slice(&self.data[self.pos..self.pos + n]);
-        self.pos += n;
-        Ok(n)
+impl Write for CountingWriter {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let len = buf.len();
+        self.count = self
+            .count
+            .checked_add(len)
+            .ok_or_else(|| Error::new(ErrorKind::Other, "CountingWriter reached max value"))?;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
     }
 }
@@ -0,0 +1,237 @@
+// Copyright (c) The Diem Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#[cfg(not(feature = "std"))]
+use alloc::{boxed::Box, vec::Vec};
+
+use core::{fmt, marker::PhantomData};
+use serde::de::{
+    self, DeserializeSeed, Deserializer, EnumAccess, IgnoredAny, MapAccess, SeqAccess,
+    VariantAccess, Visitor,
+};
+
+/// Runtime description of the shape of a BCS value.
+///
+/// Since BCS is not self-describing, a `Layout` is what allows a value to be processed without
+/// knowing its Rust type statically.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Layout {
+    Bool,
+    U8,
+    U16,
+    U32,
+    U64,
+    U128,
+    I8,
+    I16,
+    I32,
+    I64,
+    I128,
+    Unit,
+    /// A UTF-8 string.
+    Str,
+    Option(Box<Layout>),
+    /// A variable-length sequence, prefixed with its length.
+    Seq(Box<Layout>),
+    /// A fixed-length sequence such as `[T; N]`.
+    Array(Box<Layout>, usize),
+    Tuple(Vec<Layout>),
+    /// A struct with the given field layouts. Counts towards the container depth.
+    Struct(Vec<Layout>),
+    /// An enum with the given variant payloads, in variant index order. Unit variants have a
+    /// `Unit` payload and struct variants a `Tuple` of their fields. Counts towards the
+    /// container depth.
+    Enum(Vec<Layout>),
+    Map(Box<Layout>, Box<Layout>),
+}
+
+/// Container name reported in depth errors for `Layout::Struct`.
+const STRUCT_NAME: &str = "struct";
+/// Container name reported in depth errors for `Layout::Enum`.
+const ENUM_NAME: &str = "enum";
+
+/// A `DeserializeSeed` that consumes exactly one value of the given layout and discards it.
+///
+/// BCS cannot support `deserialize_ignored_any` since the input does not describe itself. When
+/// the layout of the value to ignore is known, this seed can be used instead, e.g. from a
+/// hand-written `Visitor` or `DeserializeSeed`.
+///
+/// # Examples
+///
+/// ```
+/// use bcs::{Layout, Skip};
+/// use serde::de::{DeserializeSeed, SeqAccess, Visitor};
+/// use std::fmt;
+///
+/// /// Reads the last field of a `(Vec<String>, u16)` pair, skipping the first one.
+/// struct SecondField;
+///
+/// impl<'de> Visitor<'de> for SecondField {
+///     type Value = u16;
+///
+///     fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+///         formatter.write_str("a pair")
+///     }
+///
+///     fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<u16, A::Error> {
+///         let layout = Layout::Seq(Box::new(Layout::Str));
+///         seq.next_element_seed(Skip(&layout))?;
+///         Ok(seq.next_element()?.unwrap())
+///     }
+/// }
+///
+/// impl<'de> DeserializeSeed<'de> for SecondField {
+///     type Value = u16;
+///
+///     fn deserialize<D>(self, deserializer: D) -> Result<u16, D::Error>
+///     where
+///         D: serde::Deserializer<'de>,
+///     {
+///         deserializer.deserialize_tuple(2, self)
+///     }
+/// }
+///
+/// let bytes = bcs::to_bytes(&(vec!["a", "b"], 7u16)).unwrap();
+/// assert_eq!(bcs::from_bytes_seed(SecondField, &bytes).unwrap(), 7);
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct Skip<'a>(pub &'a Layout);
+
+impl<'de, 'a> DeserializeSeed<'de> for Skip<'a> {
+    type Value = ();
+
+    fn deserialize<D>(self, deserializer: D) -> Result<(), D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let ignored = match self.0 {
+            Layout::Bool => deserializer.deserialize_bool(IgnoredAny),
+            Layout::U8 => deserializer.deserialize_u8(IgnoredAny),
+            Layout::U16 => deserializer.deserialize_u16(IgnoredAny),
+            Layout::U32 => deserializer.deserialize_u32(IgnoredAny),
+            Layout::U64 => deserializer.deserialize_u64(IgnoredAny),
+            Layout::U128 => deserializer.deserialize_u128(IgnoredAny),
+            Layout::I8 => deserializer.deserialize_i8(IgnoredAny),
+            Layout::I16 => deserializer.deserialize_i16(IgnoredAny),
+            Layout::I32 => deserializer.deserialize_i32(IgnoredAny),
+            Layout::I64 => deserializer.deserialize_i64(IgnoredAny),
+            Layout::I128 => deserializer.deserialize_i128(IgnoredAny),
+            Layout::Unit => deserializer.deserialize_unit(IgnoredAny),
+            Layout::Str => deserializer.deserialize_str(IgnoredAny),
+            Layout::Option(_) => {
+                return deserializer.deserialize_option(SkipVisitor(self.0, PhantomData));
+            }
+            Layout::Seq(_) => {
+                return deserializer.deserialize_seq(SkipVisitor(self.0, PhantomData));
+            }
+            Layout::Array(_, len) => {
+                return deserializer.deserialize_tuple(*len, SkipVisitor(self.0, PhantomData));
+            }
+            Layout::Tuple(fields) => {
+                return deserializer
+                    .deserialize_tuple(fields.len(), SkipVisitor(self.0, PhantomData));
+            }
+            Layout::Struct(fields) => {
+                return deserializer.deserialize_tuple_struct(
+                    STRUCT_NAME,
+                    fields.len(),
+                    SkipVisitor(self.0, PhantomData),
+                );
+            }
+            Layout::Enum(_) => {
+                return deserializer.deserialize_enum(
+                    ENUM_NAME,
+                    &[],
+                    SkipVisitor(self.0, PhantomData),
+                );
+            }
+            Layout::Map(_, _) => {
+                return deserializer.deserialize_map(SkipVisitor(self.0, PhantomData));
+            }
+        };
+        ignored.map(|IgnoredAny| ())
+    }
+}
+
+/// Visitor skipping the content of a composite layout.
+struct SkipVisitor<'a, 'de>(&'a Layout, PhantomData<&'de ()>);
+
+impl<'a, 'de> Visitor<'de> for SkipVisitor<'a, 'de> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "a value of layout {:?}", self.0)
+    }
+
+    fn visit_none<E>(self) -> Result<(), E>
+    where
+        E: de::Error,
+    {
+        Ok(())
+    }
+
+    fn visit_some<D>(self, deserializer: D) -> Result<(), D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        match self.0 {
+            Layout::Option(inner) => Skip(inner).deserialize(deserializer),
+            _ => Err(de::Error::invalid_type(de::Unexpected::Option, &self)),
+        }
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<(), A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        match self.0 {
+            Layout::Seq(element) => while seq.next_element_seed(Skip(element))?.is_some() {},
+            Layout::Array(element, len) => {
+                for i in 0..*len {
+                    seq.next_element_seed(Skip(element))?
+                        .ok_or_else(|| de::Error::invalid_length(i, &self))?;
+                }
+            }
+            Layout::Tuple(fields) | Layout::Struct(fields) => {
+                for (i, field) in fields.iter().enumerate() {
+                    seq.next_element_seed(Skip(field))?
+                        .ok_or_else(|| de::Error::invalid_length(i, &self))?;
+                }
+            }
+            _ => return Err(de::Error::invalid_type(de::Unexpected::Seq, &self)),
+        }
+        Ok(())
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<(), A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        match self.0 {
+            Layout::Map(key, value) => {
+                while map.next_entry_seed(Skip(key), Skip(value))?.is_some() {}
+                Ok(())
+            }
+            _ => Err(de::Error::invalid_type(de::Unexpected::Map, &self)),
+        }
+    }
+
+    fn visit_enum<A>(self, data: A) -> Result<(), A::Error>
+    where
+        A: EnumAccess<'de>,
+    {
+        match self.0 {
+            Layout::Enum(variants) => {
+                let (index, variant) = data.variant_seed(PhantomData::<u32>)?;
+                match usize::try_from(index).ok().and_then(|i| variants.get(i)) {
+                    Some(payload) => variant.newtype_variant_seed(Skip(payload)),
+                    None => Err(de::Error::invalid_value(
+                        de::Unexpected::Unsigned(index.into()),
+                        &self,
+                    )),
+                }
+            }
+            _ => Err(de::Error::invalid_type(de::Unexpected::Enum, &self)),
+        }
+    }
+}
@@ -193,6 +193,9 @@
 //! different type supported by BCS. Each element of a tuple is serialized in the order it is
 //! defined within the tuple, i.e. [tuple.0, tuple.2].
 //!
+//! Tuples have no length prefix. Serde implements `Serialize` and `Deserialize` for tuples of up to
+//! 16 elements; larger tuples need to be wrapped in a struct.
+//!
 //! ```rust
 //! # use bcs::{Result, to_bytes};
 //! # fn main() -> Result<()> {
@@ -203,6 +206,25 @@
 //! ```
 //!
 //!
+//! ### Unit Types
+//!
+//! Types without any data are encoded as zero bytes: `()`, `PhantomData<T>`, unit structs such as
+//! `struct Marker;`, and structs without fields. Decoding them consumes no input, so
+//! `from_bytes::<()>(&[])` succeeds, while any byte left after them is reported as
+//! `Error::RemainingInput`. Since structs count towards the container depth, unit structs still
+//! do. An optional unit value takes the one byte of its option tag.
+//!
+//! ```rust
+//! # use bcs::{Result, to_bytes};
+//! # fn main() -> Result<()> {
+//! #[derive(serde::Serialize)]
+//! struct Marker;
+//!
+//! assert!(to_bytes(&((), Marker, std::marker::PhantomData::<u64>))?.is_empty());
+//! assert_eq!(to_bytes(&Some(()))?, vec![1]);
+//! # Ok(())}
+//! ```
+//!
 //! ### Structures
 //!
 //! Structures are fixed length sequences consisting of fields with potentially different types.
@@ -249,6 +271,19 @@
 //! # Ok(())}
 //! ```
 //!
+//! `#[serde(flatten)]` is not supported: serde encodes a struct with flattened fields as a map
+//! keyed by field name, which cannot be decoded without self-description. Serializing or
+//! deserializing such a struct fails with `Error::FlattenUnsupported`. Nesting the struct as a
+//! regular field encodes exactly the same bytes as declaring its fields in the parent.
+//!
+//! Fields marked `#[serde(skip)]` are neither encoded nor decoded, and BCS has no field names
+//! or count to tell them apart from the next field. The encoder and the decoder must therefore
+//! skip exactly the same fields: if only one side skips a field, or `skip_serializing` is used
+//! without `skip_deserializing`, every field after it is read from the wrong bytes. This is
+//! silent unless the decoded values happen to be invalid or the input does not end where the
+//! value does (`Error::RemainingInput`). When the layout of the encoded data is known,
+//! `from_bytes_with_layout` detects such mismatches in the number of bytes consumed.
+//!
 //! ### Externally Tagged Enumerations
 //!
 //! An enumeration is typically represented as a type that can take one of potentially many
@@ -286,7 +321,8 @@
 //! Maps are represented as a variable-length, sorted sequence of (Key, Value) tuples. Keys must be
 //! unique and the tuples sorted by increasing lexicographical order on the BCS bytes of each key.
 //! The representation is otherwise similar to that of a variable-length sequence. In particular,
-//! it is preceded by the number of tuples, encoded in ULEB128.
+//! it is preceded by the number of tuples, encoded in ULEB128. Maps encoded by hand can be sorted
+//! with [`canonical_key_cmp`].
 //!
 //! ```rust
 //! # use bcs::{Result, to_bytes};
@@ -308,30 +344,103 @@
 #[cfg(not(feature = "std"))]
 extern crate alloc;
 
+// Lets `#[derive(BcsFixedSize)]`, which refers to `::bcs`, be used within this crate.
+#[cfg(all(test, feature = "derive"))]
+extern crate self as bcs;
+
+pub mod array;
+mod bytes;
+mod captured;
+mod config;
+mod context;
 mod de;
+#[cfg(feature = "envelope")]
+pub mod envelope;
 mod error;
+pub mod fixed_bytes;
+pub mod fixed_size;
+#[cfg(any(feature = "arbitrary", feature = "fuzz"))]
+pub mod fuzz;
+#[cfg(feature = "indexmap")]
+pub mod index_map;
+pub mod int;
+pub mod io;
+mod layout;
+pub mod max_len;
+#[cfg(feature = "std")]
+pub mod net;
+pub mod prelude;
 mod ser;
+#[cfg(feature = "testing")]
+pub mod testing;
 #[cfg(test)]
 mod tests;
-
-#[cfg(feature = "std")]
-pub(crate) use std::io;
-
-#[cfg(not(feature = "std"))]
-pub(crate) mod io;
+pub mod time;
+pub mod uleb128;
+mod value;
 
 /// Variable length sequences in BCS are limited to max length of 2^31 - 1.
-pub const MAX_SEQUENCE_LENGTH: usize = (1 << 31) - 1;
+///
+/// On targets where `usize` is narrower than 32 bits, the limit is `usize::MAX` instead.
+pub const MAX_SEQUENCE_LENGTH: usize = if usize::BITS >= 32 {
+    (u32::MAX >> 1) as usize
+} else {
+    usize::MAX
+};
 
 /// Maximal allowed depth of BCS data, counting only structs and enums.
+///
+/// Both serialization and deserialization fail with `Error::ExceededContainerDepthLimit` beyond
+/// this depth, so that deeply nested values cannot overflow the stack on either side.
+/// Both also bound the nesting of sequences and maps, which have no name, to this depth,
+/// failing with `Error::ExceededSequenceDepthLimit`.
 pub const MAX_CONTAINER_DEPTH: usize = 1500;
 
+/// Version of the BCS format implemented by this crate, as opposed to the version of the crate.
+///
+/// BCS is meant to never change the encoding of a value, so this only changes if the format
+/// itself does. It is part of every [`FormatProfile`], which peers compare to make sure that they
+/// accept exactly the same data.
+///
+/// # Examples
+///
+/// ```
+/// assert_eq!(bcs::version(), bcs::Config::new().format_profile().format_version);
+/// ```
+pub const fn version() -> u32 {
+    1
+}
+
+#[cfg(feature = "derive")]
+pub use bcs_derive::BcsFixedSize;
+pub use bytes::{ByteBuf, Bytes};
+pub use captured::Captured;
+pub use config::{canonical_key_cmp, Config, FormatProfile, SerializerConfig, VariantMap};
+pub use context::{from_bytes_with_context, ContextSeed, DeserializeWithContext};
+#[cfg(feature = "mmap")]
+pub use de::from_mmap;
 pub use de::{
-    from_bytes, from_bytes_seed, from_bytes_seed_with_limit, from_bytes_with_limit, from_reader,
-    from_reader_seed, from_reader_seed_with_limit, from_reader_with_limit,
+    append_from_bytes, for_each_map_entry, for_each_map_entry_seed, from_byte_iter, from_bytes,
+    from_bytes_const, from_bytes_in_place, from_bytes_map_entries, from_bytes_ref, from_bytes_seed,
+    from_bytes_seed_with_limit, from_bytes_with_config, from_bytes_with_layout,
+    from_bytes_with_limit, from_bytes_with_stats, from_bytes_with_variant, from_chunks,
+    from_dyn_reader, from_reader, from_reader_counted, from_reader_in_place, from_reader_seed,
+    from_reader_seed_with_limit, from_reader_with_config, from_reader_with_limit,
+    from_slice_advancing, from_slice_reader, map_diff, peek_length, skip_value, try_from_bytes,
+    DecodeStats, Deserializer, MapDiff, ReadStreamDeserializer, ValueAction,
 };
-pub use error::{Error, Result};
+pub use error::{Error, IoError, Result};
+pub use fixed_size::FixedSize;
+pub use layout::{Layout, Skip};
+#[cfg(feature = "digest")]
+pub use ser::hash_into;
 pub use ser::{
-    is_human_readable, serialize_into, serialize_into_with_limit, serialized_size,
-    serialized_size_with_limit, to_bytes, to_bytes_with_limit,
+    is_human_readable, serialize_into, serialize_into_slice, serialize_into_with_config,
+    serialize_into_with_limit, serialize_map_from_unsorted, serialize_seq_iter, serialized_size,
+    serialized_size_breakdown, serialized_size_with_limit, to_bytes, to_bytes_le_slice,
+    to_bytes_sorted_check, to_bytes_with_capacity, to_bytes_with_config, to_bytes_with_limit,
+    PreSorted,
 };
+#[cfg(feature = "json")]
+pub use value::to_json;
+pub use value::{from_bytes_value, to_bytes_value, Value, ValueSeed};
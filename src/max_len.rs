@@ -0,0 +1,116 @@
+// Copyright (c) The Diem Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Per-type bounds on the length of sequences, to be used with
+//! `#[serde(with = "bcs::max_len")]` on `Vec<T>` fields whose element type implements
+//! [`MaxLen`].
+//!
+//! BCS only bounds sequences globally, by [`MAX_SEQUENCE_LENGTH`](crate::MAX_SEQUENCE_LENGTH) or
+//! the limits of [`Config`](crate::Config), which must be large enough for the longest sequence
+//! of any type. With this module, a sequence of `T` longer than `T::MAX` is rejected as soon as
+//! its length is read, before any element is decoded or allocated, and cannot be serialized
+//! either. The encoding is unchanged. Sequences of types without a `MaxLen` implementation, and
+//! fields without the attribute, are still bounded by the global limits only.
+//!
+//! Since serde's error types are opaque, decoding errors are `Error::Custom`, not
+//! `Error::ExceededMaxLen`.
+//!
+//! ```rust
+//! # use bcs::{from_bytes, max_len::MaxLen, to_bytes, Result};
+//! # use serde::{Deserialize, Serialize};
+//! # fn main() -> Result<()> {
+//! #[derive(Debug, Deserialize, PartialEq, Serialize)]
+//! struct Signature([u8; 2]);
+//!
+//! impl MaxLen for Signature {
+//!     const MAX: usize = 3;
+//! }
+//!
+//! #[derive(Debug, Deserialize, PartialEq, Serialize)]
+//! struct Certificate {
+//!     #[serde(with = "bcs::max_len")]
+//!     signatures: Vec<Signature>,
+//! }
+//!
+//! let certificate = Certificate { signatures: vec![Signature([1, 2]), Signature([3, 4])] };
+//! let bytes = to_bytes(&certificate)?;
+//! assert_eq!(from_bytes::<Certificate>(&bytes)?, certificate);
+//!
+//! // Four signatures are one too many, on both sides.
+//! let forged = [4, 0, 0, 0, 0, 0, 0, 0, 0];
+//! assert!(from_bytes::<Certificate>(&forged).is_err());
+//! let certificate = Certificate { signatures: (0..4).map(|i| Signature([i; 2])).collect() };
+//! assert!(to_bytes(&certificate).is_err());
+//! # Ok(())}
+//! ```
+
+use core::{fmt, marker::PhantomData};
+
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+use serde::{
+    de::{self, Deserialize, Deserializer, SeqAccess, Visitor},
+    ser::{self, Serialize, Serializer},
+};
+
+/// Types whose sequences are bounded by `MAX` elements when (de)serialized with
+/// [`bcs::max_len`](self).
+pub trait MaxLen {
+    /// The maximal number of elements of a sequence of this type.
+    const MAX: usize;
+}
+
+pub fn serialize<S, T>(values: &[T], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    T: MaxLen + Serialize,
+{
+    if values.len() > T::MAX {
+        return Err(ser::Error::custom(format_args!(
+            "sequence of {} elements exceeds the maximal length of {}",
+            values.len(),
+            T::MAX
+        )));
+    }
+    serializer.collect_seq(values)
+}
+
+pub fn deserialize<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: MaxLen + Deserialize<'de>,
+{
+    deserializer.deserialize_seq(BoundedVecVisitor(PhantomData))
+}
+
+struct BoundedVecVisitor<T>(PhantomData<T>);
+
+impl<'de, T> Visitor<'de> for BoundedVecVisitor<T>
+where
+    T: MaxLen + Deserialize<'de>,
+{
+    type Value = Vec<T>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(formatter, "a sequence of at most {} elements", T::MAX)
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Vec<T>, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        // BCS sequences report their exact length before the first element.
+        let len = seq.size_hint().unwrap_or(0);
+        if len > T::MAX {
+            return Err(de::Error::invalid_length(len, &self));
+        }
+        let mut values = Vec::with_capacity(len.min(4096));
+        while let Some(value) = seq.next_element()? {
+            if values.len() == T::MAX {
+                return Err(de::Error::invalid_length(T::MAX + 1, &self));
+            }
+            values.push(value);
+        }
+        Ok(values)
+    }
+}
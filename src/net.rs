@@ -0,0 +1,139 @@
+// Copyright (c) The Diem Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Canonical encodings for the network address types of `std::net`, to be used with
+//! `#[serde(with = "...")]`.
+//!
+//! * `Ipv4Addr` is encoded as its 4 octets and `Ipv6Addr` as its 16 octets, in network order.
+//! * `IpAddr` is encoded like an enum with variants `V4([u8; 4])` and `V6([u8; 16])`.
+//! * `SocketAddr` is encoded like an enum with variants `V4([u8; 4], u16)` and
+//! `V6([u8; 16], u16)`, where the port is a little-endian `u16` like any other integer. The
+//! flow info and scope id of IPv6 socket addresses are not encoded.
+//!
+//! ```rust
+//! # use bcs::{from_bytes, to_bytes, Result};
+//! # use serde::{Deserialize, Serialize};
+//! # use std::net::{Ipv4Addr, SocketAddr};
+//! # fn main() -> Result<()> {
+//! #[derive(Debug, Deserialize, PartialEq, Serialize)]
+//! struct Peer {
+//!     #[serde(with = "bcs::net::socket_addr")]
+//!     address: SocketAddr,
+//! }
+//!
+//! let peer = Peer {
+//!     address: SocketAddr::from((Ipv4Addr::new(127, 0, 0, 1), 8001)),
+//! };
+//! let bytes = to_bytes(&peer)?;
+//! assert_eq!(bytes, vec![0, 127, 0, 0, 1, 0x41, 0x1f]);
+//! assert_eq!(from_bytes::<Peer>(&bytes)?, peer);
+//! # Ok(())}
+//! ```
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename = "IpAddr")]
+enum IpAddrRepr {
+    V4([u8; 4]),
+    V6([u8; 16]),
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename = "SocketAddr")]
+enum SocketAddrRepr {
+    V4([u8; 4], u16),
+    V6([u8; 16], u16),
+}
+
+/// Encodes an `Ipv4Addr` as 4 bytes.
+pub mod ipv4_addr {
+    use super::*;
+
+    pub fn serialize<S>(addr: &Ipv4Addr, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        addr.octets().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Ipv4Addr, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        <[u8; 4]>::deserialize(deserializer).map(Ipv4Addr::from)
+    }
+}
+
+/// Encodes an `Ipv6Addr` as 16 bytes.
+pub mod ipv6_addr {
+    use super::*;
+
+    pub fn serialize<S>(addr: &Ipv6Addr, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        addr.octets().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Ipv6Addr, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        <[u8; 16]>::deserialize(deserializer).map(Ipv6Addr::from)
+    }
+}
+
+/// Encodes an `IpAddr` as a variant index (0 for IPv4, 1 for IPv6) followed by the address bytes.
+pub mod ip_addr {
+    use super::*;
+
+    pub fn serialize<S>(addr: &IpAddr, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match addr {
+            IpAddr::V4(addr) => IpAddrRepr::V4(addr.octets()),
+            IpAddr::V6(addr) => IpAddrRepr::V6(addr.octets()),
+        }
+        .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<IpAddr, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(match IpAddrRepr::deserialize(deserializer)? {
+            IpAddrRepr::V4(octets) => IpAddr::from(octets),
+            IpAddrRepr::V6(octets) => IpAddr::from(octets),
+        })
+    }
+}
+
+/// Encodes a `SocketAddr` as a variant index (0 for IPv4, 1 for IPv6) followed by the address
+/// bytes and the port.
+pub mod socket_addr {
+    use super::*;
+
+    pub fn serialize<S>(addr: &SocketAddr, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match addr {
+            SocketAddr::V4(addr) => SocketAddrRepr::V4(addr.ip().octets(), addr.port()),
+            SocketAddr::V6(addr) => SocketAddrRepr::V6(addr.ip().octets(), addr.port()),
+        }
+        .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<SocketAddr, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(match SocketAddrRepr::deserialize(deserializer)? {
+            SocketAddrRepr::V4(octets, port) => SocketAddr::from((octets, port)),
+            SocketAddrRepr::V6(octets, port) => SocketAddr::from((octets, port)),
+        })
+    }
+}
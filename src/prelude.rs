@@ -0,0 +1,23 @@
+// Copyright (c) The Diem Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! The commonly used items of this crate, to be imported with `use bcs::prelude::*;`.
+//!
+//! ```rust
+//! use bcs::prelude::*;
+//!
+//! # fn main() -> Result<()> {
+//! let bytes = to_bytes_with_config(&vec![1u16, 2], &SerializerConfig::new())?;
+//! let value: Vec<u16> = from_bytes_with_config(&bytes, &Config::new())?;
+//! assert_eq!(value, vec![1, 2]);
+//! # Ok(())}
+//! ```
+
+pub use crate::{
+    from_bytes, from_bytes_ref, from_bytes_seed, from_bytes_seed_with_limit,
+    from_bytes_with_config, from_bytes_with_limit, from_reader, from_reader_seed,
+    from_reader_seed_with_limit, from_reader_with_config, from_reader_with_limit, serialize_into,
+    serialize_into_with_config, serialize_into_with_limit, serialized_size,
+    serialized_size_with_limit, to_bytes, to_bytes_with_config, to_bytes_with_limit, ByteBuf,
+    Bytes, Config, Error, Result, SerializerConfig, MAX_CONTAINER_DEPTH, MAX_SEQUENCE_LENGTH,
+};
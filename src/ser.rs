@@ -2,9 +2,12 @@
 // SPDX-License-Identifier: Apache-2.0
 
 #[cfg(not(feature = "std"))]
-use alloc::vec::Vec;
+use alloc::{vec, vec::Vec};
 
+use crate::array::LeInteger;
+use crate::config::{canonical_key_cmp, SerializerConfig};
 use crate::error::{Error, Result};
+use crate::io::CountingWriter;
 use serde::{ser, Serialize};
 
 /// Serialize the given data structure as a `Vec<u8>` of BCS.
@@ -58,6 +61,54 @@ where
     Ok(output)
 }
 
+/// Same as `to_bytes`, but reserves `capacity_hint` bytes up front.
+///
+/// When the hint covers the whole encoding, e.g. because it comes from `serialized_size` or a
+/// previous encoding of a similar value, the output is allocated exactly once. The hint is not
+/// a limit: the output still grows if it is too small.
+///
+/// ```
+/// use bcs::{serialized_size, to_bytes_with_capacity};
+///
+/// let value = vec![(1u64, 2u32); 1000];
+/// let bytes = to_bytes_with_capacity(&value, serialized_size(&value).unwrap()).unwrap();
+/// assert_eq!(bytes.len(), bytes.capacity());
+/// ```
+pub fn to_bytes_with_capacity<T>(value: &T, capacity_hint: usize) -> Result<Vec<u8>>
+where
+    T: ?Sized + Serialize,
+{
+    let mut output = Vec::with_capacity(capacity_hint);
+    serialize_into(&mut output, value)?;
+    Ok(output)
+}
+
+/// Same as `to_bytes(slice)` for a slice of integers, but converts the elements in bulk.
+///
+/// `to_bytes` encodes the elements of a `&[u64]` one by one through the serializer. Integers are
+/// encoded as their little-endian bytes, so this function writes the length prefix and then
+/// appends the `to_le_bytes` of every element into a buffer allocated once. On little-endian
+/// hosts the conversion is the identity and the loop compiles down to a copy of the slice. Only
+/// the primitive integer types implementing [`LeInteger`] qualify; other element types must go
+/// through `to_bytes`.
+///
+/// ```
+/// use bcs::{to_bytes, to_bytes_le_slice};
+///
+/// let amounts: Vec<u64> = (0..1000).map(|i| i * 1_000_000).collect();
+/// assert_eq!(to_bytes_le_slice(&amounts).unwrap(), to_bytes(&amounts).unwrap());
+/// ```
+pub fn to_bytes_le_slice<T>(slice: &[T]) -> Result<Vec<u8>>
+where
+    T: LeInteger,
+{
+    let payload_len = slice.len().saturating_mul(T::SIZE);
+    let mut output = Vec::with_capacity(payload_len.saturating_add(5));
+    Serializer::with_defaults(&mut output).output_seq_len(slice.len())?;
+    T::extend_le_bytes(slice, &mut output);
+    Ok(output)
+}
+
 /// Same as `to_bytes` but use `limit` as max container depth instead of MAX_CONTAINER_DEPTH
 /// Note that `limit` has to be lower than MAX_CONTAINER_DEPTH
 pub fn to_bytes_with_limit<T>(value: &T, limit: usize) -> Result<Vec<u8>>
@@ -72,16 +123,88 @@ where
     Ok(output)
 }
 
+/// Same as `to_bytes` but enforce the limits of the given [`SerializerConfig`].
+pub fn to_bytes_with_config<T>(value: &T, config: &SerializerConfig) -> Result<Vec<u8>>
+where
+    T: ?Sized + Serialize,
+{
+    let mut output = Vec::new();
+    serialize_into_with_config(&mut output, value, config)?;
+    Ok(output)
+}
+
 /// Same as `to_bytes` but write directly into an `crate::io::Write` object.
 pub fn serialize_into<W, T>(write: &mut W, value: &T) -> Result<()>
 where
     W: ?Sized + crate::io::Write,
     T: ?Sized + Serialize,
 {
-    let serializer = Serializer::new(write, crate::MAX_CONTAINER_DEPTH);
+    let serializer = Serializer::with_defaults(write);
+    value.serialize(serializer)
+}
+
+/// Same as `serialize_into` but enforce the limits of the given [`SerializerConfig`].
+pub fn serialize_into_with_config<W, T>(
+    write: &mut W,
+    value: &T,
+    config: &SerializerConfig,
+) -> Result<()>
+where
+    W: ?Sized + crate::io::Write,
+    T: ?Sized + Serialize,
+{
+    if config.max_container_depth > crate::MAX_CONTAINER_DEPTH {
+        return Err(Error::NotSupported("limit exceeds the max allowed depth"));
+    }
+    if config.max_sequence_length > crate::MAX_SEQUENCE_LENGTH {
+        return Err(Error::NotSupported(
+            "limit exceeds the max allowed sequence length",
+        ));
+    }
+    let serializer = Serializer::new(
+        write,
+        config.max_container_depth,
+        config.max_container_depth,
+        config.max_sequence_length,
+    );
     value.serialize(serializer)
 }
 
+/// Same as `to_bytes` but write into a caller-provided buffer, returning the number of bytes
+/// written. This does not allocate, which makes it usable on heapless targets.
+///
+/// If the serialized value does not fit, `Error::BufferTooSmall` reports the size of the full
+/// encoding so that the call can be retried with a large enough buffer. The content of `buf` is
+/// unspecified in that case.
+///
+/// # Examples
+///
+/// ```
+/// use bcs::{serialize_into_slice, Error};
+///
+/// let mut buf = [0u8; 8];
+/// let len = serialize_into_slice(&mut buf, &(1u16, true)).unwrap();
+/// assert_eq!(&buf[..len], &[1, 0, 1]);
+///
+/// assert_eq!(
+///     serialize_into_slice(&mut buf, &[7u32; 3]),
+///     Err(Error::BufferTooSmall { needed: 12 }),
+/// );
+/// ```
+pub fn serialize_into_slice<T>(buf: &mut [u8], value: &T) -> Result<usize>
+where
+    T: ?Sized + Serialize,
+{
+    let mut writer = SliceWriter { buf, needed: 0 };
+    serialize_into(&mut writer, value)?;
+    if writer.needed > writer.buf.len() {
+        return Err(Error::BufferTooSmall {
+            needed: writer.needed,
+        });
+    }
+    Ok(writer.needed)
+}
+
 /// Same as `serialize_into` but use `limit` as max container depth instead of MAX_CONTAINER_DEPTH
 /// Note that `limit` has to be lower than MAX_CONTAINER_DEPTH
 pub fn serialize_into_with_limit<W, T>(write: &mut W, value: &T, limit: usize) -> Result<()>
@@ -92,22 +215,331 @@ where
     if limit > crate::MAX_CONTAINER_DEPTH {
         return Err(Error::NotSupported("limit exceeds the max allowed depth"));
     }
-    let serializer = Serializer::new(write, limit);
+    let serializer = Serializer::new(write, limit, limit, crate::MAX_SEQUENCE_LENGTH);
     value.serialize(serializer)
 }
 
-struct WriteCounter(usize);
+/// Serialize the items of an iterator as a BCS sequence into an `crate::io::Write` object.
+///
+/// This produces the same bytes as serializing a `Vec` of the items, without having to collect
+/// them first. The length prefix is written up front from `ExactSizeIterator::len`, so the
+/// iterator must report its length accurately: otherwise, this fails with
+/// `Error::IteratorLengthMismatch` once the items run out, and the output must be discarded.
+///
+/// # Examples
+///
+/// ```
+/// use bcs::{serialize_seq_iter, to_bytes};
+///
+/// let mut output = Vec::new();
+/// serialize_seq_iter(&mut output, 0u16..1000).unwrap();
+///
+/// let collected: Vec<u16> = (0..1000).collect();
+/// assert_eq!(output, to_bytes(&collected).unwrap());
+/// ```
+pub fn serialize_seq_iter<W, I>(write: &mut W, iter: I) -> Result<()>
+where
+    W: ?Sized + crate::io::Write,
+    I: IntoIterator,
+    I::IntoIter: ExactSizeIterator,
+    I::Item: Serialize,
+{
+    let iter = iter.into_iter();
+    let mut serializer = Serializer::with_defaults(write);
+    let len = iter.len();
+    serializer.enter_sequence()?;
+    serializer.output_seq_len(len)?;
+    let mut yielded = 0;
+    for item in iter {
+        yielded += 1;
+        ser::SerializeSeq::serialize_element(&mut serializer, &item)?;
+    }
+    if yielded != len {
+        return Err(Error::IteratorLengthMismatch {
+            reported: len,
+            yielded,
+        });
+    }
+    Ok(())
+}
+
+/// Serialize unsorted map entries as a canonical BCS map into an `crate::io::Write` object.
+///
+/// Each key is serialized first, and the entries are then sorted by the bytes of their keys,
+/// as BCS requires. This produces the same bytes as serializing a map with the same entries,
+/// without building one: the keys only need to implement `Serialize`, not `Ord` or `Hash`.
+/// Unlike maps, whose keys are unique by construction, `entries` may contain the same key
+/// twice; this fails with `Error::DuplicateMapKey` rather than silently dropping an entry.
+///
+/// # Examples
+///
+/// ```
+/// use bcs::{serialize_map_from_unsorted, to_bytes, Error};
+/// use std::collections::BTreeMap;
+///
+/// let entries = vec![(300u16, "c"), (1, "a"), (2, "b")];
+/// let mut output = Vec::new();
+/// serialize_map_from_unsorted(&mut output, entries.clone()).unwrap();
+///
+/// let map: BTreeMap<u16, &str> = entries.into_iter().collect();
+/// assert_eq!(output, to_bytes(&map).unwrap());
+///
+/// let duplicates = vec![(1u8, "a"), (1, "b")];
+/// assert_eq!(
+///     serialize_map_from_unsorted(&mut Vec::new(), duplicates),
+///     Err(Error::DuplicateMapKey),
+/// );
+/// ```
+pub fn serialize_map_from_unsorted<W, K, V, I>(write: &mut W, entries: I) -> Result<()>
+where
+    W: ?Sized + crate::io::Write,
+    I: IntoIterator<Item = (K, V)>,
+    K: Serialize,
+    V: Serialize,
+{
+    let mut entries = entries
+        .into_iter()
+        .map(|(key, value)| Ok((to_bytes(&key)?, value)))
+        .collect::<Result<Vec<_>>>()?;
+    entries.sort_by(|e1, e2| canonical_key_cmp(&e1.0, &e2.0));
+    if entries.windows(2).any(|pair| pair[0].0 == pair[1].0) {
+        return Err(Error::DuplicateMapKey);
+    }
+
+    let mut serializer = Serializer::with_defaults(write);
+    serializer.enter_sequence()?;
+    serializer.output_seq_len(entries.len())?;
+    for (key, value) in &entries {
+        serializer.output.write_all(key)?;
+        ser::SerializeSeq::serialize_element(&mut serializer, value)?;
+    }
+    Ok(())
+}
+
+/// Serialize map entries that are expected to be sorted in canonical BCS order, checking that
+/// they are.
+///
+/// This writes the same bytes as [`PreSorted`], without buffering or sorting the entries, but
+/// fails with `Error::NonCanonicalMap` if a key is not strictly greater than the previous one
+/// by its BCS bytes, as a deserializer would. Use it instead of `PreSorted` wherever a bug in
+/// the ordering of the entries should be caught at encoding time, even in release builds. The
+/// check reuses the bytes of the keys in the output, so it costs one comparison per entry.
+/// The length prefix is taken from `ExactSizeIterator::len`, and this fails with
+/// `Error::IteratorLengthMismatch` if the number of entries does not match it.
+///
+/// # Examples
+///
+/// ```
+/// use bcs::{to_bytes, to_bytes_sorted_check, Error};
+/// use std::collections::BTreeMap;
+///
+/// let map: BTreeMap<u8, &str> = [(1, "a"), (2, "b")].into();
+/// assert_eq!(to_bytes_sorted_check(&map).unwrap(), to_bytes(&map).unwrap());
+///
+/// // Sorted as integers, but 256 is encoded as [0, 1], which comes before 1, encoded as [1, 0].
+/// assert_eq!(
+///     to_bytes_sorted_check(vec![(1u16, "a"), (256, "b")]),
+///     Err(Error::NonCanonicalMap { index: 1, offset: 5 }),
+/// );
+/// ```
+pub fn to_bytes_sorted_check<I, K, V>(entries: I) -> Result<Vec<u8>>
+where
+    I: IntoIterator<Item = (K, V)>,
+    I::IntoIter: ExactSizeIterator,
+    K: Serialize,
+    V: Serialize,
+{
+    let entries = entries.into_iter();
+    let mut output = Vec::new();
+    let mut serializer = Serializer::with_defaults(&mut output);
+    let len = entries.len();
+    serializer.enter_sequence()?;
+    serializer.output_seq_len(len)?;
+    let mut previous_key = None;
+    let mut yielded = 0;
+    for (index, (key, value)) in entries.enumerate() {
+        yielded += 1;
+        let start = serializer.output.len();
+        ser::SerializeSeq::serialize_element(&mut serializer, &key)?;
+        let key_bytes = start..serializer.output.len();
+        if let Some(previous_key) = previous_key {
+            let (previous, current) = (
+                &serializer.output[previous_key],
+                &serializer.output[key_bytes.clone()],
+            );
+            if canonical_key_cmp(previous, current) != core::cmp::Ordering::Less {
+                return Err(Error::NonCanonicalMap {
+                    index,
+                    offset: key_bytes.start,
+                });
+            }
+        }
+        previous_key = Some(key_bytes);
+        ser::SerializeSeq::serialize_element(&mut serializer, &value)?;
+    }
+    if yielded != len {
+        return Err(Error::IteratorLengthMismatch {
+            reported: len,
+            yielded,
+        });
+    }
+    Ok(output)
+}
+
+/// Wrapper for map entries that are already sorted in canonical BCS order.
+///
+/// BCS requires map entries to be sorted by the serialized bytes of their keys, so the
+/// serializer normally buffers and sorts every map before writing it. `PreSorted` skips that
+/// work: entries are written out directly, in the order they are yielded, exactly as a map
+/// with the same entries would be encoded.
+///
+/// The caller must guarantee that the keys are unique and strictly increasing by their BCS
+/// bytes. Note that this is not the same as the `Ord` order of the keys in general (e.g. for
+/// integers wider than one byte, which are encoded in little endian). In debug builds, the
+/// ordering is checked and violations panic; release builds do not check it at all and write
+/// a non-canonical map. [`to_bytes_sorted_check`] checks it in all builds and returns an error
+/// instead.
+///
+/// The length prefix is taken from `ExactSizeIterator::len`. If the entries do not match it,
+/// serialization fails in all builds with `Error::IteratorLengthMismatch`.
+///
+/// Other serializers see the entries as a map in a newtype struct, which most of them encode
+/// like the map itself.
+///
+/// # Examples
+///
+/// ```
+/// use bcs::{to_bytes, PreSorted};
+/// use std::collections::BTreeMap;
+///
+/// let mut map = BTreeMap::new();
+/// map.insert(b'a', 1u16);
+/// map.insert(b'b', 2u16);
+///
+/// assert_eq!(to_bytes(&PreSorted(&map)).unwrap(), to_bytes(&map).unwrap());
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct PreSorted<I>(pub I);
+
+/// The name of the newtype struct around the entries of a [`PreSorted`], which the BCS serializer
+/// writes as they come instead of sorting them.
+const PRE_SORTED_NAME: &str = "bcs::PreSorted";
+
+impl<I, K, V> Serialize for PreSorted<I>
+where
+    I: Clone + IntoIterator<Item = (K, V)>,
+    I::IntoIter: ExactSizeIterator,
+    K: Serialize,
+    V: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error>
+    where
+        S: ser::Serializer,
+    {
+        serializer.serialize_newtype_struct(PRE_SORTED_NAME, &PreSortedEntries(&self.0))
+    }
+}
+
+/// The entries of a [`PreSorted`], serialized as a map.
+struct PreSortedEntries<'a, I>(&'a I);
 
-impl crate::io::Write for WriteCounter {
+impl<'a, I, K, V> Serialize for PreSortedEntries<'a, I>
+where
+    I: Clone + IntoIterator<Item = (K, V)>,
+    I::IntoIter: ExactSizeIterator,
+    K: Serialize,
+    V: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error>
+    where
+        S: ser::Serializer,
+    {
+        use ser::SerializeMap;
+
+        let entries = self.0.clone().into_iter();
+        let len = entries.len();
+        let mut map = serializer.serialize_map(Some(len))?;
+        let mut yielded = 0;
+        for (key, value) in entries {
+            yielded += 1;
+            map.serialize_entry(&key, &value)?;
+        }
+        let result = map.end();
+        if yielded != len {
+            // The BCS serializer returns the mismatch itself, other serializers don't.
+            result?;
+            return Err(ser::Error::custom(Error::IteratorLengthMismatch {
+                reported: len,
+                yielded,
+            }));
+        }
+        result
+    }
+}
+
+/// Adapter feeding everything written to it into a `digest::Update` implementation.
+#[cfg(feature = "digest")]
+struct UpdateWriter<'a, H: ?Sized>(&'a mut H);
+
+#[cfg(feature = "digest")]
+impl<'a, H> crate::io::Write for UpdateWriter<'a, H>
+where
+    H: ?Sized + digest::Update,
+{
     fn write(&mut self, buf: &[u8]) -> crate::io::Result<usize> {
-        let len = buf.len();
-        self.0 = self.0.checked_add(len).ok_or_else(|| {
-            crate::io::Error::new(
-                crate::io::ErrorKind::Other,
-                "WriteCounter reached max value",
-            )
-        })?;
-        Ok(len)
+        self.0.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> crate::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Same as `serialize_into` but feed the serialized bytes into a hasher, without ever
+/// materializing the full encoding.
+///
+/// # Examples
+///
+/// ```
+/// use digest::Update;
+///
+/// /// A toy "hasher" counting the bytes fed to it.
+/// #[derive(Default)]
+/// struct Counter(usize);
+///
+/// impl Update for Counter {
+///     fn update(&mut self, data: &[u8]) {
+///         self.0 += data.len();
+///     }
+/// }
+///
+/// let mut hasher = Counter::default();
+/// bcs::hash_into(&mut hasher, &vec![0u64; 100]).unwrap();
+/// assert_eq!(hasher.0, 801);
+/// ```
+#[cfg(feature = "digest")]
+pub fn hash_into<H, T>(hasher: &mut H, value: &T) -> Result<()>
+where
+    H: ?Sized + digest::Update,
+    T: ?Sized + Serialize,
+{
+    serialize_into(&mut UpdateWriter(hasher), value)
+}
+
+/// Writes into a fixed buffer, and keeps counting the bytes that don't fit.
+struct SliceWriter<'a> {
+    buf: &'a mut [u8],
+    needed: usize,
+}
+
+impl<'a> crate::io::Write for SliceWriter<'a> {
+    fn write(&mut self, buf: &[u8]) -> crate::io::Result<usize> {
+        let start = core::cmp::min(self.needed, self.buf.len());
+        let len = core::cmp::min(buf.len(), self.buf.len() - start);
+        self.buf[start..start + len].copy_from_slice(&buf[..len]);
+        self.needed = self.needed.saturating_add(buf.len());
+        Ok(buf.len())
     }
 
     fn flush(&mut self) -> crate::io::Result<()> {
@@ -116,13 +548,16 @@ impl crate::io::Write for WriteCounter {
 }
 
 /// Same as `to_bytes` but only return the size of the serialized bytes.
+///
+/// This runs the same serializer as `to_bytes` over a [`CountingWriter`], so it fails exactly when
+/// `to_bytes` would, with the same error. It is therefore safe to use for pre-sizing buffers.
 pub fn serialized_size<T>(value: &T) -> Result<usize>
 where
     T: ?Sized + Serialize,
 {
-    let mut counter = WriteCounter(0);
+    let mut counter = CountingWriter::new();
     serialize_into(&mut counter, value)?;
-    Ok(counter.0)
+    Ok(counter.count())
 }
 
 /// Same as `serialized_size` but use `limit` as max container depth instead of MAX_CONTAINER_DEPTH
@@ -134,14 +569,59 @@ where
     if limit > crate::MAX_CONTAINER_DEPTH {
         return Err(Error::NotSupported("limit exceeds the max allowed depth"));
     }
-    let mut counter = WriteCounter(0);
+    let mut counter = CountingWriter::new();
     serialize_into_with_limit(&mut counter, value, limit)?;
-    Ok(counter.0)
+    Ok(counter.count())
+}
+
+/// Same as `serialized_size`, but return the size of each field of a struct separately.
+///
+/// For a struct, possibly wrapped in newtype structs, this returns the name and serialized
+/// size of each of its fields, in order. Nested structs are not broken down. For any other
+/// value, a single entry with an empty name and the total size is returned. Either way, the
+/// sizes add up to `serialized_size(value)`, and this fails exactly when `serialized_size`
+/// would, with the same error.
+///
+/// # Examples
+///
+/// ```
+/// use bcs::serialized_size_breakdown;
+/// use serde::Serialize;
+///
+/// #[derive(Serialize)]
+/// struct Message {
+///     id: u64,
+///     payload: Vec<u8>,
+///     signature: Option<Vec<u8>>,
+/// }
+///
+/// let message = Message { id: 1, payload: vec![0; 300], signature: None };
+/// assert_eq!(
+///     serialized_size_breakdown(&message).unwrap(),
+///     [("id", 8), ("payload", 302), ("signature", 1)],
+/// );
+/// assert_eq!(serialized_size_breakdown(&7u16).unwrap(), [("", 2)]);
+/// ```
+pub fn serialized_size_breakdown<T>(value: &T) -> Result<Vec<(&'static str, usize)>>
+where
+    T: ?Sized + Serialize,
+{
+    let mut counter = CountingWriter::new();
+    let mut field_sizes = FieldSizes {
+        sizes: None,
+        count: CountingWriter::count,
+    };
+    let mut serializer = Serializer::with_defaults(&mut counter);
+    serializer.field_sizes = Some(&mut field_sizes);
+    value.serialize(serializer)?;
+    Ok(field_sizes
+        .sizes
+        .unwrap_or_else(|| vec![("", counter.count())]))
 }
 
 pub fn is_human_readable() -> bool {
     let mut output = Vec::new();
-    let serializer = Serializer::new(&mut output, crate::MAX_CONTAINER_DEPTH);
+    let serializer = Serializer::with_defaults(&mut output);
     ser::Serializer::is_human_readable(&serializer)
 }
 
@@ -149,6 +629,21 @@ pub fn is_human_readable() -> bool {
 struct Serializer<'a, W: ?Sized> {
     output: &'a mut W,
     max_remaining_depth: usize,
+    /// The container depth limit of the top-level call, kept for error reporting.
+    container_depth_limit: usize,
+    max_sequence_length: usize,
+    /// Sequences and maps, which are not counted by `max_remaining_depth`, are bounded
+    /// separately, like when deserializing.
+    max_remaining_sequence_depth: usize,
+    /// Where to record the size of each field if the value is a struct, possibly wrapped in
+    /// newtype structs, for `serialized_size_breakdown`. Not passed on to fields and elements.
+    field_sizes: Option<&'a mut FieldSizes<W>>,
+}
+
+/// The size of each field of a struct, measured with the `count` of the output.
+struct FieldSizes<W: ?Sized> {
+    sizes: Option<Vec<(&'static str, usize)>>,
+    count: fn(&W) -> usize,
 }
 
 impl<'a, W> Serializer<'a, W>
@@ -156,10 +651,54 @@ where
     W: ?Sized + crate::io::Write,
 {
     /// Creates a new `Serializer` which will emit BCS.
-    fn new(output: &'a mut W, max_remaining_depth: usize) -> Self {
+    fn new(
+        output: &'a mut W,
+        max_remaining_depth: usize,
+        container_depth_limit: usize,
+        max_sequence_length: usize,
+    ) -> Self {
         Self {
             output,
             max_remaining_depth,
+            container_depth_limit,
+            max_sequence_length,
+            max_remaining_sequence_depth: crate::MAX_CONTAINER_DEPTH,
+            field_sizes: None,
+        }
+    }
+
+    /// Creates a new `Serializer` with the default limits.
+    fn with_defaults(output: &'a mut W) -> Self {
+        Self::new(
+            output,
+            crate::MAX_CONTAINER_DEPTH,
+            crate::MAX_CONTAINER_DEPTH,
+            crate::MAX_SEQUENCE_LENGTH,
+        )
+    }
+
+    /// Returns a serializer for a value nested in the current one, with the same output and the
+    /// remaining limits.
+    fn reborrow(&mut self) -> Serializer<'_, W> {
+        Serializer {
+            output: &mut *self.output,
+            max_remaining_depth: self.max_remaining_depth,
+            container_depth_limit: self.container_depth_limit,
+            max_sequence_length: self.max_sequence_length,
+            max_remaining_sequence_depth: self.max_remaining_sequence_depth,
+            field_sizes: None,
+        }
+    }
+
+    /// Same as `reborrow`, but writing into `output`, e.g. to buffer the keys of a map.
+    fn with_output<'b, V: ?Sized>(&self, output: &'b mut V) -> Serializer<'b, V> {
+        Serializer {
+            output,
+            max_remaining_depth: self.max_remaining_depth,
+            container_depth_limit: self.container_depth_limit,
+            max_sequence_length: self.max_sequence_length,
+            max_remaining_sequence_depth: self.max_remaining_sequence_depth,
+            field_sizes: None,
         }
     }
 
@@ -181,19 +720,68 @@ where
 
     /// Serialize a sequence length as a u32.
     fn output_seq_len(&mut self, len: usize) -> Result<()> {
-        if len > crate::MAX_SEQUENCE_LENGTH {
-            return Err(Error::ExceededMaxLen(len));
+        if len > self.max_sequence_length {
+            return Err(Error::ExceededMaxLen {
+                len,
+                max: self.max_sequence_length,
+            });
         }
         self.output_u32_as_uleb128(len as u32)
     }
 
+    /// Starts recording the sizes of the fields of a struct in `field_sizes`, unless something
+    /// was written before it, e.g. the tag of an option: the sizes would not add up to the total.
+    #[inline(never)]
+    fn start_field_sizes(&mut self, len: usize) {
+        if let Some(field_sizes) = self.field_sizes.as_deref_mut() {
+            if (field_sizes.count)(self.output) == 0 {
+                field_sizes.sizes = Some(Vec::with_capacity(len));
+            }
+        }
+    }
+
+    /// Serializes a field of the outermost struct, and records its size in `field_sizes`.
+    #[inline(never)]
+    fn serialize_field_with_size<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        let Some(field_sizes) = &self.field_sizes else {
+            return value.serialize(self.reborrow());
+        };
+        let count = field_sizes.count;
+        let start = count(self.output);
+        value.serialize(self.reborrow())?;
+        let size = count(self.output) - start;
+        if let Some(FieldSizes {
+            sizes: Some(sizes), ..
+        }) = &mut self.field_sizes
+        {
+            sizes.push((key, size));
+        }
+        Ok(())
+    }
+
     fn enter_named_container(&mut self, name: &'static str) -> Result<()> {
         if self.max_remaining_depth == 0 {
-            return Err(Error::ExceededContainerDepthLimit(name));
+            return Err(Error::ExceededContainerDepthLimit {
+                name,
+                limit: self.container_depth_limit,
+            });
         }
         self.max_remaining_depth -= 1;
         Ok(())
     }
+
+    fn enter_sequence(&mut self) -> Result<()> {
+        if self.max_remaining_sequence_depth == 0 {
+            return Err(Error::ExceededSequenceDepthLimit {
+                limit: crate::MAX_CONTAINER_DEPTH,
+            });
+        }
+        self.max_remaining_sequence_depth -= 1;
+        Ok(())
+    }
 }
 
 impl<'a, W> ser::Serializer for Serializer<'a, W>
@@ -320,6 +908,10 @@ where
     where
         T: ?Sized + Serialize,
     {
+        // `PreSorted` is transparent, and does not count towards the container depth.
+        if name == PRE_SORTED_NAME {
+            return value.serialize(PreSortedSerializer(self));
+        }
         self.enter_named_container(name)?;
         value.serialize(self)
     }
@@ -345,6 +937,7 @@ where
     // length structures, the length encoded as a u32.
     fn serialize_seq(mut self, len: Option<usize>) -> Result<Self::SerializeSeq> {
         if let Some(len) = len {
+            self.enter_sequence()?;
             self.output_seq_len(len)?;
             Ok(self)
         } else {
@@ -378,16 +971,16 @@ where
         Ok(self)
     }
 
-    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
-        Ok(MapSerializer::new(self))
+    fn serialize_map(mut self, len: Option<usize>) -> Result<Self::SerializeMap> {
+        self.enter_sequence()?;
+        Ok(MapSerializer::new(self, len.is_none()))
     }
 
-    fn serialize_struct(
-        mut self,
-        name: &'static str,
-        _len: usize,
-    ) -> Result<Self::SerializeStruct> {
+    fn serialize_struct(mut self, name: &'static str, len: usize) -> Result<Self::SerializeStruct> {
         self.enter_named_container(name)?;
+        if self.field_sizes.is_some() {
+            self.start_field_sizes(len);
+        }
         Ok(self)
     }
 
@@ -420,7 +1013,7 @@ where
     where
         T: ?Sized + Serialize,
     {
-        value.serialize(Serializer::new(self.output, self.max_remaining_depth))
+        value.serialize(self.reborrow())
     }
 
     fn end(self) -> Result<()> {
@@ -439,7 +1032,7 @@ where
     where
         T: ?Sized + Serialize,
     {
-        value.serialize(Serializer::new(self.output, self.max_remaining_depth))
+        value.serialize(self.reborrow())
     }
 
     fn end(self) -> Result<()> {
@@ -458,7 +1051,7 @@ where
     where
         T: ?Sized + Serialize,
     {
-        value.serialize(Serializer::new(self.output, self.max_remaining_depth))
+        value.serialize(self.reborrow())
     }
 
     fn end(self) -> Result<()> {
@@ -477,7 +1070,7 @@ where
     where
         T: ?Sized + Serialize,
     {
-        value.serialize(Serializer::new(self.output, self.max_remaining_depth))
+        value.serialize(self.reborrow())
     }
 
     fn end(self) -> Result<()> {
@@ -485,23 +1078,233 @@ where
     }
 }
 
+/// Serializes the map of a `PreSorted`, whose entries are written as they come. Since that is
+/// the only value it is given, everything else is rejected.
+struct PreSortedSerializer<'a, W: ?Sized>(Serializer<'a, W>);
+
+fn not_a_map() -> Error {
+    Error::NotSupported("PreSorted entries must be serialized as a map")
+}
+
+impl<'a, W> ser::Serializer for PreSortedSerializer<'a, W>
+where
+    W: ?Sized + crate::io::Write,
+{
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = ser::Impossible<(), Error>;
+    type SerializeTuple = ser::Impossible<(), Error>;
+    type SerializeTupleStruct = ser::Impossible<(), Error>;
+    type SerializeTupleVariant = ser::Impossible<(), Error>;
+    type SerializeMap = MapSerializer<'a, W>;
+    type SerializeStruct = ser::Impossible<(), Error>;
+    type SerializeStructVariant = ser::Impossible<(), Error>;
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap> {
+        MapSerializer::pre_sorted(self.0, len.ok_or(Error::MissingLen)?)
+    }
+
+    fn serialize_bool(self, _v: bool) -> Result<()> {
+        Err(not_a_map())
+    }
+
+    fn serialize_i8(self, _v: i8) -> Result<()> {
+        Err(not_a_map())
+    }
+
+    fn serialize_i16(self, _v: i16) -> Result<()> {
+        Err(not_a_map())
+    }
+
+    fn serialize_i32(self, _v: i32) -> Result<()> {
+        Err(not_a_map())
+    }
+
+    fn serialize_i64(self, _v: i64) -> Result<()> {
+        Err(not_a_map())
+    }
+
+    fn serialize_u8(self, _v: u8) -> Result<()> {
+        Err(not_a_map())
+    }
+
+    fn serialize_u16(self, _v: u16) -> Result<()> {
+        Err(not_a_map())
+    }
+
+    fn serialize_u32(self, _v: u32) -> Result<()> {
+        Err(not_a_map())
+    }
+
+    fn serialize_u64(self, _v: u64) -> Result<()> {
+        Err(not_a_map())
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<()> {
+        Err(not_a_map())
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<()> {
+        Err(not_a_map())
+    }
+
+    fn serialize_char(self, _v: char) -> Result<()> {
+        Err(not_a_map())
+    }
+
+    fn serialize_str(self, _v: &str) -> Result<()> {
+        Err(not_a_map())
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<()> {
+        Err(not_a_map())
+    }
+
+    fn serialize_none(self) -> Result<()> {
+        Err(not_a_map())
+    }
+
+    fn serialize_some<T>(self, _value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        Err(not_a_map())
+    }
+
+    fn serialize_unit(self) -> Result<()> {
+        Err(not_a_map())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<()> {
+        Err(not_a_map())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<()> {
+        Err(not_a_map())
+    }
+
+    fn serialize_newtype_struct<T>(self, _name: &'static str, _value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        Err(not_a_map())
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        Err(not_a_map())
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
+        Err(not_a_map())
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
+        Err(not_a_map())
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        Err(not_a_map())
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        Err(not_a_map())
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
+        Err(not_a_map())
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        Err(not_a_map())
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
 #[doc(hidden)]
 struct MapSerializer<'a, W: ?Sized> {
     serializer: Serializer<'a, W>,
     entries: Vec<(Vec<u8>, Vec<u8>)>,
     next_key: Option<Vec<u8>>,
+    /// Whether serde gave no length, as it does for structs with `#[serde(flatten)]` fields
+    /// and for iterators without an exact size.
+    unknown_len: bool,
+    /// For a map from `PreSorted`, whose entries are written as they come instead of being
+    /// buffered in `entries`.
+    pre_sorted: Option<PreSortedState>,
+}
+
+/// The progress of a map from `PreSorted`.
+struct PreSortedState {
+    reported: usize,
+    yielded: usize,
+    /// The bytes of the previous key, to check the order of the keys in debug builds.
+    previous_key: Option<Vec<u8>>,
 }
 
 impl<'a, W: ?Sized> MapSerializer<'a, W> {
-    fn new(serializer: Serializer<'a, W>) -> Self {
+    fn new(serializer: Serializer<'a, W>, unknown_len: bool) -> Self {
         MapSerializer {
             serializer,
             entries: Vec::new(),
             next_key: None,
+            unknown_len,
+            pre_sorted: None,
         }
     }
 }
 
+impl<'a, W> MapSerializer<'a, W>
+where
+    W: ?Sized + crate::io::Write,
+{
+    /// Writes the length of a map from `PreSorted` up front, since its entries are not
+    /// buffered.
+    fn pre_sorted(mut serializer: Serializer<'a, W>, len: usize) -> Result<Self> {
+        serializer.enter_sequence()?;
+        serializer.output_seq_len(len)?;
+        let mut map = Self::new(serializer, false);
+        map.pre_sorted = Some(PreSortedState {
+            reported: len,
+            yielded: 0,
+            previous_key: None,
+        });
+        Ok(map)
+    }
+}
+
 impl<'a, W> ser::SerializeMap for MapSerializer<'a, W>
 where
     W: ?Sized + crate::io::Write,
@@ -516,12 +1319,23 @@ where
         if self.next_key.is_some() {
             return Err(Error::ExpectedMapValue);
         }
+        // Structs with flattened fields pass their field names as `str`, while the keys of
+        // actual maps are passed by reference, e.g. as `&str` or `&String`.
+        if self.unknown_len && core::any::type_name::<T>() == "str" {
+            return Err(Error::FlattenUnsupported);
+        }
 
         let mut output = Vec::new();
-        key.serialize(Serializer::new(
-            &mut output,
-            self.serializer.max_remaining_depth,
-        ))?;
+        key.serialize(self.serializer.with_output(&mut output))?;
+        if let Some(state) = &mut self.pre_sorted {
+            if let Some(previous_key) = &state.previous_key {
+                debug_assert!(
+                    canonical_key_cmp(previous_key, &output).is_lt(),
+                    "keys of PreSorted entries must be unique and in increasing order"
+                );
+            }
+            self.serializer.output.write_all(&output)?;
+        }
         self.next_key = Some(output);
         Ok(())
     }
@@ -531,12 +1345,17 @@ where
         T: ?Sized + Serialize,
     {
         match self.next_key.take() {
+            Some(key) if self.pre_sorted.is_some() => {
+                value.serialize(self.serializer.reborrow())?;
+                if let Some(state) = &mut self.pre_sorted {
+                    state.yielded += 1;
+                    state.previous_key = Some(key);
+                }
+                Ok(())
+            }
             Some(key) => {
                 let mut output = Vec::new();
-                value.serialize(Serializer::new(
-                    &mut output,
-                    self.serializer.max_remaining_depth,
-                ))?;
+                value.serialize(self.serializer.with_output(&mut output))?;
                 self.entries.push((key, output));
                 Ok(())
             }
@@ -548,7 +1367,17 @@ where
         if self.next_key.is_some() {
             return Err(Error::ExpectedMapValue);
         }
-        self.entries.sort_by(|e1, e2| e1.0.cmp(&e2.0));
+        if let Some(state) = self.pre_sorted {
+            if state.yielded != state.reported {
+                return Err(Error::IteratorLengthMismatch {
+                    reported: state.reported,
+                    yielded: state.yielded,
+                });
+            }
+            return Ok(());
+        }
+        self.entries
+            .sort_by(|e1, e2| canonical_key_cmp(&e1.0, &e2.0));
         self.entries.dedup_by(|e1, e2| e1.0.eq(&e2.0));
 
         let len = self.entries.len();
@@ -570,11 +1399,14 @@ where
     type Ok = ();
     type Error = Error;
 
-    fn serialize_field<T>(&mut self, _key: &'static str, value: &T) -> Result<()>
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
     where
         T: ?Sized + Serialize,
     {
-        value.serialize(Serializer::new(self.output, self.max_remaining_depth))
+        if self.field_sizes.is_some() {
+            return self.serialize_field_with_size(key, value);
+        }
+        value.serialize(self.reborrow())
     }
 
     fn end(self) -> Result<()> {
@@ -593,7 +1425,7 @@ where
     where
         T: ?Sized + Serialize,
     {
-        value.serialize(Serializer::new(self.output, self.max_remaining_depth))
+        value.serialize(self.reborrow())
     }
 
     fn end(self) -> Result<()> {
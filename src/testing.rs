@@ -0,0 +1,110 @@
+// Copyright (c) The Diem Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Property tests of the BCS round-trip invariants, for use in the unit tests of downstream
+//! crates.
+//!
+//! [`roundtrip_test!`](crate::roundtrip_test) generates a test that encodes arbitrary values of
+//! a type and checks that:
+//!
+//! * decoding the encoding yields the original value,
+//! * re-encoding the decoded value reproduces the same bytes, i.e. the encoding is canonical,
+//! * [`serialized_size`](crate::serialized_size) matches the length of the encoding.
+//!
+//! Values are generated with [proptest](https://docs.rs/proptest), so the type must implement
+//! `proptest::arbitrary::Arbitrary`. This requires `proptest` (and usually `proptest-derive`,
+//! to derive the implementation) as a dev-dependency of the crate, next to `bcs` with its
+//! `testing` feature:
+//!
+//! ```toml
+//! [dev-dependencies]
+//! bcs = { version = "0.2", features = ["testing"] }
+//! proptest = "1.4"
+//! proptest-derive = "0.4"
+//! ```
+//!
+//! ```rust
+//! use proptest_derive::Arbitrary;
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Arbitrary, Debug, Deserialize, PartialEq, Serialize)]
+//! struct Transfer {
+//!     amount: u64,
+//!     memo: Option<String>,
+//! }
+//!
+//! // Generates `#[test] fn bcs_roundtrip()`, or `transfer_roundtrip` when named.
+//! bcs::roundtrip_test!(transfer_roundtrip: Transfer);
+//! ```
+//!
+//! The number of cases follows the proptest configuration, e.g. the `PROPTEST_CASES`
+//! environment variable.
+
+use core::fmt::Debug;
+use proptest::{
+    arbitrary::{any, Arbitrary},
+    test_runner::{Config, TestCaseError, TestRunner},
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Generates a `#[test]` checking the BCS round-trip invariants of a type on arbitrary values.
+///
+/// `roundtrip_test!(MyType)` generates a test named `bcs_roundtrip`, and
+/// `roundtrip_test!(my_name: MyType)` one named `my_name`, e.g. to test several types in the
+/// same module. The type must implement `Serialize`, `DeserializeOwned`, `PartialEq`, `Debug`
+/// and `proptest::arbitrary::Arbitrary`. See the [`testing`](crate::testing) module for the
+/// invariants and the required dev-dependencies.
+#[macro_export]
+macro_rules! roundtrip_test {
+    ($name:ident : $ty:ty) => {
+        #[test]
+        fn $name() {
+            $crate::testing::assert_roundtrip_property::<$ty>();
+        }
+    };
+    ($ty:ty) => {
+        $crate::roundtrip_test!(bcs_roundtrip: $ty);
+    };
+}
+
+/// Checks the BCS round-trip invariants of `T` on arbitrary values, panicking with a minimal
+/// failing value if one of them does not hold.
+///
+/// This is the body of the tests generated by [`roundtrip_test!`](crate::roundtrip_test).
+pub fn assert_roundtrip_property<T>()
+where
+    T: Arbitrary + Serialize + DeserializeOwned + PartialEq + Debug,
+{
+    // Without a source file to write next to, proptest cannot persist failures.
+    let mut runner = TestRunner::new(Config {
+        failure_persistence: None,
+        ..Config::default()
+    });
+    if let Err(err) = runner.run(&any::<T>(), |value| check_roundtrip(&value)) {
+        panic!("{}\n{}", err, runner);
+    }
+}
+
+fn check_roundtrip<T>(value: &T) -> Result<(), TestCaseError>
+where
+    T: Serialize + DeserializeOwned + PartialEq + Debug,
+{
+    let bytes = crate::to_bytes(value)
+        .map_err(|err| TestCaseError::fail(format!("cannot serialize {:?}: {}", value, err)))?;
+    // Panics, which the runner reports as failures, if the encoding is not canonical or does not
+    // match the serialized size.
+    crate::fuzz::check_roundtrip::<T>(&bytes).map_err(|err| {
+        TestCaseError::fail(format!(
+            "cannot decode {:?} from {:?}: {}",
+            value, bytes, err
+        ))
+    })?;
+    let decoded: T = crate::from_bytes(&bytes).expect("decoded by check_roundtrip");
+    if &decoded != value {
+        return Err(TestCaseError::fail(format!(
+            "{:?} decodes into {:?}",
+            value, decoded
+        )));
+    }
+    Ok(())
+}
@@ -15,7 +15,6 @@ use alloc::{
     borrow::Cow,
     boxed::Box,
     collections::{BTreeMap, BTreeSet},
-    format,
     string::String,
     vec,
     vec::Vec,
@@ -27,7 +26,9 @@ use proptest_derive::Arbitrary;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 use crate::{
-    from_bytes, from_bytes_with_limit, from_reader, serialized_size, to_bytes, to_bytes_with_limit,
+    from_byte_iter, from_bytes, from_bytes_const, from_bytes_in_place, from_bytes_with_config,
+    from_bytes_with_limit, from_reader, from_reader_in_place, from_reader_with_config,
+    serialized_size, to_bytes, to_bytes_with_capacity, to_bytes_with_limit, ByteBuf, Bytes, Config,
     Error, MAX_CONTAINER_DEPTH, MAX_SEQUENCE_LENGTH,
 };
 
@@ -279,10 +280,45 @@ proptest! {
 #[test]
 fn invalid_utf8() {
     let invalid_utf8 = vec![1, 0xFF];
-    assert_eq!(from_bytes::<String>(&invalid_utf8), Err(Error::Utf8));
+    assert_eq!(
+        from_bytes::<String>(&invalid_utf8),
+        Err(Error::InvalidUtf8 { offset: 0 })
+    );
     assert_eq!(
         from_bytes_via_reader::<String>(&invalid_utf8),
-        Err(Error::Utf8)
+        Err(Error::InvalidUtf8 { offset: 0 })
+    );
+
+    // An encoded surrogate is not a Unicode scalar value.
+    let surrogate = vec![5, b'a', b'b', 0xED, 0xA0, 0x80];
+    assert_eq!(
+        from_bytes::<String>(&surrogate),
+        Err(Error::InvalidUtf8 { offset: 2 })
+    );
+    assert_eq!(
+        from_bytes_via_reader::<String>(&surrogate),
+        Err(Error::InvalidUtf8 { offset: 2 })
+    );
+    assert_eq!(
+        from_bytes::<&str>(&surrogate),
+        Err(Error::InvalidUtf8 { offset: 2 })
+    );
+
+    // A declared length exceeding the input is reported as such.
+    let truncated = vec![5, b'a', b'b'];
+    assert_eq!(
+        from_bytes::<String>(&truncated),
+        Err(Error::Eof {
+            expected: 5,
+            available: 2
+        })
+    );
+    assert_eq!(
+        from_bytes_via_reader::<String>(&truncated),
+        Err(Error::Eof {
+            expected: 5,
+            available: 2
+        })
     );
 }
 
@@ -315,31 +351,46 @@ fn uleb_encoding_and_variant() {
 
     let invalid_bytes = vec![0x80, 0x80, 0x80, 0x80];
     // Error is due to EOF.
-    assert_eq!(from_bytes::<Test>(&invalid_bytes), Err(Error::Eof));
+    assert_eq!(
+        from_bytes::<Test>(&invalid_bytes),
+        Err(Error::Eof {
+            expected: 1,
+            available: 0
+        })
+    );
     assert_eq!(
         from_bytes_via_reader::<Test>(&invalid_bytes),
-        Err(Error::Eof)
+        Err(Error::Eof {
+            expected: 1,
+            available: 0
+        })
     );
 
     let invalid_uleb = vec![0x80, 0x80, 0x80, 0x80, 0x80];
-    // Error comes from uleb decoder because u32 are never that long.
+    // Error comes from uleb decoder because u32 are never that long, and mentions the enum.
     assert_eq!(
         from_bytes::<Test>(&invalid_uleb),
-        Err(Error::IntegerOverflowDuringUleb128Decoding)
+        Err(Error::MalformedVariantIndex { name: "Test" })
     );
     assert_eq!(
         from_bytes_via_reader::<Test>(&invalid_uleb),
-        Err(Error::IntegerOverflowDuringUleb128Decoding)
+        Err(Error::MalformedVariantIndex { name: "Test" })
     );
 
     let invalid_uleb = vec![0x80, 0x80, 0x80, 0x80, 0x1f];
     // Error comes from uleb decoder because we are truncating a larger integer into u32.
     assert_eq!(
         from_bytes::<Test>(&invalid_uleb),
-        Err(Error::IntegerOverflowDuringUleb128Decoding)
+        Err(Error::MalformedVariantIndex { name: "Test" })
     );
     assert_eq!(
         from_bytes_via_reader::<Test>(&invalid_uleb),
+        Err(Error::MalformedVariantIndex { name: "Test" })
+    );
+
+    // Lengths that overflow are still reported as such.
+    assert_eq!(
+        from_bytes::<Vec<Test>>(&invalid_uleb),
         Err(Error::IntegerOverflowDuringUleb128Decoding)
     );
 
@@ -375,11 +426,11 @@ fn invalid_option() {
     let invalid_option = vec![5, 0];
     assert_eq!(
         from_bytes::<Option<u8>>(&invalid_option),
-        Err(Error::ExpectedOption)
+        Err(Error::ExpectedOption(5))
     );
     assert_eq!(
         from_bytes_via_reader::<Option<u8>>(&invalid_option),
-        Err(Error::ExpectedOption)
+        Err(Error::ExpectedOption(5))
     );
 }
 
@@ -388,11 +439,11 @@ fn invalid_bool() {
     let invalid_bool = vec![9];
     assert_eq!(
         from_bytes::<bool>(&invalid_bool),
-        Err(Error::ExpectedBoolean)
+        Err(Error::ExpectedBoolean(9))
     );
     assert_eq!(
         from_bytes_via_reader::<bool>(&invalid_bool),
-        Err(Error::ExpectedBoolean)
+        Err(Error::ExpectedBoolean(9))
     );
 }
 
@@ -400,7 +451,10 @@ fn invalid_bool() {
 fn sequence_too_long() {
     let seq = vec![0; MAX_SEQUENCE_LENGTH + 1];
     match to_bytes(&seq).unwrap_err() {
-        Error::ExceededMaxLen(len) => assert_eq!(len, MAX_SEQUENCE_LENGTH + 1),
+        Error::ExceededMaxLen { len, max } => {
+            assert_eq!(len, MAX_SEQUENCE_LENGTH + 1);
+            assert_eq!(max, MAX_SEQUENCE_LENGTH);
+        }
         _ => panic!(),
     }
 }
@@ -419,8 +473,20 @@ fn variable_lengths() {
 #[test]
 fn sequence_not_long_enough() {
     let seq = vec![5, 1, 2, 3, 4]; // Missing 5th element
-    assert_eq!(from_bytes::<Vec<u8>>(&seq), Err(Error::Eof));
-    assert_eq!(from_bytes_via_reader::<Vec<u8>>(&seq), Err(Error::Eof));
+    assert_eq!(
+        from_bytes::<Vec<u8>>(&seq),
+        Err(Error::Eof {
+            expected: 4,
+            available: 3
+        })
+    );
+    assert_eq!(
+        from_bytes_via_reader::<Vec<u8>>(&seq),
+        Err(Error::Eof {
+            expected: 1,
+            available: 0
+        })
+    );
 }
 
 #[test]
@@ -435,21 +501,33 @@ fn map_not_canonical() {
     let seq = vec![2, 5, 4];
     assert_eq!(
         from_bytes::<BTreeMap<u8, ()>>(&seq),
-        Err(Error::NonCanonicalMap)
+        Err(Error::NonCanonicalMap {
+            index: 1,
+            offset: 2
+        })
     );
     assert_eq!(
         from_bytes_via_reader::<BTreeMap<u8, ()>>(&seq),
-        Err(Error::NonCanonicalMap)
+        Err(Error::NonCanonicalMap {
+            index: 1,
+            offset: 2
+        })
     );
     // Make sure duplicate keys are rejected.
     let seq = vec![2, 5, 5];
     assert_eq!(
         from_bytes::<BTreeMap<u8, ()>>(&seq),
-        Err(Error::NonCanonicalMap)
+        Err(Error::NonCanonicalMap {
+            index: 1,
+            offset: 2
+        })
     );
     assert_eq!(
         from_bytes_via_reader::<BTreeMap<u8, ()>>(&seq),
-        Err(Error::NonCanonicalMap)
+        Err(Error::NonCanonicalMap {
+            index: 1,
+            offset: 2
+        })
     );
 }
 
@@ -475,10 +553,13 @@ fn by_default_btreesets_are_serialized_as_sequences() {
 #[test]
 fn leftover_bytes() {
     let seq = vec![5, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10]; // 5 extra elements
-    assert_eq!(from_bytes::<Vec<u8>>(&seq), Err(Error::RemainingInput));
+    assert_eq!(
+        from_bytes::<Vec<u8>>(&seq),
+        Err(Error::RemainingInput { len: 5, first: 6 })
+    );
     assert_eq!(
         from_bytes_via_reader::<Vec<u8>>(&seq),
-        Err(Error::RemainingInput)
+        Err(Error::RemainingInput { len: 1, first: 6 })
     );
 }
 
@@ -737,17 +818,26 @@ fn test_recursion_limit() {
     let l3 = List::integers(MAX_CONTAINER_DEPTH);
     assert_eq!(
         to_bytes(&l3),
-        Err(Error::ExceededContainerDepthLimit("List"))
+        Err(Error::ExceededContainerDepthLimit {
+            name: "List",
+            limit: MAX_CONTAINER_DEPTH
+        })
     );
     let mut b3 = vec![244, 1, 0, 0, 0, 0, 0, 0, 1];
     b3.extend(b2);
     assert_eq!(
         from_bytes::<List<usize>>(&b3),
-        Err(Error::ExceededContainerDepthLimit("List"))
+        Err(Error::ExceededContainerDepthLimit {
+            name: "List",
+            limit: MAX_CONTAINER_DEPTH
+        })
     );
     assert_eq!(
         from_bytes_via_reader::<List<usize>>(&b3),
-        Err(Error::ExceededContainerDepthLimit("List"))
+        Err(Error::ExceededContainerDepthLimit {
+            name: "List",
+            limit: MAX_CONTAINER_DEPTH
+        })
     );
 
     let b2_pair = to_bytes(&(&l2, &l2)).unwrap();
@@ -761,15 +851,24 @@ fn test_recursion_limit() {
     );
     assert_eq!(
         to_bytes(&(&l2, &l3)),
-        Err(Error::ExceededContainerDepthLimit("List"))
+        Err(Error::ExceededContainerDepthLimit {
+            name: "List",
+            limit: MAX_CONTAINER_DEPTH
+        })
     );
     assert_eq!(
         to_bytes(&(&l3, &l2)),
-        Err(Error::ExceededContainerDepthLimit("List"))
+        Err(Error::ExceededContainerDepthLimit {
+            name: "List",
+            limit: MAX_CONTAINER_DEPTH
+        })
     );
     assert_eq!(
         to_bytes(&(&l3, &l3)),
-        Err(Error::ExceededContainerDepthLimit("List"))
+        Err(Error::ExceededContainerDepthLimit {
+            name: "List",
+            limit: MAX_CONTAINER_DEPTH
+        })
     );
 
     // test customized limit
@@ -778,7 +877,10 @@ fn test_recursion_limit() {
     let l4 = List::integers(limit);
     assert_eq!(
         to_bytes_with_limit(&l4, limit),
-        Err(Error::ExceededContainerDepthLimit("List"))
+        Err(Error::ExceededContainerDepthLimit {
+            name: "List",
+            limit
+        })
     );
     assert_eq!(
         to_bytes_with_limit(&l4, MAX_CONTAINER_DEPTH + 1),
@@ -787,7 +889,10 @@ fn test_recursion_limit() {
     let bytes = to_bytes_with_limit(&l4, limit + 1).unwrap();
     assert_eq!(
         from_bytes_with_limit::<List<usize>>(&bytes, limit),
-        Err(Error::ExceededContainerDepthLimit("List"))
+        Err(Error::ExceededContainerDepthLimit {
+            name: "List",
+            limit
+        })
     );
     assert_eq!(from_bytes_with_limit(&bytes, limit + 1), Ok(l4));
     assert_eq!(
@@ -817,17 +922,26 @@ fn test_recursion_limit_enum() {
     let l3 = List::repeat(MAX_CONTAINER_DEPTH - 1, EnumA::ValueA);
     assert_eq!(
         to_bytes(&l3),
-        Err(Error::ExceededContainerDepthLimit("EnumA"))
+        Err(Error::ExceededContainerDepthLimit {
+            name: "EnumA",
+            limit: MAX_CONTAINER_DEPTH
+        })
     );
     let mut b3 = vec![0, 1];
     b3.extend(b2);
     assert_eq!(
         from_bytes::<List<EnumA>>(&b3),
-        Err(Error::ExceededContainerDepthLimit("EnumA"))
+        Err(Error::ExceededContainerDepthLimit {
+            name: "EnumA",
+            limit: MAX_CONTAINER_DEPTH
+        })
     );
     assert_eq!(
         from_bytes_via_reader::<List<EnumA>>(&b3),
-        Err(Error::ExceededContainerDepthLimit("EnumA"))
+        Err(Error::ExceededContainerDepthLimit {
+            name: "EnumA",
+            limit: MAX_CONTAINER_DEPTH
+        })
     );
 }
 
@@ -861,3 +975,4600 @@ fn test_triple_nested_map() {
     assert_eq!(from_bytes(&bytes2).as_ref(), Ok(&top_level2));
     assert_eq!(from_bytes_via_reader(&bytes2), Ok(top_level2));
 }
+
+#[test]
+fn limit_errors_report_the_configured_maximum() {
+    let depth_err = to_bytes_with_limit(&List::integers(3), 2).unwrap_err();
+    assert_eq!(
+        depth_err,
+        Error::ExceededContainerDepthLimit {
+            name: "List",
+            limit: 2
+        }
+    );
+    assert_eq!(
+        depth_err.to_string(),
+        "exceeded max container depth 2 while entering: List"
+    );
+
+    let len_err = Error::ExceededMaxLen {
+        len: MAX_SEQUENCE_LENGTH + 1,
+        max: MAX_SEQUENCE_LENGTH,
+    };
+    assert_eq!(
+        len_err.to_string(),
+        "exceeded max sequence length: 2147483648 (max: 2147483647)"
+    );
+}
+
+#[test]
+fn deserialize_in_place_reuses_allocations() {
+    let mut place: Vec<String> = vec![String::with_capacity(32), String::with_capacity(32)];
+    let outer_ptr = place.as_ptr();
+    let inner_ptr = place[0].as_ptr();
+
+    let bytes = to_bytes(&vec!["abc".to_owned(), "de".to_owned()]).unwrap();
+    from_bytes_in_place(&bytes, &mut place).unwrap();
+    assert_eq!(place, vec!["abc".to_owned(), "de".to_owned()]);
+    assert_eq!(place.as_ptr(), outer_ptr);
+    assert_eq!(place[0].as_ptr(), inner_ptr);
+
+    // Shorter input truncates the target.
+    let bytes = to_bytes(&vec!["f".to_owned()]).unwrap();
+    from_bytes_in_place(&bytes, &mut place).unwrap();
+    assert_eq!(place, vec!["f".to_owned()]);
+    assert_eq!(place[0].as_ptr(), inner_ptr);
+
+    // Longer input extends it.
+    let bytes = to_bytes(&vec!["g".to_owned(), "h".to_owned(), "i".to_owned()]).unwrap();
+    let reader = crate::io::Cursor::new(bytes.clone());
+    from_reader_in_place(reader, &mut place).unwrap();
+    assert_eq!(place, vec!["g".to_owned(), "h".to_owned(), "i".to_owned()]);
+
+    let mut trailing = bytes;
+    trailing.push(0);
+    assert_eq!(
+        from_bytes_in_place(&trailing, &mut place),
+        Err(Error::RemainingInput { len: 1, first: 0 })
+    );
+}
+
+proptest! {
+    #[test]
+    fn proptest_uleb128_encoded_len(v in any::<u32>()) {
+        // A unit variant is encoded as just its ULEB128 variant index.
+        struct VariantIndex(u32);
+
+        impl Serialize for VariantIndex {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_unit_variant("VariantIndex", self.0, "")
+            }
+        }
+
+        assert_eq!(crate::uleb128::encoded_len(v), serialized_size(&VariantIndex(v))?);
+    }
+
+    #[test]
+    fn proptest_u256(v in any::<[u8; 32]>()) {
+        let value = crate::int::U256::from_le_bytes(v);
+        assert_eq!(to_bytes(&value)?, v);
+        is_same(value);
+    }
+
+    #[test]
+    fn proptest_u512(lo in any::<[u8; 32]>(), hi in any::<[u8; 32]>()) {
+        let mut le_bytes = [0u8; 64];
+        le_bytes[..32].copy_from_slice(&lo);
+        le_bytes[32..].copy_from_slice(&hi);
+        let value = crate::int::U512::from_le_bytes(le_bytes);
+        assert_eq!(to_bytes(&value)?, le_bytes.to_vec());
+        assert_eq!(value.to_le_bytes(), le_bytes);
+        is_same(value);
+    }
+
+    #[test]
+    fn proptest_u256_from_u128(v in any::<u128>()) {
+        let value = crate::int::U256::from(v);
+        let mut expected = v.to_le_bytes().to_vec();
+        expected.resize(32, 0);
+        assert_eq!(to_bytes(&value)?, expected);
+    }
+}
+
+#[test]
+fn wide_integer_truncated_input() {
+    assert_eq!(
+        from_bytes::<crate::int::U256>(&[0u8; 31]),
+        Err(Error::Eof {
+            expected: 1,
+            available: 0
+        })
+    );
+    assert_eq!(
+        from_bytes::<crate::int::U512>(&[0u8; 65]),
+        Err(Error::RemainingInput { len: 1, first: 0 })
+    );
+}
+
+#[test]
+fn pre_sorted_map() {
+    let mut map = BTreeMap::new();
+    for key in 0u8..200 {
+        map.insert(key, vec![key; key as usize % 3]);
+    }
+    let expected = to_bytes(&map).unwrap();
+    assert_eq!(to_bytes(&crate::PreSorted(&map)).unwrap(), expected);
+    assert_eq!(
+        serialized_size(&crate::PreSorted(&map)).unwrap(),
+        expected.len()
+    );
+
+    let entries: Vec<(u8, Vec<u8>)> = map.clone().into_iter().collect();
+    assert_eq!(to_bytes(&crate::PreSorted(entries)).unwrap(), expected);
+    assert_eq!(from_bytes::<BTreeMap<u8, Vec<u8>>>(&expected).unwrap(), map);
+
+    let empty: BTreeMap<u8, u8> = BTreeMap::new();
+    assert_eq!(to_bytes(&crate::PreSorted(&empty)).unwrap(), vec![0]);
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "keys of PreSorted entries must be unique and in increasing order")]
+fn pre_sorted_map_out_of_order() {
+    // `256u32` is encoded as [0, 1, 0, 0], which sorts before [1, 0, 0, 0].
+    let mut map = BTreeMap::new();
+    map.insert(1u32, ());
+    map.insert(256u32, ());
+    let _ = to_bytes(&crate::PreSorted(&map));
+}
+
+#[test]
+fn pre_sorted_map_checks_the_reported_length() {
+    use test_helpers::WrongLen;
+
+    for (len, yielded) in [(3, 2), (1, 2)] {
+        let entries = WrongLen {
+            items: (0u8..yielded).map(|key| (key, ())),
+            len,
+        };
+        assert_eq!(
+            to_bytes(&crate::PreSorted(entries)),
+            Err(Error::IteratorLengthMismatch {
+                reported: len,
+                yielded: yielded.into(),
+            })
+        );
+    }
+}
+
+#[test]
+fn serialize_seq_iter_matches_vec() {
+    let strings = vec!["a".to_owned(), "bc".to_owned(), String::new()];
+    let mut output = Vec::new();
+    crate::serialize_seq_iter(&mut output, strings.iter()).unwrap();
+    assert_eq!(output, to_bytes(&strings).unwrap());
+
+    let mut output = Vec::new();
+    crate::serialize_seq_iter(&mut output, (0u32..300).map(|i| (i, i % 7 == 0))).unwrap();
+    let expected: Vec<(u32, bool)> = (0u32..300).map(|i| (i, i % 7 == 0)).collect();
+    assert_eq!(output, to_bytes(&expected).unwrap());
+
+    let mut output = Vec::new();
+    crate::serialize_seq_iter(&mut output, core::iter::empty::<u8>()).unwrap();
+    assert_eq!(output, vec![0]);
+
+    for (len, yielded) in [(3, 2), (1, 2), (0, 1)] {
+        let items = test_helpers::WrongLen {
+            items: 0u8..yielded,
+            len,
+        };
+        assert_eq!(
+            crate::serialize_seq_iter(&mut Vec::new(), items),
+            Err(Error::IteratorLengthMismatch {
+                reported: len,
+                yielded: yielded.into(),
+            })
+        );
+    }
+}
+
+#[test]
+fn from_reader_counted_reports_consumed_bytes() {
+    let value = (S {
+        int: 7,
+        option: Some(3),
+        seq: vec!["x".to_owned(); 200],
+        boolean: true,
+    },);
+    let mut bytes = to_bytes(&value).unwrap();
+    let value_len = bytes.len();
+    bytes.extend_from_slice(&[0xff; 4]);
+
+    let reader = crate::io::Cursor::new(bytes);
+    let (decoded, len): ((S,), usize) = crate::from_reader_counted(reader).unwrap();
+    assert_eq!(decoded, value);
+    assert_eq!(len, value_len);
+
+    let reader = crate::io::Cursor::new(vec![1, 2]);
+    assert_eq!(
+        crate::from_reader_counted::<u32>(reader),
+        Err(Error::Eof {
+            expected: 4,
+            available: 2
+        })
+    );
+}
+
+#[test]
+fn peek_length_prefix() {
+    let bytes = to_bytes(&"hello world").unwrap();
+    assert_eq!(crate::peek_length(&bytes), Ok((11, &b"hello world"[..])));
+
+    let mut map = BTreeMap::new();
+    map.insert(1u8, 2u8);
+    map.insert(3u8, 4u8);
+    let bytes = to_bytes(&map).unwrap();
+    assert_eq!(crate::peek_length(&bytes), Ok((2, &[1, 2, 3, 4][..])));
+
+    assert_eq!(
+        crate::peek_length(&[]),
+        Err(Error::Eof {
+            expected: 1,
+            available: 0
+        })
+    );
+    assert_eq!(
+        crate::peek_length(&[0x80, 0x00]),
+        Err(Error::NonCanonicalUleb128Encoding)
+    );
+    assert_eq!(
+        crate::peek_length(&[0x80, 0x80, 0x80, 0x80, 0x08]),
+        Err(Error::ExceededMaxLen {
+            len: 1 << 31,
+            max: MAX_SEQUENCE_LENGTH
+        })
+    );
+}
+
+#[test]
+fn skip_value_by_layout() {
+    use crate::Layout;
+
+    #[derive(Serialize)]
+    struct Inner {
+        id: u64,
+        tags: Option<Vec<String>>,
+    }
+
+    #[derive(Serialize)]
+    struct Record {
+        flag: bool,
+        index: BTreeMap<String, Vec<Inner>>,
+        kind: E,
+        last: u32,
+    }
+
+    let mut index = BTreeMap::new();
+    index.insert(
+        "a".to_owned(),
+        vec![
+            Inner { id: 1, tags: None },
+            Inner {
+                id: 2,
+                tags: Some(vec!["x".to_owned()]),
+            },
+        ],
+    );
+    index.insert("b".to_owned(), vec![]);
+    let record = Record {
+        flag: true,
+        index,
+        kind: E::Struct { a: 7 },
+        last: 0xdead_beef,
+    };
+    let bytes = to_bytes(&record).unwrap();
+
+    let inner = Layout::Struct(vec![
+        Layout::U64,
+        Layout::Option(Box::new(Layout::Seq(Box::new(Layout::Str)))),
+    ]);
+    let map = Layout::Map(
+        Box::new(Layout::Str),
+        Box::new(Layout::Seq(Box::new(inner))),
+    );
+    let e = Layout::Enum(vec![
+        Layout::Unit,
+        Layout::U16,
+        Layout::Tuple(vec![Layout::U16, Layout::U16]),
+        Layout::Tuple(vec![Layout::U32]),
+    ]);
+
+    let rest = crate::skip_value(&bytes[1..], &map).unwrap();
+    let rest = crate::skip_value(rest, &e).unwrap();
+    assert_eq!(rest, &0xdead_beefu32.to_le_bytes());
+
+    let record_layout = Layout::Struct(vec![Layout::Bool, map.clone(), e.clone(), Layout::U32]);
+    assert_eq!(crate::skip_value(&bytes, &record_layout), Ok(&[][..]));
+
+    // Structural errors are still reported.
+    assert_eq!(
+        crate::skip_value(&bytes[..bytes.len() - 1], &record_layout),
+        Err(Error::Eof {
+            expected: 4,
+            available: 3
+        })
+    );
+    assert_eq!(
+        crate::skip_value(&[2], &Layout::Bool),
+        Err(Error::ExpectedBoolean(2))
+    );
+    assert_eq!(
+        crate::skip_value(&[2, 0], &Layout::Option(Box::new(Layout::U8))),
+        Err(Error::ExpectedOption(2))
+    );
+    assert_eq!(
+        crate::skip_value(
+            &[2, 3, 0, 1, 0],
+            &Layout::Map(Box::new(Layout::U8), Box::new(Layout::U8))
+        ),
+        Err(Error::NonCanonicalMap {
+            index: 1,
+            offset: 3
+        })
+    );
+    assert!(crate::skip_value(&[4], &e).is_err());
+    assert_eq!(
+        crate::skip_value(&[1, 0xff], &Layout::Str),
+        Err(Error::InvalidUtf8 { offset: 0 })
+    );
+}
+
+#[test]
+fn ignored_any_is_not_supported() {
+    let err = from_bytes::<serde::de::IgnoredAny>(&[0]).unwrap_err();
+    assert!(matches!(err, Error::NotSupported(msg) if msg.contains("bcs::Skip")));
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn net_addresses() {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Addresses {
+        #[serde(with = "crate::net::ipv4_addr")]
+        v4: Ipv4Addr,
+        #[serde(with = "crate::net::ipv6_addr")]
+        v6: Ipv6Addr,
+        #[serde(with = "crate::net::ip_addr")]
+        ip: IpAddr,
+        #[serde(with = "crate::net::socket_addr")]
+        socket: SocketAddr,
+    }
+
+    let v6 = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1);
+    let addresses = Addresses {
+        v4: Ipv4Addr::new(192, 168, 1, 1),
+        v6,
+        ip: IpAddr::V6(v6),
+        socket: SocketAddr::from((v6, 443)),
+    };
+    let bytes = to_bytes(&addresses).unwrap();
+
+    let mut expected = vec![192, 168, 1, 1];
+    expected.extend_from_slice(&v6.octets());
+    expected.push(1);
+    expected.extend_from_slice(&v6.octets());
+    expected.push(1);
+    expected.extend_from_slice(&v6.octets());
+    expected.extend_from_slice(&443u16.to_le_bytes());
+    assert_eq!(bytes, expected);
+    is_same(addresses);
+
+    let v4 = Addresses {
+        v4: Ipv4Addr::LOCALHOST,
+        v6: Ipv6Addr::LOCALHOST,
+        ip: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        socket: SocketAddr::from((Ipv4Addr::LOCALHOST, 8001)),
+    };
+    let bytes = to_bytes(&v4).unwrap();
+    assert_eq!(bytes[20..25], [0, 0, 0, 0, 0]);
+    assert_eq!(bytes[25..], [0, 127, 0, 0, 1, 0x41, 0x1f]);
+    is_same(v4);
+
+    // Unknown address families are rejected.
+    let mut invalid = bytes;
+    invalid[20] = 2;
+    assert!(from_bytes::<Addresses>(&invalid).is_err());
+}
+
+#[test]
+fn time_encodings() {
+    use core::time::Duration;
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Timeout(#[serde(with = "crate::time::duration")] Duration);
+
+    for duration in [
+        Duration::ZERO,
+        Duration::new(0, 999_999_999),
+        Duration::new(1, 0),
+        Duration::new(1_700_000_000, 123_456_789),
+        Duration::MAX,
+    ] {
+        let bytes = to_bytes(&Timeout(duration)).unwrap();
+        assert_eq!(
+            bytes,
+            to_bytes(&(duration.as_secs(), duration.subsec_nanos())).unwrap()
+        );
+        is_same(Timeout(duration));
+    }
+
+    // Only canonical nanoseconds are accepted.
+    let non_canonical = to_bytes(&(0u64, 1_000_000_000u32)).unwrap();
+    assert!(from_bytes::<Timeout>(&non_canonical).is_err());
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn system_time_encoding() {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Timestamp(#[serde(with = "crate::time::system_time")] SystemTime);
+
+    for since_epoch in [
+        Duration::ZERO,
+        Duration::new(1_700_000_000, 999_999_999),
+        Duration::new(1_700_000_001, 0),
+    ] {
+        let time = UNIX_EPOCH + since_epoch;
+        let bytes = to_bytes(&Timestamp(time)).unwrap();
+        assert_eq!(
+            bytes,
+            to_bytes(&(since_epoch.as_secs(), since_epoch.subsec_nanos())).unwrap()
+        );
+        is_same(Timestamp(time));
+    }
+    is_same(Timestamp(SystemTime::now()));
+
+    let before_epoch = UNIX_EPOCH - Duration::from_secs(1);
+    assert_eq!(
+        to_bytes(&Timestamp(before_epoch)),
+        Err(Error::Custom("SystemTime is before the Unix epoch".into()))
+    );
+}
+
+#[test]
+#[cfg(feature = "mmap")]
+fn zero_copy_from_mmap() {
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Dataset<'a> {
+        name: &'a str,
+        payload: &'a [u8],
+    }
+
+    let bytes = to_bytes(&Dataset {
+        name: "mapped",
+        payload: &[1, 2, 3],
+    })
+    .unwrap();
+    let mut mmap = memmap2::MmapMut::map_anon(bytes.len()).unwrap();
+    mmap.copy_from_slice(&bytes);
+    let mmap = mmap.make_read_only().unwrap();
+
+    let dataset: Dataset = crate::from_mmap(&mmap).unwrap();
+    assert_eq!(dataset.name, "mapped");
+    assert_eq!(dataset.payload, &[1, 2, 3]);
+    assert!(mmap.as_ptr_range().contains(&dataset.name.as_ptr()));
+}
+
+#[test]
+#[cfg(feature = "arbitrary")]
+fn arbitrary_bytes_are_valid() {
+    type T = (
+        BTreeMap<String, Vec<Option<u32>>>,
+        Result<(u8, String), Option<bool>>,
+        [i64; 3],
+    );
+
+    let data: Vec<u8> = (0..4096u32)
+        .map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8)
+        .collect();
+    let mut u = arbitrary::Unstructured::new(&data);
+    while !u.is_empty() {
+        let bytes = crate::fuzz::arbitrary_bytes::<T>(&mut u).unwrap();
+        let value: T = from_bytes(&bytes).unwrap();
+        assert_eq!(to_bytes(&value).unwrap(), bytes);
+    }
+}
+
+#[test]
+#[cfg(feature = "fuzz")]
+fn check_roundtrip_invariants() {
+    use crate::fuzz::check_roundtrip;
+
+    type T = (BTreeMap<u16, String>, Vec<E>);
+
+    let mut map = BTreeMap::new();
+    map.insert(1u16, "one".to_owned());
+    map.insert(256u16, "two hundred fifty-six".to_owned());
+    let value: T = (map, vec![E::Unit, E::Struct { a: 3 }]);
+    check_roundtrip::<T>(&to_bytes(&value).unwrap()).unwrap();
+
+    // Maps must be sorted by key bytes: 1u16 is [1, 0], 256u16 is [0, 1].
+    assert_eq!(
+        check_roundtrip::<BTreeMap<u16, u8>>(&[2, 1, 0, 7, 0, 1, 8]),
+        Err(Error::NonCanonicalMap {
+            index: 1,
+            offset: 4
+        })
+    );
+    // ULEB128 must be minimal, for lengths and variant indices alike.
+    assert_eq!(
+        check_roundtrip::<Vec<u8>>(&[0x81, 0x00, 7]),
+        Err(Error::NonCanonicalUleb128Encoding)
+    );
+    assert_eq!(
+        check_roundtrip::<E>(&[0x80, 0x00]),
+        Err(Error::NonCanonicalUleb128Encoding)
+    );
+    // Invalid input is not a failure of the invariant.
+    assert_eq!(
+        check_roundtrip::<bool>(&[2]),
+        Err(Error::ExpectedBoolean(2))
+    );
+}
+
+#[test]
+#[cfg(feature = "fuzz")]
+#[should_panic(expected = "does not reproduce the decoded bytes")]
+fn check_roundtrip_detects_non_canonical_types() {
+    // Decodes any byte but always encodes `0`.
+    #[derive(Debug, PartialEq)]
+    struct Lossy;
+
+    impl Serialize for Lossy {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_u8(0)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Lossy {
+        fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            u8::deserialize(deserializer).map(|_| Lossy)
+        }
+    }
+
+    let _ = crate::fuzz::check_roundtrip::<Lossy>(&[1]);
+}
+
+#[test]
+fn from_slice_advancing_moves_cursor() {
+    let mut bytes = to_bytes(&(E::Newtype(5), "abc")).unwrap();
+    bytes.extend(to_bytes(&Some(7u64)).unwrap());
+    let mut input = &bytes[..];
+
+    let value: (E, &str) = crate::from_slice_advancing(&mut input).unwrap();
+    assert_eq!(value, (E::Newtype(5), "abc"));
+    assert_eq!(input, &to_bytes(&Some(7u64)).unwrap()[..]);
+
+    // A failed read leaves the cursor in place.
+    let before = input;
+    assert_eq!(
+        crate::from_slice_advancing::<(Option<u64>, u8)>(&mut input),
+        Err(Error::Eof {
+            expected: 1,
+            available: 0
+        })
+    );
+    assert_eq!(input, before);
+
+    assert_eq!(
+        crate::from_slice_advancing::<Option<u64>>(&mut input),
+        Ok(Some(7))
+    );
+    assert!(input.is_empty());
+}
+
+#[test]
+fn map_entries_with_key_bytes() {
+    let mut map = BTreeMap::new();
+    map.insert(1u32, vec![1u8]);
+    map.insert(256u32, vec![]);
+    map.insert(u32::MAX, vec![2, 3]);
+    let bytes = to_bytes(&map).unwrap();
+
+    let mut entries = Vec::new();
+    crate::from_bytes_map_entries(&bytes, |key_bytes: &[u8], key: u32, value: Vec<u8>| {
+        assert_eq!(key_bytes, &key.to_le_bytes());
+        // Key bytes are borrowed from the input.
+        assert!(bytes.as_ptr_range().contains(&key_bytes.as_ptr()));
+        entries.push((key, value));
+    })
+    .unwrap();
+    // Entries come in canonical (key bytes) order.
+    assert_eq!(
+        entries,
+        vec![(256, vec![]), (1, vec![1]), (u32::MAX, vec![2, 3])]
+    );
+
+    let unsorted = vec![2, 1, 0, 0, 0, 7, 0, 1, 0, 0, 8];
+    assert_eq!(
+        crate::from_bytes_map_entries(&unsorted, |_, _: u32, _: u8| {}),
+        Err(Error::NonCanonicalMap {
+            index: 1,
+            offset: 6
+        })
+    );
+    let mut trailing = bytes;
+    trailing.push(0);
+    assert_eq!(
+        crate::from_bytes_map_entries(&trailing, |_, _: u32, _: Vec<u8>| {}),
+        Err(Error::RemainingInput { len: 1, first: 0 })
+    );
+}
+
+#[test]
+#[cfg(feature = "digest")]
+fn hash_into_feeds_canonical_bytes() {
+    #[derive(Default)]
+    struct Recorder {
+        bytes: Vec<u8>,
+        updates: usize,
+    }
+
+    impl digest::Update for Recorder {
+        fn update(&mut self, data: &[u8]) {
+            self.bytes.extend_from_slice(data);
+            self.updates += 1;
+        }
+    }
+
+    let mut map = BTreeMap::new();
+    map.insert(3u16, E::Tuple(1, 2));
+    map.insert(1u16, E::Unit);
+    let value = (vec!["x".to_owned(); 3], map, Some(true));
+
+    let mut recorder = Recorder::default();
+    crate::hash_into(&mut recorder, &value).unwrap();
+    assert_eq!(recorder.bytes, to_bytes(&value).unwrap());
+    assert!(recorder.updates > 1);
+
+    let mut recorder = Recorder::default();
+    assert_eq!(
+        crate::hash_into(&mut recorder, &1.0f32),
+        Err(Error::NotSupported("serialize_f32"))
+    );
+}
+
+#[test]
+fn serialized_size_rejects_what_to_bytes_rejects() {
+    #[derive(Serialize)]
+    struct WithFloat {
+        id: u32,
+        ratio: f64,
+    }
+
+    #[derive(Serialize)]
+    struct WithChar(Vec<u8>, char);
+
+    let float = WithFloat { id: 1, ratio: 0.5 };
+    assert_eq!(to_bytes(&float), Err(Error::NotSupported("serialize_f64")));
+    assert_eq!(
+        serialized_size(&float),
+        Err(Error::NotSupported("serialize_f64"))
+    );
+
+    let char = WithChar(vec![1, 2], 'c');
+    assert_eq!(to_bytes(&char), Err(Error::NotSupported("serialize_char")));
+    assert_eq!(
+        serialized_size(&char),
+        Err(Error::NotSupported("serialize_char"))
+    );
+
+    // Errors raised inside map entries are propagated too.
+    let mut map = BTreeMap::new();
+    map.insert(1u8, 1.5f32);
+    assert_eq!(to_bytes(&map), Err(Error::NotSupported("serialize_f32")));
+    assert_eq!(
+        serialized_size(&map),
+        Err(Error::NotSupported("serialize_f32"))
+    );
+
+    let deep = List::integers(MAX_CONTAINER_DEPTH);
+    assert_eq!(serialized_size(&deep), to_bytes(&deep).map(|b| b.len()));
+    assert!(serialized_size(&deep).is_err());
+    assert_eq!(
+        crate::serialized_size_with_limit(&List::integers(10), 5),
+        to_bytes_with_limit(&List::integers(10), 5).map(|b| b.len())
+    );
+}
+
+#[test]
+fn reader_returning_zero_is_eof() {
+    /// Claims to be exhausted after every other read, even though data remains.
+    struct Stuttering {
+        data: Vec<u8>,
+        pos: usize,
+        reads: usize,
+    }
+
+    impl crate::io::Read for Stuttering {
+        fn read(&mut self, buf: &mut [u8]) -> crate::io::Result<usize> {
+            self.reads += 1;
+            assert!(self.reads < 100, "reader is being polled in a loop");
+            if self.reads % 2 == 0 {
+                return Ok(0);
+            }
+            let n = core::cmp::min(1, self.data.len() - self.pos);
+            buf[..n].copy_from_slice(&self.data[self.pos..self.pos + n]);
+            self.pos += n;
+            Ok(n)
+        }
+    }
+
+    let reader = Stuttering {
+        data: to_bytes(&0x0102_0304u32).unwrap(),
+        pos: 0,
+        reads: 0,
+    };
+    assert_eq!(
+        from_reader::<u32>(reader),
+        Err(Error::Eof {
+            expected: 4,
+            available: 1
+        })
+    );
+}
+
+#[test]
+fn bulk_integer_arrays() {
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Arrays {
+        #[serde(with = "crate::array")]
+        bytes: [u8; 3],
+        #[serde(with = "crate::array")]
+        signed: [i16; 4],
+        #[serde(with = "crate::array")]
+        large: [u64; 64],
+        #[serde(with = "crate::array")]
+        wide: [u128; 2],
+        #[serde(with = "crate::array")]
+        empty: [u32; 0],
+    }
+
+    let mut large = [0u64; 64];
+    for (i, x) in large.iter_mut().enumerate() {
+        *x = (i as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    }
+    let arrays = Arrays {
+        bytes: [1, 2, 3],
+        signed: [-1, 2, i16::MIN, i16::MAX],
+        large,
+        wide: [u128::MAX, 1],
+        empty: [],
+    };
+    let bytes = to_bytes(&arrays).unwrap();
+
+    // Same encoding as a plain tuple of the elements.
+    let expected = to_bytes(&(
+        [1u8, 2, 3],
+        [-1i16, 2, i16::MIN, i16::MAX],
+        large.to_vec(),
+        [u128::MAX, 1],
+    ))
+    .unwrap();
+    let mut expected_without_len = expected[..11].to_vec();
+    expected_without_len.extend_from_slice(&expected[12..]);
+    assert_eq!(bytes, expected_without_len);
+    is_same(arrays);
+
+    assert_eq!(
+        from_bytes::<Arrays>(&bytes[..bytes.len() - 1]),
+        Err(Error::Eof {
+            expected: 32,
+            available: 31
+        })
+    );
+    assert_eq!(
+        from_bytes_via_reader::<Arrays>(&bytes[..bytes.len() - 1]),
+        Err(Error::Eof {
+            expected: 32,
+            available: 31
+        })
+    );
+
+    // Bulk arrays don't count towards the container depth, like regular arrays.
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Wrapper(#[serde(with = "crate::array")] [u8; 2]);
+    assert_eq!(
+        from_bytes_with_limit::<Wrapper>(&[1, 2], 1),
+        Ok(Wrapper([1, 2]))
+    );
+}
+
+#[test]
+#[cfg(feature = "indexmap")]
+fn index_map_keeps_canonical_order() {
+    use indexmap::IndexMap;
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Wrapper(#[serde(with = "crate::index_map")] IndexMap<u32, String>);
+
+    let mut sorted = BTreeMap::new();
+    for i in [1u32, 256, 2, 65536, 3, u32::MAX] {
+        sorted.insert(i, i.to_string());
+    }
+    let bytes = to_bytes(&sorted).unwrap();
+
+    // Decoding yields the entries in canonical order, whatever the container.
+    let decoded: Wrapper = from_bytes(&bytes).unwrap();
+    let plain: IndexMap<u32, String> = from_bytes(&bytes).unwrap();
+    let keys: Vec<u32> = decoded.0.keys().copied().collect();
+    assert_eq!(keys, vec![65536, 256, 1, 2, 3, u32::MAX]);
+    assert_eq!(plain.keys().copied().collect::<Vec<_>>(), keys);
+
+    // Re-encoding streams the entries in that order.
+    assert_eq!(to_bytes(&decoded).unwrap(), bytes);
+    assert_eq!(to_bytes(&plain).unwrap(), bytes);
+    is_same(decoded);
+}
+
+#[test]
+#[cfg(all(feature = "indexmap", debug_assertions))]
+#[should_panic(expected = "keys of PreSorted entries must be unique and in increasing order")]
+fn index_map_out_of_order() {
+    #[derive(Serialize)]
+    struct Wrapper(#[serde(with = "crate::index_map")] indexmap::IndexMap<u8, ()>);
+
+    let mut map = indexmap::IndexMap::new();
+    map.insert(2u8, ());
+    map.insert(1u8, ());
+    let _ = to_bytes(&Wrapper(map));
+}
+
+#[test]
+fn from_byte_iter_matches_slice_path() {
+    let value = (vec![String::from("abc"), String::new()], Some(300u16));
+    let bytes = to_bytes(&value).unwrap();
+    assert_eq!(
+        from_byte_iter::<(Vec<String>, Option<u16>), _>(bytes.iter().copied()).unwrap(),
+        value
+    );
+
+    // Malformed lengths and truncated or trailing input fail the same way as `from_bytes`.
+    for input in [
+        vec![0x80, 0x00],
+        vec![0x80, 0x80, 0x80, 0x80, 0x10],
+        vec![0xff, 0xff, 0xff, 0xff, 0x08],
+        vec![0x02, 0x01],
+        vec![0x00, 0x00],
+    ] {
+        assert_eq!(
+            from_byte_iter::<Vec<u8>, _>(input.iter().copied()).unwrap_err(),
+            from_bytes::<Vec<u8>>(&input).unwrap_err(),
+        );
+    }
+}
+
+#[test]
+fn slice_reader() {
+    use crate::io::{Read, SliceReader};
+
+    let mut bytes = to_bytes(&(5u8, String::from("five"))).unwrap();
+    bytes.push(0xff);
+
+    let mut reader = SliceReader::new(&bytes);
+    let (value, len): ((u8, String), usize) = crate::from_reader_counted(&mut reader).unwrap();
+    assert_eq!(value, (5, String::from("five")));
+    assert_eq!(len, 6);
+    assert_eq!(reader.remaining(), &[0xff]);
+
+    let mut buf = [0; 4];
+    assert_eq!(reader.read(&mut buf).unwrap(), 1);
+    assert_eq!(reader.read(&mut buf).unwrap(), 0);
+    assert_eq!(
+        from_reader::<(u8, String)>(SliceReader::new(&bytes)).unwrap_err(),
+        Error::RemainingInput {
+            len: 1,
+            first: 0xff
+        }
+    );
+}
+
+#[test]
+fn config_max_map_entries() {
+    let config = Config::new().max_map_entries(2);
+    let map: BTreeMap<u8, Vec<u8>> = (0..3).map(|i| (i, vec![i])).collect();
+    let bytes = to_bytes(&map).unwrap();
+
+    assert_eq!(
+        from_bytes_with_config::<BTreeMap<u8, Vec<u8>>>(&bytes, &config),
+        Err(Error::ExceededMaxMapEntries { len: 3, max: 2 })
+    );
+    assert_eq!(
+        from_reader_with_config::<BTreeMap<u8, Vec<u8>>>(&bytes[..], &config),
+        Err(Error::ExceededMaxMapEntries { len: 3, max: 2 })
+    );
+    // The limit only applies to maps.
+    assert!(from_bytes_with_config::<Vec<(u8, Vec<u8>)>>(&bytes, &config).is_ok());
+    // The default configuration behaves like `from_bytes`.
+    assert_eq!(
+        from_bytes_with_config::<BTreeMap<u8, Vec<u8>>>(&bytes, &Config::default()).unwrap(),
+        map
+    );
+
+    let too_deep = Config::new().max_container_depth(crate::MAX_CONTAINER_DEPTH + 1);
+    assert_eq!(
+        from_bytes_with_config::<u8>(&[0], &too_deep),
+        Err(Error::NotSupported("limit exceeds the max allowed depth"))
+    );
+    assert_eq!(
+        from_bytes_with_config::<List<usize>>(
+            &to_bytes(&List::integers(4)).unwrap(),
+            &Config::new().max_container_depth(2)
+        ),
+        Err(Error::ExceededContainerDepthLimit {
+            name: "List",
+            limit: 2
+        })
+    );
+}
+
+#[test]
+fn serialize_into_slice_fits_or_reports_size() {
+    let value = (List::integers(3), String::from("tail"));
+    let expected = to_bytes(&value).unwrap();
+
+    let mut buf = [0xaa; 64];
+    let len = crate::serialize_into_slice(&mut buf, &value).unwrap();
+    assert_eq!(&buf[..len], &expected[..]);
+    assert!(buf[len..].iter().all(|b| *b == 0xaa));
+
+    let mut exact = vec![0; expected.len()];
+    assert_eq!(
+        crate::serialize_into_slice(&mut exact, &value),
+        Ok(expected.len())
+    );
+    assert_eq!(exact, expected);
+
+    let mut small = vec![0; expected.len() - 1];
+    assert_eq!(
+        crate::serialize_into_slice(&mut small, &value),
+        Err(Error::BufferTooSmall {
+            needed: expected.len()
+        })
+    );
+    assert_eq!(
+        crate::serialize_into_slice(&mut [], &value),
+        Err(Error::BufferTooSmall {
+            needed: serialized_size(&value).unwrap()
+        })
+    );
+}
+
+#[test]
+fn invalid_tag_errors_report_the_byte() {
+    assert_eq!(
+        from_bytes::<Option<u8>>(&[0xff, 0])
+            .unwrap_err()
+            .to_string(),
+        "expected option tag, found byte 0xff"
+    );
+    assert_eq!(
+        from_bytes::<bool>(&[0x02]).unwrap_err().to_string(),
+        "expected boolean, found byte 0x02"
+    );
+}
+
+#[test]
+fn from_bytes_with_layout_checks_arity() {
+    use crate::{from_bytes_with_layout, Layout};
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Header {
+        version: u8,
+        tags: Vec<String>,
+        flags: u16,
+    }
+
+    /// An outdated definition, missing the last field.
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct OldHeader {
+        version: u8,
+        tags: Vec<String>,
+    }
+
+    let layout = Layout::Struct(vec![
+        Layout::U8,
+        Layout::Seq(Box::new(Layout::Str)),
+        Layout::U16,
+    ]);
+    let header = Header {
+        version: 1,
+        tags: vec!["a".to_string()],
+        flags: 3,
+    };
+    let mut bytes = to_bytes(&header).unwrap();
+    assert_eq!(
+        from_bytes_with_layout::<Header>(&bytes, &layout),
+        Ok(header)
+    );
+    assert_eq!(
+        from_bytes_with_layout::<OldHeader>(&bytes, &layout),
+        Err(Error::LayoutMismatch {
+            expected: 6,
+            found: 4
+        })
+    );
+    // Errors of the type itself are reported as usual.
+    assert_eq!(
+        from_bytes_with_layout::<(Header, u8)>(&bytes, &layout),
+        Err(Error::Eof {
+            expected: 1,
+            available: 0
+        })
+    );
+    // Input that doesn't match the layout is reported as such.
+    assert_eq!(
+        from_bytes_with_layout::<Header>(&bytes[..5], &layout),
+        Err(Error::Eof {
+            expected: 2,
+            available: 1
+        })
+    );
+    bytes.push(0);
+    assert_eq!(
+        from_bytes_with_layout::<Header>(&bytes, &layout),
+        Err(Error::RemainingInput { len: 1, first: 0 })
+    );
+}
+
+#[test]
+fn try_from_bytes_reports_consumed_length() {
+    let first = (String::from("key"), Some(7u64));
+    let mut bytes = to_bytes(&first).unwrap();
+    let first_len = bytes.len();
+    bytes.extend(to_bytes(&List::integers(2)).unwrap());
+
+    let (decoded, len) = crate::try_from_bytes::<(String, Option<u64>)>(&bytes).unwrap();
+    assert_eq!((decoded, len), (first, first_len));
+    let (list, rest_len) = crate::try_from_bytes::<List<usize>>(&bytes[len..]).unwrap();
+    assert_eq!(list, List::integers(2));
+    assert_eq!(len + rest_len, bytes.len());
+
+    assert_eq!(
+        crate::try_from_bytes::<(String, Option<u64>)>(&bytes[..first_len - 1]),
+        Err(Error::EofInOption(Box::new(Error::Eof {
+            expected: 8,
+            available: 7
+        })))
+    );
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn io_errors_are_chained() {
+    use std::error::Error as _;
+
+    /// A connection dropping after a few bytes.
+    struct Truncated(&'static [u8]);
+
+    impl std::io::Read for Truncated {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.0.is_empty() {
+                return Err(std::io::ErrorKind::ConnectionReset.into());
+            }
+            let len = std::cmp::min(buf.len(), self.0.len());
+            buf[..len].copy_from_slice(&self.0[..len]);
+            self.0 = &self.0[len..];
+            Ok(len)
+        }
+    }
+
+    let err = from_reader::<(u8, u64)>(Truncated(&[1, 2, 3])).unwrap_err();
+    let source = err.source().expect("I/O errors have a source");
+    assert_eq!(source.to_string(), "connection reset");
+    let io = source.source().unwrap();
+    assert_eq!(
+        io.downcast_ref::<std::io::Error>().unwrap().kind(),
+        std::io::ErrorKind::ConnectionReset
+    );
+    match &err {
+        Error::Io(io) => {
+            assert_eq!(io.kind(), std::io::ErrorKind::ConnectionReset);
+            assert_eq!(io.get_ref().kind(), std::io::ErrorKind::ConnectionReset);
+        }
+        _ => panic!("unexpected error: {:?}", err),
+    }
+    assert_eq!(err.clone(), err);
+    assert_eq!(err.to_string(), "I/O error: connection reset");
+
+    /// A reader reporting the end of its input as an error.
+    struct Exhausted;
+
+    impl std::io::Read for Exhausted {
+        fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+            Err(std::io::ErrorKind::UnexpectedEof.into())
+        }
+    }
+
+    assert_eq!(
+        from_reader::<u8>(Exhausted),
+        Err(Error::Eof {
+            expected: 1,
+            available: 0
+        })
+    );
+
+    // A clean end of input is still reported as `Eof`.
+    assert_eq!(
+        from_reader::<(u8, u64)>(&[1, 2, 3][..]),
+        Err(Error::Eof {
+            expected: 8,
+            available: 2
+        })
+    );
+}
+
+#[test]
+fn serializer_config_mirrors_deserializer_limits() {
+    use crate::{to_bytes_with_config, SerializerConfig};
+
+    let list = List::integers(3);
+    let bytes = to_bytes(&list).unwrap();
+    for depth in 0..6 {
+        let ser = to_bytes_with_config(&list, &SerializerConfig::new().max_container_depth(depth));
+        let de = from_bytes_with_config::<List<usize>>(
+            &bytes,
+            &Config::new().max_container_depth(depth),
+        );
+        // Whatever the limit, serialization succeeds exactly when deserialization does.
+        assert_eq!(ser.is_ok(), de.is_ok());
+        if let Err(err) = ser {
+            assert_eq!(Err(err), de);
+        }
+    }
+
+    let config = SerializerConfig::new().max_sequence_length(2);
+    assert!(to_bytes_with_config(&vec![1u8, 2], &config).is_ok());
+    let too_long = Err(Error::ExceededMaxLen { len: 3, max: 2 });
+    assert_eq!(to_bytes_with_config(&"abc", &config), too_long);
+    assert_eq!(to_bytes_with_config(&[vec![0u16; 3]], &config), too_long);
+    let map: BTreeMap<u8, u8> = (0..3).map(|i| (i, i)).collect();
+    assert_eq!(to_bytes_with_config(&map, &config), too_long);
+    // Arrays and tuples have no length prefix.
+    assert!(to_bytes_with_config(&[0u8; 3], &config).is_ok());
+
+    assert_eq!(
+        to_bytes_with_config(
+            &0u8,
+            &SerializerConfig::new().max_sequence_length(MAX_SEQUENCE_LENGTH + 1)
+        ),
+        Err(Error::NotSupported(
+            "limit exceeds the max allowed sequence length"
+        ))
+    );
+}
+
+#[test]
+fn wide_integers_from_chunked_reader() {
+    /// Delivers at most `chunk` bytes per read.
+    struct Chunked {
+        data: Vec<u8>,
+        pos: usize,
+        chunk: usize,
+    }
+
+    impl crate::io::Read for Chunked {
+        fn read(&mut self, buf: &mut [u8]) -> crate::io::Result<usize> {
+            let n = core::cmp::min(
+                core::cmp::min(buf.len(), self.chunk),
+                self.data.len() - self.pos,
+            );
+            buf[..n].copy_from_slice(&self.data[self.pos..self.pos + n]);
+            self.pos += n;
+            Ok(n)
+        }
+    }
+
+    let value = (u128::MAX - 1, i128::MIN + 1, 0x0102_0304_0506_0708u64);
+    let bytes = to_bytes(&value).unwrap();
+    for chunk in 1..=17 {
+        let reader = Chunked {
+            data: bytes.clone(),
+            pos: 0,
+            chunk,
+        };
+        assert_eq!(from_reader::<(u128, i128, u64)>(reader), Ok(value));
+
+        // Running out of data in the middle of an integer is still an `Eof`.
+        let reader = Chunked {
+            data: bytes[..20].to_vec(),
+            pos: 0,
+            chunk,
+        };
+        assert_eq!(
+            from_reader::<(u128, i128, u64)>(reader),
+            Err(Error::Eof {
+                expected: 16,
+                available: 4
+            })
+        );
+    }
+}
+
+#[test]
+fn value_roundtrip() {
+    use crate::{from_bytes_value, to_bytes_value, Layout, Value};
+
+    #[derive(Serialize)]
+    enum Kind {
+        Empty,
+        Pair(u8, i128),
+    }
+
+    #[derive(Serialize)]
+    struct Record {
+        name: String,
+        kinds: Vec<Kind>,
+        index: BTreeMap<u16, Option<bool>>,
+        hash: [u8; 2],
+        unit: (),
+    }
+
+    let mut index = BTreeMap::new();
+    index.insert(256, Some(true));
+    index.insert(1, None);
+    let record = Record {
+        name: "r".to_string(),
+        kinds: vec![Kind::Pair(1, -2), Kind::Empty],
+        index,
+        hash: [0xab, 0xcd],
+        unit: (),
+    };
+    let layout = Layout::Struct(vec![
+        Layout::Str,
+        Layout::Seq(Box::new(Layout::Enum(vec![
+            Layout::Unit,
+            Layout::Tuple(vec![Layout::U8, Layout::I128]),
+        ]))),
+        Layout::Map(
+            Box::new(Layout::U16),
+            Box::new(Layout::Option(Box::new(Layout::Bool))),
+        ),
+        Layout::Array(Box::new(Layout::U8), 2),
+        Layout::Unit,
+    ]);
+    let bytes = to_bytes(&record).unwrap();
+    let value = from_bytes_value(&bytes, &layout).unwrap();
+    assert_eq!(to_bytes_value(&value).unwrap(), bytes);
+
+    // Map entries are re-sorted in canonical order when encoding.
+    let mut value = value;
+    if let Value::Struct(fields) = &mut value {
+        if let Value::Map(entries) = &mut fields[2] {
+            assert_eq!(entries[0].0, Value::U16(256));
+            entries.reverse();
+        }
+    }
+    assert_eq!(to_bytes_value(&value).unwrap(), bytes);
+
+    // Decoding follows the same rules as typed decoding.
+    assert_eq!(
+        from_bytes_value(&bytes[..bytes.len() - 1], &layout),
+        Err(Error::Eof {
+            expected: 1,
+            available: 0
+        })
+    );
+    assert_eq!(
+        from_bytes_value(&[2], &Layout::Bool),
+        Err(Error::ExpectedBoolean(2))
+    );
+}
+
+#[test]
+#[cfg(feature = "json")]
+fn value_to_json() {
+    use crate::{to_json, Value};
+    use serde_json::json;
+
+    let value = Value::Struct(vec![
+        Value::U64(u64::MAX),
+        Value::I128(-1),
+        Value::Str("s".to_string()),
+        Value::Option(Some(Box::new(Value::Bool(true)))),
+        Value::Enum(1, Box::new(Value::Unit)),
+        Value::Map(vec![(Value::U8(1), Value::Seq(vec![Value::I8(-1)]))]),
+    ]);
+    assert_eq!(
+        to_json(&value),
+        json!([
+            u64::MAX,
+            "-1",
+            "s",
+            true,
+            {"variant": 1, "value": null},
+            [[1, [-1]]],
+        ])
+    );
+}
+
+#[test]
+fn impossible_lengths_fail_early() {
+    /// Counts how many elements were decoded.
+    #[derive(Debug)]
+    struct Counted;
+
+    static DECODED: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+    impl<'de> Deserialize<'de> for Counted {
+        fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            DECODED.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            u8::deserialize(deserializer).map(|_| Counted)
+        }
+    }
+
+    // 10 million elements claimed, 20 bytes available.
+    let mut bytes = vec![0x80, 0xad, 0xe2, 0x04];
+    bytes.extend([0u8; 20]);
+    assert_eq!(
+        from_bytes::<Vec<u64>>(&bytes).unwrap_err(),
+        Error::Eof {
+            expected: 9999999,
+            available: 12
+        }
+    );
+    assert_eq!(
+        from_bytes::<Vec<Counted>>(&bytes).unwrap_err(),
+        Error::Eof {
+            expected: 9999999,
+            available: 19
+        }
+    );
+    assert_eq!(DECODED.load(std::sync::atomic::Ordering::Relaxed), 1);
+    assert_eq!(
+        from_bytes::<BTreeMap<u8, u8>>(&bytes).unwrap_err(),
+        Error::Eof {
+            expected: 10000000,
+            available: 20
+        }
+    );
+    // The reader path only fails once it runs out of input.
+    assert_eq!(
+        from_bytes_via_reader::<Vec<u64>>(&bytes),
+        Err(Error::Eof {
+            expected: 8,
+            available: 4
+        })
+    );
+
+    // Zero-sized elements take no bytes, so their sequences may be longer than the input.
+    assert_eq!(from_bytes::<Vec<()>>(&[5]).unwrap(), vec![(); 5]);
+    assert_eq!(from_bytes::<Vec<[u8; 0]>>(&[2]).unwrap(), vec![[0u8; 0]; 2]);
+    assert_eq!(
+        from_bytes::<BTreeMap<(), ()>>(&[1]).unwrap(),
+        vec![((), ())].into_iter().collect()
+    );
+    assert_eq!(
+        from_bytes::<BTreeMap<(), ()>>(&[2]).unwrap_err(),
+        Error::Eof {
+            expected: 2,
+            available: 0
+        }
+    );
+}
+
+#[test]
+fn config_map_key_order() {
+    /// Orders little-endian integer keys numerically.
+    fn numeric(a: &[u8], b: &[u8]) -> std::cmp::Ordering {
+        a.iter().rev().cmp(b.iter().rev())
+    }
+
+    let config = Config::new().map_key_order(numeric);
+    // Keys 1 then 256: numeric order, but not canonical BCS order.
+    let bytes = vec![2, 1, 0, 0, 0, 1, 1];
+    let expected: BTreeMap<u16, bool> = vec![(1, false), (256, true)].into_iter().collect();
+    assert_eq!(
+        from_bytes_with_config::<BTreeMap<u16, bool>>(&bytes, &config),
+        Ok(expected.clone())
+    );
+    assert_eq!(
+        from_reader_with_config::<BTreeMap<u16, bool>>(&bytes[..], &config),
+        Ok(expected)
+    );
+    assert_eq!(
+        from_bytes::<BTreeMap<u16, bool>>(&bytes),
+        Err(Error::NonCanonicalMap {
+            index: 1,
+            offset: 4
+        })
+    );
+
+    // Duplicates are rejected even by a comparator accepting them.
+    let lenient = Config::new().map_key_order(|_, _| std::cmp::Ordering::Less);
+    assert_eq!(
+        from_bytes_with_config::<BTreeMap<u8, u8>>(&[2, 1, 0, 1, 0], &lenient),
+        Err(Error::NonCanonicalMap {
+            index: 1,
+            offset: 3
+        })
+    );
+    assert!(from_bytes_with_config::<BTreeMap<u8, u8>>(&[2, 2, 0, 1, 0], &lenient).is_ok());
+}
+
+#[test]
+fn from_bytes_ref_accepts_owners() {
+    let bytes = to_bytes(&List::integers(2)).unwrap();
+    let boxed: Box<[u8]> = bytes.clone().into_boxed_slice();
+    assert_eq!(
+        crate::from_bytes_ref::<List<usize>>(&bytes),
+        Ok(List::integers(2))
+    );
+    assert_eq!(
+        crate::from_bytes_ref::<List<usize>>(&boxed),
+        Ok(List::integers(2))
+    );
+    assert_eq!(
+        crate::from_bytes_ref::<List<usize>>(&bytes[..3]),
+        Err(Error::Eof {
+            expected: 8,
+            available: 3
+        })
+    );
+    assert_eq!(
+        crate::from_bytes_ref::<List<usize>>([bytes, vec![0]].concat()),
+        Err(Error::RemainingInput { len: 1, first: 0 })
+    );
+}
+
+#[test]
+fn empty_collections() {
+    fn check<T>(value: T, expected: &[u8])
+    where
+        T: Serialize + DeserializeOwned + PartialEq + std::fmt::Debug,
+    {
+        assert_eq!(to_bytes(&value).unwrap(), expected);
+        assert_eq!(serialized_size(&value).unwrap(), expected.len());
+        assert_eq!(from_bytes::<T>(expected).unwrap(), value);
+        assert_eq!(from_bytes_via_reader::<T>(expected).unwrap(), value);
+    }
+
+    check(Vec::<u64>::new(), &[0]);
+    check(Vec::<Vec<u8>>::new(), &[0]);
+    check(String::new(), &[0]);
+    check(BTreeMap::<String, u8>::new(), &[0]);
+    check(None::<u32>, &[0]);
+    check(Some(String::new()), &[1, 0]);
+    check(
+        (Vec::<u8>::new(), String::new(), BTreeMap::<u8, u8>::new()),
+        &[0, 0, 0],
+    );
+    assert_eq!(from_bytes::<&str>(&[0]).unwrap(), "");
+
+    // The length prefix of an empty collection cannot be omitted.
+    let missing_len = Error::Eof {
+        expected: 1,
+        available: 0,
+    };
+    assert_eq!(from_bytes::<Vec<u64>>(&[]).unwrap_err(), missing_len);
+    assert_eq!(from_bytes::<String>(&[]).unwrap_err(), missing_len);
+    assert_eq!(
+        from_bytes_via_reader::<BTreeMap<u8, u8>>(&[]).unwrap_err(),
+        missing_len
+    );
+    assert_eq!(from_bytes::<(u8, Vec<u8>)>(&[7]).unwrap_err(), missing_len);
+    assert_eq!(
+        from_bytes::<Option<u8>>(&[]),
+        Err(Error::Eof {
+            expected: 1,
+            available: 0
+        })
+    );
+    // Only the first byte of the prefix is reported as missing.
+    assert_eq!(
+        from_bytes::<Vec<u8>>(&[0x80]),
+        Err(Error::Eof {
+            expected: 1,
+            available: 0
+        })
+    );
+}
+
+#[test]
+fn cow_fields_borrow_from_slices() {
+    use std::borrow::Cow;
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Record<'a> {
+        #[serde(borrow)]
+        name: Cow<'a, str>,
+        #[serde(borrow, with = "serde_bytes_cow")]
+        payload: Cow<'a, [u8]>,
+        #[serde(borrow)]
+        tags: Vec<&'a str>,
+    }
+
+    /// `Cow<[u8]>` goes through `deserialize_seq` unless treated as bytes.
+    mod serde_bytes_cow {
+        use serde::{Deserializer, Serializer};
+        use std::borrow::Cow;
+
+        #[allow(clippy::ptr_arg)]
+        pub fn serialize<S: Serializer>(
+            bytes: &Cow<[u8]>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            serializer.serialize_bytes(bytes)
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Cow<'de, [u8]>, D::Error> {
+            struct CowBytes;
+
+            impl<'de> serde::de::Visitor<'de> for CowBytes {
+                type Value = Cow<'de, [u8]>;
+
+                fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                    f.write_str("bytes")
+                }
+
+                fn visit_borrowed_bytes<E>(self, v: &'de [u8]) -> Result<Self::Value, E> {
+                    Ok(Cow::Borrowed(v))
+                }
+
+                fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E> {
+                    Ok(Cow::Owned(v.to_vec()))
+                }
+
+                fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+                    Ok(Cow::Owned(v))
+                }
+            }
+
+            deserializer.deserialize_bytes(CowBytes)
+        }
+    }
+
+    let record = Record {
+        name: Cow::Borrowed("name"),
+        payload: Cow::Borrowed(&[1, 2, 3]),
+        tags: vec!["a", "b"],
+    };
+    let bytes = to_bytes(&record).unwrap();
+
+    let borrowed: Record = from_bytes(&bytes).unwrap();
+    assert_eq!(borrowed, record);
+    assert!(matches!(borrowed.name, Cow::Borrowed(_)));
+    assert!(matches!(borrowed.payload, Cow::Borrowed(_)));
+    // The borrowed data points into the input.
+    let range = bytes.as_ptr_range();
+    assert!(range.contains(&borrowed.name.as_ptr()));
+    assert!(range.contains(&borrowed.payload.as_ptr()));
+    assert!(range.contains(&borrowed.tags[1].as_ptr()));
+
+    /// Reader functions need types that own their data.
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct OwnedRecord {
+        name: Cow<'static, str>,
+        payload: Cow<'static, [u8]>,
+        tags: Vec<Cow<'static, str>>,
+    }
+
+    let owned: OwnedRecord = from_reader(&bytes[..]).unwrap();
+    assert_eq!(owned.name, record.name);
+    assert_eq!(owned.payload, record.payload);
+    assert_eq!(owned.tags, record.tags);
+    assert!(matches!(owned.name, Cow::Owned(_)));
+    assert!(matches!(owned.payload, Cow::Owned(_)));
+    assert!(owned.tags.iter().all(|t| matches!(t, Cow::Owned(_))));
+}
+
+#[test]
+fn prelude_covers_common_usage() {
+    mod user {
+        use crate::prelude::*;
+
+        pub fn roundtrip(value: &(u8, String)) -> Result<(u8, String)> {
+            let bytes = to_bytes_with_limit(value, MAX_CONTAINER_DEPTH)?;
+            if bytes.len() != serialized_size(value)? {
+                return Err(Error::Custom("size mismatch".to_string()));
+            }
+            from_bytes_with_config(&bytes, &Config::new())
+        }
+    }
+
+    let value = (1, "one".to_string());
+    assert_eq!(user::roundtrip(&value), Ok(value));
+}
+
+#[test]
+fn decode_stats() {
+    use crate::{from_bytes_with_stats, DecodeStats};
+
+    let list = List::integers(9);
+    let bytes = to_bytes(&list).unwrap();
+    let (decoded, stats) = from_bytes_with_stats::<List<usize>>(&bytes).unwrap();
+    assert_eq!(decoded, list);
+    assert_eq!(
+        stats,
+        DecodeStats {
+            max_depth: 10,
+            bytes_read: bytes.len(),
+            map_entries: 0,
+            seq_elements: 0,
+        }
+    );
+
+    let mut map = BTreeMap::new();
+    map.insert(vec![1u8, 2], vec![E::Unit, E::Newtype(3)]);
+    map.insert(vec![], vec![]);
+    let bytes = to_bytes(&map).unwrap();
+    let (_, stats) = from_bytes_with_stats::<BTreeMap<Vec<u8>, Vec<E>>>(&bytes).unwrap();
+    assert_eq!(
+        stats,
+        DecodeStats {
+            max_depth: 1,
+            bytes_read: bytes.len(),
+            map_entries: 2,
+            seq_elements: 4,
+        }
+    );
+
+    // Errors are reported as usual.
+    assert_eq!(
+        from_bytes_with_stats::<BTreeMap<Vec<u8>, Vec<E>>>(&bytes[1..]),
+        Err(Error::RemainingInput {
+            len: bytes.len() - 2,
+            first: 0
+        })
+    );
+}
+
+#[test]
+fn trailing_padding_after_fixed_array() {
+    let padded = [1u8, 2, 3, 4, 0];
+    let err = from_bytes::<[u8; 4]>(&padded).unwrap_err();
+    assert_eq!(err, Error::RemainingInput { len: 1, first: 0 });
+    assert_eq!(
+        err.to_string(),
+        "remaining input: 1 trailing byte(s), starting with 0x00"
+    );
+    assert_eq!(
+        from_bytes::<[u8; 4]>(&[1, 2, 3, 4, 0xee, 0, 0]),
+        Err(Error::RemainingInput {
+            len: 3,
+            first: 0xee
+        })
+    );
+    assert_eq!(from_bytes::<[u8; 4]>(&padded[..4]), Ok([1, 2, 3, 4]));
+}
+
+#[test]
+fn byte_buf_matches_vec_encoding() {
+    let payload: Vec<u8> = (0..=255).collect();
+    let buf = ByteBuf::from(payload.clone());
+    let bytes = to_bytes(&buf).unwrap();
+    assert_eq!(bytes, to_bytes(&payload).unwrap());
+    assert_eq!(from_bytes::<ByteBuf>(&bytes).unwrap(), buf);
+    assert_eq!(from_bytes_via_reader::<ByteBuf>(&bytes).unwrap(), buf);
+    assert_eq!(
+        from_bytes::<Vec<u8>>(&to_bytes(&buf).unwrap()).unwrap(),
+        payload
+    );
+
+    let borrowed: Bytes = from_bytes(&bytes).unwrap();
+    assert_eq!(borrowed.as_slice(), &payload[..]);
+    assert_eq!(to_bytes(&Bytes::new(&payload)).unwrap(), bytes);
+
+    assert_eq!(to_bytes(&ByteBuf::new()).unwrap(), vec![0]);
+    assert_eq!(
+        from_bytes::<ByteBuf>(&[3, 1, 2]),
+        Err(Error::Eof {
+            expected: 3,
+            available: 2
+        })
+    );
+    assert_eq!(
+        from_bytes::<ByteBuf>(&[1, 1, 2]),
+        Err(Error::RemainingInput { len: 1, first: 2 })
+    );
+}
+
+#[test]
+fn const_depth_limit() {
+    let bytes = to_bytes(&List::integers(2)).unwrap();
+    assert_eq!(
+        from_bytes_const::<List<usize>, 3>(&bytes).unwrap(),
+        List::integers(2)
+    );
+    assert_eq!(
+        from_bytes_const::<List<usize>, 2>(&bytes),
+        Err(Error::ExceededContainerDepthLimit {
+            name: "List",
+            limit: 2
+        })
+    );
+    assert_eq!(
+        from_bytes_const::<List<usize>, 2>(&bytes),
+        from_bytes_with_limit::<List<usize>>(&bytes, 2)
+    );
+    assert_eq!(from_bytes_const::<u8, MAX_CONTAINER_DEPTH>(&[7]), Ok(7));
+}
+
+#[test]
+fn deserialize_any_names_the_culprit() {
+    #[derive(Debug, Deserialize)]
+    #[serde(untagged)]
+    enum Untagged {
+        Number(u64),
+    }
+
+    #[allow(dead_code)]
+    #[derive(Debug, Deserialize)]
+    #[serde(tag = "kind")]
+    enum Tagged {
+        Number { value: u64 },
+    }
+
+    // The same message lists the usual culprits, whatever the visitor.
+    let err = from_bytes::<Untagged>(&[0; 8]).unwrap_err();
+    for culprit in ["#[serde(untagged)]", "#[serde(tag", "serde_json::Value"] {
+        assert!(err.to_string().contains(culprit), "{}", err);
+    }
+    assert_eq!(from_bytes_via_reader::<Untagged>(&[0; 8]).unwrap_err(), err);
+    assert_eq!(from_bytes::<Tagged>(&[0; 8]).unwrap_err(), err);
+    #[cfg(feature = "json")]
+    assert_eq!(from_bytes::<serde_json::Value>(&[0]).unwrap_err(), err);
+
+    struct Dynamic;
+
+    impl<'de> Deserialize<'de> for Dynamic {
+        fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer
+                .deserialize_any(serde::de::IgnoredAny)
+                .map(|_| Dynamic)
+        }
+    }
+
+    assert!(matches!(
+        from_bytes::<Dynamic>(&[0]),
+        Err(Error::NotSupported(msg)) if msg.contains("concrete type")
+    ));
+}
+
+#[test]
+fn to_bytes_with_capacity_matches_to_bytes() {
+    let value = vec![(1u64, "one".to_string()); 100];
+    let size = serialized_size(&value).unwrap();
+    let bytes = to_bytes_with_capacity(&value, size).unwrap();
+    assert_eq!(bytes, to_bytes(&value).unwrap());
+    assert_eq!(bytes.len(), size);
+    assert!(bytes.capacity() >= size);
+    // The hint is not a limit.
+    assert_eq!(to_bytes_with_capacity(&value, 1).unwrap(), bytes);
+    assert_eq!(
+        to_bytes_with_capacity(&List::integers(MAX_CONTAINER_DEPTH), 16),
+        to_bytes(&List::integers(MAX_CONTAINER_DEPTH))
+    );
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+struct Tree {
+    children: Vec<Tree>,
+    next: Option<Box<Tree>>,
+}
+
+impl Tree {
+    /// A tree of the given container depth, alternating between nesting through `children` and
+    /// `next`.
+    fn deep(depth: usize) -> Self {
+        let mut tree = Tree {
+            children: vec![],
+            next: None,
+        };
+        for i in 1..depth {
+            tree = if i % 2 == 0 {
+                Tree {
+                    children: vec![tree],
+                    next: None,
+                }
+            } else {
+                Tree {
+                    children: vec![],
+                    next: Some(Box::new(tree)),
+                }
+            };
+        }
+        tree
+    }
+}
+
+#[test]
+fn serialization_enforces_container_depth() {
+    let too_deep = Tree::deep(MAX_CONTAINER_DEPTH + 1);
+    let depth_err = Error::ExceededContainerDepthLimit {
+        name: "Tree",
+        limit: MAX_CONTAINER_DEPTH,
+    };
+    assert_eq!(to_bytes(&too_deep), Err(depth_err.clone()));
+    assert_eq!(serialized_size(&too_deep), Err(depth_err.clone()));
+    assert_eq!(
+        crate::serialize_into(&mut Vec::new(), &too_deep),
+        Err(depth_err)
+    );
+
+    // The encoder and the decoder agree on the depth of a value.
+    let bytes = to_bytes_with_limit(&Tree::deep(10), 10).unwrap();
+    assert_eq!(
+        from_bytes_with_limit::<Tree>(&bytes, 10).unwrap(),
+        Tree::deep(10)
+    );
+    let depth_err = Error::ExceededContainerDepthLimit {
+        name: "Tree",
+        limit: 9,
+    };
+    assert_eq!(
+        to_bytes_with_limit(&Tree::deep(10), 9),
+        Err(depth_err.clone())
+    );
+    assert_eq!(from_bytes_with_limit::<Tree>(&bytes, 9), Err(depth_err));
+}
+
+#[test]
+fn serialization_bounds_sequence_nesting() {
+    /// Sequences nested `depth` levels deep through a custom `Serialize`, like dynamic values
+    /// such as `serde_json::Value`. With `map`, the innermost one is an empty map instead.
+    struct Nested {
+        depth: usize,
+        map: bool,
+    }
+
+    impl Serialize for Nested {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            use serde::ser::SerializeSeq;
+
+            if self.depth == 1 {
+                return match self.map {
+                    true => serializer.collect_map(BTreeMap::<u8, u8>::new()),
+                    false => serializer.collect_seq(Vec::<u8>::new()),
+                };
+            }
+            let mut seq = serializer.serialize_seq(Some(1))?;
+            seq.serialize_element(&Nested {
+                depth: self.depth - 1,
+                map: self.map,
+            })?;
+            seq.end()
+        }
+    }
+
+    let err = Error::ExceededSequenceDepthLimit {
+        limit: MAX_CONTAINER_DEPTH,
+    };
+    for map in [false, true] {
+        let deepest = Nested {
+            depth: MAX_CONTAINER_DEPTH,
+            map,
+        };
+        assert!(to_bytes(&deepest).is_ok());
+        let too_deep = Nested {
+            depth: MAX_CONTAINER_DEPTH + 1,
+            map,
+        };
+        assert_eq!(to_bytes(&too_deep), Err(err.clone()));
+        assert_eq!(serialized_size(&too_deep), Err(err.clone()));
+    }
+
+    // The encoder and the decoder agree on the depth of nested sequences.
+    let deepest = Nested {
+        depth: MAX_CONTAINER_DEPTH,
+        map: false,
+    };
+    let bytes = to_bytes(&deepest).unwrap();
+    assert_eq!(
+        crate::from_bytes_seed(NestedSeqs, &bytes),
+        Ok(MAX_CONTAINER_DEPTH)
+    );
+    assert_eq!(
+        crate::serialize_seq_iter(&mut Vec::new(), [deepest]),
+        Err(err)
+    );
+}
+
+/// Decodes `bytes` as the type of `_value`, which need not implement `PartialEq` or `Debug`.
+fn decode_like<T: DeserializeOwned>(_value: &T, bytes: &[u8]) -> Result<T, Error> {
+    from_bytes(bytes)
+}
+
+#[test]
+fn tuples_of_every_arity() {
+    // The standard library only implements `PartialEq` and `Debug` for tuples of up to 12
+    // elements, so decoded tuples are compared through their encoding.
+    macro_rules! check_tuple {
+        ($($value:expr),*) => {{
+            let tuple = ($($value,)*);
+            let expected: Vec<u8> = vec![$($value),*];
+            let bytes = to_bytes(&tuple).unwrap();
+            assert_eq!(bytes, expected);
+            let decoded = decode_like(&tuple, &bytes).unwrap();
+            assert_eq!(to_bytes(&decoded).unwrap(), bytes);
+            if let Some((_, truncated)) = bytes.split_last() {
+                assert_eq!(decode_like(&tuple, truncated).err(), Some(Error::Eof { expected: 1, available: 0 }));
+            }
+        }};
+    }
+
+    check_tuple!();
+    check_tuple!(1u8);
+    check_tuple!(1u8, 2u8);
+    check_tuple!(1u8, 2u8, 3u8);
+    check_tuple!(1u8, 2u8, 3u8, 4u8);
+    check_tuple!(1u8, 2u8, 3u8, 4u8, 5u8);
+    check_tuple!(1u8, 2u8, 3u8, 4u8, 5u8, 6u8);
+    check_tuple!(1u8, 2u8, 3u8, 4u8, 5u8, 6u8, 7u8);
+    check_tuple!(1u8, 2u8, 3u8, 4u8, 5u8, 6u8, 7u8, 8u8);
+    check_tuple!(1u8, 2u8, 3u8, 4u8, 5u8, 6u8, 7u8, 8u8, 9u8);
+    check_tuple!(1u8, 2u8, 3u8, 4u8, 5u8, 6u8, 7u8, 8u8, 9u8, 10u8);
+    check_tuple!(1u8, 2u8, 3u8, 4u8, 5u8, 6u8, 7u8, 8u8, 9u8, 10u8, 11u8);
+    check_tuple!(1u8, 2u8, 3u8, 4u8, 5u8, 6u8, 7u8, 8u8, 9u8, 10u8, 11u8, 12u8);
+    check_tuple!(1u8, 2u8, 3u8, 4u8, 5u8, 6u8, 7u8, 8u8, 9u8, 10u8, 11u8, 12u8, 13u8);
+    check_tuple!(1u8, 2u8, 3u8, 4u8, 5u8, 6u8, 7u8, 8u8, 9u8, 10u8, 11u8, 12u8, 13u8, 14u8);
+    check_tuple!(1u8, 2u8, 3u8, 4u8, 5u8, 6u8, 7u8, 8u8, 9u8, 10u8, 11u8, 12u8, 13u8, 14u8, 15u8);
+    check_tuple!(
+        1u8, 2u8, 3u8, 4u8, 5u8, 6u8, 7u8, 8u8, 9u8, 10u8, 11u8, 12u8, 13u8, 14u8, 15u8, 16u8
+    );
+
+    // Elements of different types are concatenated without any padding or prefix.
+    let tuple = (
+        1u8,
+        2u16,
+        3u32,
+        4u64,
+        5u128,
+        -6i8,
+        -7i16,
+        -8i32,
+        -9i64,
+        -10i128,
+        true,
+        (),
+        Some(1u8),
+        String::from("bcs"),
+        vec![1u8],
+        [0u8; 2],
+    );
+    let bytes = to_bytes(&tuple).unwrap();
+    assert_eq!(bytes.len(), 31 + 31 + 1 + 2 + 4 + 2 + 2);
+    let decoded = decode_like(&tuple, &bytes).unwrap();
+    assert_eq!(decoded.13, "bcs");
+    assert_eq!(to_bytes(&decoded).unwrap(), bytes);
+}
+
+#[test]
+fn non_canonical_uleb128_opt_in() {
+    let config = Config::new().allow_non_canonical_uleb128(true);
+    let padded_len = [0x82, 0x80, 0x00, 7, 8];
+    assert_eq!(
+        from_bytes::<Vec<u8>>(&padded_len),
+        Err(Error::NonCanonicalUleb128Encoding)
+    );
+    assert_eq!(
+        from_bytes_with_config::<Vec<u8>>(&padded_len, &config),
+        Ok(vec![7, 8])
+    );
+    assert_eq!(
+        from_reader_with_config::<Vec<u8>>(&padded_len[..], &config),
+        Ok(vec![7, 8])
+    );
+
+    // Variant indices are ULEB128 too.
+    assert_eq!(
+        from_bytes_with_config::<E>(&[0x80, 0x00], &config),
+        Ok(E::Unit)
+    );
+
+    // Overflowing or overlong encodings are still rejected.
+    assert_eq!(
+        from_bytes_with_config::<Vec<u8>>(&[0x80, 0x80, 0x80, 0x80, 0x10], &config),
+        Err(Error::IntegerOverflowDuringUleb128Decoding)
+    );
+    assert_eq!(
+        from_bytes_with_config::<Vec<u8>>(&[0x80, 0x80, 0x80, 0x80, 0x80, 0x00], &config),
+        Err(Error::IntegerOverflowDuringUleb128Decoding)
+    );
+    assert_eq!(
+        from_bytes_with_config::<Vec<u8>>(&[0x80, 0x80, 0x80, 0x80, 0x00], &config),
+        Ok(vec![])
+    );
+}
+
+#[test]
+fn eof_reports_missing_bytes() {
+    let truncated = [1, 2, 3];
+    let err = Error::Eof {
+        expected: 8,
+        available: 3,
+    };
+    assert_eq!(from_bytes::<u64>(&truncated), Err(err.clone()));
+    assert_eq!(from_bytes_via_reader::<u64>(&truncated), Err(err.clone()));
+    assert_eq!(
+        err.to_string(),
+        "unexpected end of input: expected 8 byte(s), found 3"
+    );
+
+    // The count is relative to the value being read, not to the whole input.
+    assert_eq!(
+        from_bytes::<(u8, u32)>(&truncated),
+        Err(Error::Eof {
+            expected: 4,
+            available: 2
+        })
+    );
+    assert_eq!(
+        from_bytes::<String>(&[4, b'a']),
+        Err(Error::Eof {
+            expected: 4,
+            available: 1
+        })
+    );
+}
+
+#[test]
+fn fixed_capacity_sequences() {
+    use arrayvec::ArrayVec;
+
+    /// Records the size hint before each element.
+    struct Hints(Vec<Option<usize>>);
+
+    impl<'de> Deserialize<'de> for Hints {
+        fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            struct HintsVisitor;
+
+            impl<'de> serde::de::Visitor<'de> for HintsVisitor {
+                type Value = Hints;
+
+                fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                    formatter.write_str("a sequence")
+                }
+
+                fn visit_seq<A: serde::de::SeqAccess<'de>>(
+                    self,
+                    mut seq: A,
+                ) -> Result<Hints, A::Error> {
+                    let mut hints = vec![seq.size_hint()];
+                    while seq.next_element::<u16>()?.is_some() {
+                        hints.push(seq.size_hint());
+                    }
+                    Ok(Hints(hints))
+                }
+            }
+
+            deserializer.deserialize_seq(HintsVisitor)
+        }
+    }
+
+    let bytes = to_bytes(&vec![1u16, 2, 3]).unwrap();
+    let expected = vec![Some(3), Some(2), Some(1), Some(0)];
+    assert_eq!(from_bytes::<Hints>(&bytes).unwrap().0, expected);
+    assert_eq!(from_bytes_via_reader::<Hints>(&bytes).unwrap().0, expected);
+
+    let small: ArrayVec<u16, 3> = from_bytes(&bytes).unwrap();
+    assert_eq!(small.as_slice(), &[1, 2, 3]);
+    let small: ArrayVec<u16, 3> = from_bytes_via_reader(&bytes).unwrap();
+    assert_eq!(small.as_slice(), &[1, 2, 3]);
+
+    let over_capacity =
+        Error::Custom("invalid length 3, expected an array with no more than 2 items".to_string());
+    assert_eq!(
+        from_bytes::<ArrayVec<u16, 2>>(&bytes),
+        Err(over_capacity.clone())
+    );
+    assert_eq!(
+        from_bytes_via_reader::<ArrayVec<u16, 2>>(&bytes),
+        Err(over_capacity)
+    );
+}
+
+#[test]
+fn reusable_deserializer() {
+    let bytes = to_bytes(&List::integers(3)).unwrap();
+    let mut deserializer = crate::Deserializer::new(&bytes, 4);
+
+    // Fail deep inside the list, with most of the depth budget used.
+    deserializer.reset(&bytes[..bytes.len() - 1]);
+    assert!(List::<usize>::deserialize(&mut deserializer).is_err());
+
+    deserializer.reset(&bytes);
+    assert_eq!(
+        List::<usize>::deserialize(&mut deserializer),
+        Ok(List::integers(3))
+    );
+    assert_eq!(deserializer.end(), Ok(()));
+
+    // Values can be decoded one after the other.
+    let pair = to_bytes(&(7u8, "x")).unwrap();
+    deserializer.reset(&pair);
+    assert_eq!(u8::deserialize(&mut deserializer), Ok(7));
+    assert_eq!(deserializer.remaining(), &pair[1..]);
+    assert_eq!(
+        deserializer.end(),
+        Err(Error::RemainingInput { len: 2, first: 1 })
+    );
+    assert_eq!(String::deserialize(&mut deserializer).unwrap(), "x");
+    assert_eq!(deserializer.end(), Ok(()));
+
+    // The depth limit is capped.
+    let mut deserializer = crate::Deserializer::new(&bytes, usize::MAX);
+    assert_eq!(
+        List::<usize>::deserialize(&mut deserializer),
+        Ok(List::integers(3))
+    );
+    let bytes = to_bytes(&List::integers(4)).unwrap();
+    let mut deserializer = crate::Deserializer::new(&bytes, 4);
+    assert_eq!(
+        List::<usize>::deserialize(&mut deserializer),
+        Err(Error::ExceededContainerDepthLimit {
+            name: "List",
+            limit: 4
+        })
+    );
+}
+
+#[test]
+fn for_each_map_entry_streams_entries() {
+    let map: BTreeMap<u16, Vec<u8>> = (0..80).map(|i| (i * 3, vec![i as u8; 2])).collect();
+    let mut bytes = to_bytes(&map).unwrap();
+    let map_len = bytes.len();
+    bytes.extend_from_slice(&[1, 2, 3]);
+
+    let mut streamed = BTreeMap::new();
+    let rest = crate::for_each_map_entry(&bytes, |key: u16, value: Vec<u8>| {
+        streamed.insert(key, value);
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(streamed, map);
+    assert_eq!(rest, &bytes[map_len..]);
+
+    // Errors of the callback stop the decoding.
+    let mut calls = 0;
+    let err = crate::for_each_map_entry(&bytes, |key: u16, _: Vec<u8>| {
+        calls += 1;
+        if key == 6 {
+            return Err(Error::Custom("full".to_string()));
+        }
+        Ok(())
+    });
+    assert_eq!(err, Err(Error::Custom("full".to_string())));
+    assert_eq!(calls, 3);
+
+    // Keys are checked as for map types.
+    let unordered = vec![2, 1, 0, 0, 0];
+    let duplicated = vec![2, 0, 0, 0, 0];
+    for bytes in [unordered, duplicated] {
+        let mut keys = vec![];
+        assert_eq!(
+            crate::for_each_map_entry(&bytes, |key: u8, _: u8| {
+                keys.push(key);
+                Ok(())
+            }),
+            Err(Error::NonCanonicalMap {
+                index: 1,
+                offset: 3
+            })
+        );
+        assert_eq!(keys, vec![bytes[1]]);
+    }
+
+    // Impossible lengths are rejected up front, as for map types.
+    let forged = [0xff, 0xff, 0x03, 1, 2];
+    let err = Error::Eof {
+        expected: 0xffff,
+        available: 2,
+    };
+    assert_eq!(
+        crate::for_each_map_entry(&forged, |_: u8, _: u8| Ok(())),
+        Err(err.clone())
+    );
+    assert_eq!(
+        crate::for_each_map_entry_seed(
+            &forged,
+            |_: &u8| crate::ValueAction::Decode(core::marker::PhantomData::<u8>),
+            |_, _| Ok(())
+        ),
+        Err(err.clone())
+    );
+    assert_eq!(
+        crate::from_bytes_map_entries(&forged, |_, _: u8, _: u8| ()),
+        Err(err)
+    );
+}
+
+#[test]
+fn from_bytes_with_variant_names_outermost_variant() {
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    enum Outer {
+        Empty,
+        Wrapped(E),
+    }
+
+    let bytes = to_bytes(&Outer::Wrapped(E::Tuple(1, 2))).unwrap();
+    assert_eq!(
+        crate::from_bytes_with_variant::<Outer>(&bytes),
+        Ok((Outer::Wrapped(E::Tuple(1, 2)), "Wrapped"))
+    );
+    assert_eq!(
+        crate::from_bytes_with_variant::<Box<E>>(&[3, 1, 0, 0, 0]),
+        Ok((Box::new(E::Struct { a: 1 }), "Struct"))
+    );
+
+    let err = crate::from_bytes_with_variant::<Outer>(&[2]).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid value: integer `2`, expected a valid variant index"
+    );
+    assert_eq!(
+        crate::from_bytes_with_variant::<Outer>(&[0, 0]),
+        Err(Error::RemainingInput { len: 1, first: 0 })
+    );
+
+    // Enums nested in structs are not reported.
+    #[derive(Debug, Deserialize)]
+    struct Envelope(#[allow(dead_code)] Outer);
+
+    assert!(matches!(
+        crate::from_bytes_with_variant::<Envelope>(&[0]),
+        Err(Error::NotSupported(_))
+    ));
+    assert!(matches!(
+        crate::from_bytes_with_variant::<u8>(&[0]),
+        Err(Error::NotSupported(_))
+    ));
+
+    // Nor are enums in sequences, options, or tuples.
+    let not_an_enum =
+        Error::NotSupported("from_bytes_with_variant: the decoded type is not an enum");
+    assert_eq!(
+        crate::from_bytes_with_variant::<Vec<Outer>>(&[1, 0]).unwrap_err(),
+        not_an_enum
+    );
+    assert_eq!(
+        crate::from_bytes_with_variant::<Option<Outer>>(&[1, 0]).unwrap_err(),
+        not_an_enum
+    );
+    assert_eq!(
+        crate::from_bytes_with_variant::<(Outer, Outer)>(&[0, 0]).unwrap_err(),
+        not_an_enum
+    );
+}
+
+#[test]
+fn public_writers() {
+    use crate::io::{CountingWriter, VecWriter, Write};
+
+    let value = (vec![1u32, 2, 3], Some("four".to_string()));
+
+    let mut bytes = vec![0xaa];
+    let mut writer = VecWriter::new(&mut bytes);
+    crate::serialize_into(&mut writer, &value).unwrap();
+    crate::serialize_into(&mut writer, &5u8).unwrap();
+    assert_eq!(writer.get_ref()[0], 0xaa);
+    let mut expected = vec![0xaa];
+    expected.extend(to_bytes(&value).unwrap());
+    expected.push(5);
+    assert_eq!(bytes, expected);
+
+    let mut counter = CountingWriter::new();
+    crate::serialize_into(&mut counter, &value).unwrap();
+    assert_eq!(counter.count(), serialized_size(&value).unwrap());
+    counter.write_all(&[0; 3]).unwrap();
+    assert_eq!(counter.count(), serialized_size(&value).unwrap() + 3);
+
+    /// A sink splitting its input into fixed-size chunks, like a network buffer.
+    struct Chunks(Vec<Vec<u8>>);
+
+    impl Write for Chunks {
+        fn write(&mut self, buf: &[u8]) -> crate::io::Result<usize> {
+            match self.0.last_mut() {
+                Some(chunk) if chunk.len() < 4 => {
+                    let len = core::cmp::min(4 - chunk.len(), buf.len());
+                    chunk.extend_from_slice(&buf[..len]);
+                    Ok(len)
+                }
+                _ => {
+                    self.0.push(vec![]);
+                    self.write(buf)
+                }
+            }
+        }
+
+        fn flush(&mut self) -> crate::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut chunks = Chunks(vec![]);
+    crate::serialize_into(&mut chunks, &value).unwrap();
+    assert!(chunks.0.iter().all(|chunk| chunk.len() <= 4));
+    assert_eq!(chunks.0.concat(), to_bytes(&value).unwrap());
+}
+
+/// Values on either side of each ULEB128 continuation boundary, with their canonical encoding.
+const ULEB128_BOUNDARIES: [(u32, &[u8]); 10] = [
+    (0, &[0x00]),
+    (127, &[0x7f]),
+    (128, &[0x80, 0x01]),
+    (16383, &[0xff, 0x7f]),
+    (16384, &[0x80, 0x80, 0x01]),
+    (2097151, &[0xff, 0xff, 0x7f]),
+    (2097152, &[0x80, 0x80, 0x80, 0x01]),
+    (268435455, &[0xff, 0xff, 0xff, 0x7f]),
+    (268435456, &[0x80, 0x80, 0x80, 0x80, 0x01]),
+    (u32::MAX, &[0xff, 0xff, 0xff, 0xff, 0x0f]),
+];
+
+#[test]
+fn uleb128_boundaries_of_variant_indices() {
+    /// A unit variant with an arbitrary index.
+    #[derive(Debug, PartialEq)]
+    struct VariantIndex(u32);
+
+    impl Serialize for VariantIndex {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_unit_variant("VariantIndex", self.0, "")
+        }
+    }
+
+    impl<'de> Deserialize<'de> for VariantIndex {
+        fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            struct IndexVisitor;
+
+            impl<'de> serde::de::Visitor<'de> for IndexVisitor {
+                type Value = VariantIndex;
+
+                fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                    formatter.write_str("a unit variant")
+                }
+
+                fn visit_enum<A: serde::de::EnumAccess<'de>>(
+                    self,
+                    data: A,
+                ) -> Result<VariantIndex, A::Error> {
+                    use serde::de::VariantAccess;
+                    let (index, variant) = data.variant::<u32>()?;
+                    variant.unit_variant()?;
+                    Ok(VariantIndex(index))
+                }
+            }
+
+            deserializer.deserialize_enum("VariantIndex", &[], IndexVisitor)
+        }
+    }
+
+    for (index, encoding) in ULEB128_BOUNDARIES {
+        assert_eq!(to_bytes(&VariantIndex(index)).unwrap(), encoding);
+        assert_eq!(from_bytes(encoding), Ok(VariantIndex(index)));
+        assert_eq!(from_bytes_via_reader(encoding), Ok(VariantIndex(index)));
+        assert_eq!(crate::uleb128::encoded_len(index), encoding.len());
+    }
+}
+
+#[test]
+fn uleb128_boundaries_of_lengths() {
+    /// Only the length prefix of a sequence.
+    struct LengthPrefix(usize);
+
+    impl Serialize for LengthPrefix {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            use serde::ser::SerializeSeq;
+            serializer.serialize_seq(Some(self.0))?.end()
+        }
+    }
+
+    for (len, encoding) in ULEB128_BOUNDARIES {
+        let len = len as usize;
+        if len > MAX_SEQUENCE_LENGTH {
+            assert_eq!(
+                to_bytes(&LengthPrefix(len)),
+                Err(Error::ExceededMaxLen {
+                    len,
+                    max: MAX_SEQUENCE_LENGTH
+                })
+            );
+            continue;
+        }
+        assert_eq!(to_bytes(&LengthPrefix(len)).unwrap(), encoding);
+        assert_eq!(crate::peek_length(encoding), Ok((len, &[][..])));
+        if len <= 16384 {
+            // Zero-sized elements take no space, so the prefix is the whole encoding.
+            assert_eq!(to_bytes(&vec![(); len]).unwrap(), encoding);
+            assert_eq!(from_bytes::<Vec<()>>(encoding).unwrap().len(), len);
+            assert_eq!(
+                from_bytes_via_reader::<Vec<()>>(encoding).unwrap().len(),
+                len
+            );
+        }
+    }
+}
+
+#[test]
+fn unsupported_primitives_suggest_replacements() {
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    struct Reading {
+        id: u32,
+        value: f64,
+    }
+
+    let err = from_bytes::<Reading>(&[1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]).unwrap_err();
+    assert!(matches!(err, Error::NotSupported(msg) if msg.starts_with("deserialize_f64")));
+    assert!(err
+        .to_string()
+        .contains("use an integer or fixed-point field"));
+
+    let err = from_bytes::<f32>(&[0; 4]).unwrap_err();
+    assert!(err.to_string().contains("BCS has no floating-point types"));
+
+    let err = from_bytes::<char>(&[b'c']).unwrap_err();
+    assert!(matches!(err, Error::NotSupported(msg) if msg.starts_with("deserialize_char")));
+    assert!(err.to_string().contains("`String` or `u32`"));
+}
+
+#[test]
+fn from_slice_reader_borrows_from_the_underlying_buffer() {
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Frame<'a> {
+        kind: u8,
+        #[serde(borrow)]
+        payload: Bytes<'a>,
+        name: &'a str,
+    }
+
+    let bytes = to_bytes(&(3u8, vec![9u8, 8, 7], "frame")).unwrap();
+    let frame = {
+        let mut reader = &bytes[..];
+        let frame: Frame = crate::from_slice_reader(&mut reader).unwrap();
+        assert!(reader.is_empty());
+        frame
+    };
+    assert_eq!(frame.kind, 3);
+    assert_eq!(&*frame.payload, &[9, 8, 7]);
+    assert_eq!(frame.name, "frame");
+    assert!(bytes.as_ptr_range().contains(&frame.name.as_ptr()));
+
+    // Same outcome as `from_reader` for owned types.
+    let mut reader = &bytes[..];
+    let owned: (u8, Vec<u8>, String) = crate::from_reader(&mut reader).unwrap();
+    let mut reader = &bytes[..];
+    assert_eq!(
+        crate::from_slice_reader::<(u8, Vec<u8>, String)>(&mut reader).unwrap(),
+        owned
+    );
+
+    // Trailing bytes are rejected and the reader is left untouched.
+    let mut padded = bytes.clone();
+    padded.push(0);
+    let mut reader = &padded[..];
+    assert_eq!(
+        crate::from_slice_reader::<Frame>(&mut reader),
+        Err(Error::RemainingInput { len: 1, first: 0 })
+    );
+    assert_eq!(reader.len(), padded.len());
+}
+
+#[test]
+fn non_canonical_map_reports_the_offending_entry() {
+    // Entries 0..=2 are sorted; entry 3 repeats the key of entry 2.
+    let seq = vec![5, 1, 0, 2, 0, 4, 0, 4, 0, 9, 0];
+    let err = from_bytes::<BTreeMap<u8, u8>>(&seq).unwrap_err();
+    assert_eq!(
+        err,
+        Error::NonCanonicalMap {
+            index: 3,
+            offset: 7
+        }
+    );
+    assert_eq!(from_bytes_via_reader::<BTreeMap<u8, u8>>(&seq), Err(err));
+
+    // The index is local to the map that failed, not to the outer sequence, while the offset
+    // is in the whole input.
+    let mut outer = vec![2, 1, 3, 0];
+    outer.extend_from_slice(&seq);
+    let err = Error::NonCanonicalMap {
+        index: 3,
+        offset: 11,
+    };
+    assert_eq!(
+        from_bytes::<Vec<BTreeMap<u8, u8>>>(&outer),
+        Err(err.clone())
+    );
+    assert_eq!(
+        from_bytes_via_reader::<Vec<BTreeMap<u8, u8>>>(&outer),
+        Err(err.clone())
+    );
+    assert_eq!(
+        err.to_string(),
+        "keys of serialized maps must be unique and in increasing order, but entry 3 at byte 11 is not"
+    );
+}
+
+#[test]
+fn to_bytes_le_slice_matches_to_bytes() {
+    fn check<T: crate::array::LeInteger + Serialize>(slice: &[T]) {
+        assert_eq!(
+            crate::to_bytes_le_slice(slice).unwrap(),
+            to_bytes(slice).unwrap()
+        );
+    }
+
+    check::<u64>(&[]);
+    check(&[0xabu8; 300]);
+    check(
+        &(0..200u16)
+            .map(|i| i.wrapping_mul(0x1234))
+            .collect::<Vec<_>>(),
+    );
+    check(&[u32::MAX, 0, 1, 0x0102_0304]);
+    check(&(0..1000u64).map(|i| i << 40 | i).collect::<Vec<_>>());
+    check(&[u128::MAX, 1 << 100]);
+    check(&[i8::MIN, -1, 0, i8::MAX]);
+    check(&[i16::MIN, -2]);
+    check(&[i32::MIN, -3]);
+    check(&[i64::MIN, -4]);
+    check(&[i128::MIN, -5]);
+
+    let amounts = vec![7u64; 129];
+    assert_eq!(
+        from_bytes::<Vec<u64>>(&crate::to_bytes_le_slice(&amounts).unwrap()).unwrap(),
+        amounts
+    );
+}
+
+/// Decodes arbitrarily nested sequences, e.g. `[[[]]]`, returning their depth.
+struct NestedSeqs;
+
+impl<'de> serde::de::DeserializeSeed<'de> for NestedSeqs {
+    type Value = usize;
+
+    fn deserialize<D: serde::Deserializer<'de>>(self, deserializer: D) -> Result<usize, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> serde::de::Visitor<'de> for NestedSeqs {
+    type Value = usize;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("nested sequences")
+    }
+
+    fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<usize, A::Error> {
+        let mut depth = 0;
+        while let Some(inner) = seq.next_element_seed(NestedSeqs)? {
+            depth = core::cmp::max(depth, inner);
+        }
+        Ok(depth + 1)
+    }
+}
+
+#[test]
+fn sequence_nesting_is_bounded() {
+    let nested = |depth: usize| {
+        let mut bytes = vec![1; depth - 1];
+        bytes.push(0);
+        bytes
+    };
+
+    let bytes = nested(MAX_CONTAINER_DEPTH);
+    assert_eq!(
+        crate::from_bytes_seed(NestedSeqs, &bytes),
+        Ok(MAX_CONTAINER_DEPTH)
+    );
+
+    let bytes = nested(MAX_CONTAINER_DEPTH + 1);
+    let err = Error::ExceededSequenceDepthLimit {
+        limit: MAX_CONTAINER_DEPTH,
+    };
+    assert_eq!(crate::from_bytes_seed(NestedSeqs, &bytes), Err(err.clone()));
+    assert_eq!(
+        crate::from_reader_seed(NestedSeqs, &mut &bytes[..]),
+        Err(err.clone())
+    );
+    assert_eq!(
+        err.to_string(),
+        format!(
+            "sequence nesting exceeded depth limit {}",
+            MAX_CONTAINER_DEPTH
+        )
+    );
+
+    // Maps count as sequences, and the bound does not depend on the named container limit.
+    let value = vec![BTreeMap::from([(1u8, vec![vec![2u8]])])];
+    let bytes = to_bytes(&value).unwrap();
+    assert_eq!(from_bytes_with_limit(&bytes, 0), Ok(value));
+}
+
+#[cfg(feature = "envelope")]
+#[test]
+fn envelope_round_trip_and_corruption() {
+    use crate::envelope;
+
+    let value = (42u32, "payload".to_owned(), vec![1u8, 2, 3]);
+    let bytes = envelope::encode(7, &value).unwrap();
+    let payload = to_bytes(&value).unwrap();
+    assert_eq!(bytes.len(), payload.len() + 5);
+    assert_eq!(bytes[0], 7);
+    assert_eq!(&bytes[1..bytes.len() - 4], &payload[..]);
+    assert_eq!(envelope::decode(&bytes), Ok((7, value.clone())));
+
+    // The CRC32 of "123456789" is the standard check value of the algorithm.
+    let bytes = envelope::encode(0, b"123456789").unwrap();
+    assert_eq!(bytes[10..], 0xcbf4_3926u32.to_le_bytes());
+
+    let mut corrupted = envelope::encode(7, &value).unwrap();
+    corrupted[3] ^= 0x80;
+    assert!(matches!(
+        envelope::decode::<(u32, String, Vec<u8>)>(&corrupted),
+        Err(Error::ChecksumMismatch { .. })
+    ));
+    let last = corrupted.len() - 1;
+    let mut corrupted = envelope::encode(7, &value).unwrap();
+    corrupted[last] ^= 1;
+    assert!(matches!(
+        envelope::decode::<(u32, String, Vec<u8>)>(&corrupted),
+        Err(Error::ChecksumMismatch { expected, found }) if expected ^ found == 1 << 24
+    ));
+
+    assert_eq!(
+        envelope::decode::<()>(&[1, 0, 0, 0]),
+        Err(Error::Eof {
+            expected: 5,
+            available: 4
+        })
+    );
+    // An empty payload is only valid for types with an empty encoding.
+    assert_eq!(
+        envelope::decode(&envelope::encode(3, &()).unwrap()),
+        Ok((3, ()))
+    );
+}
+
+#[test]
+fn from_dyn_reader_matches_from_reader() {
+    let value = (vec![1u32, 2, 3], BTreeMap::from([(1u8, "one".to_owned())]));
+    let bytes = to_bytes(&value).unwrap();
+
+    let mut reader = crate::io::Cursor::new(bytes.clone());
+    let dyn_reader: &mut dyn crate::io::Read = &mut reader;
+    assert_eq!(crate::from_dyn_reader(dyn_reader), Ok(value.clone()));
+
+    let mut padded = bytes.clone();
+    padded.push(9);
+    let mut reader = &padded[..];
+    assert_eq!(
+        crate::from_dyn_reader::<(Vec<u32>, BTreeMap<u8, String>)>(&mut reader),
+        Err(Error::RemainingInput { len: 1, first: 9 })
+    );
+
+    let mut reader = &bytes[..bytes.len() - 1];
+    assert_eq!(
+        crate::from_dyn_reader::<(Vec<u32>, BTreeMap<u8, String>)>(&mut reader),
+        from_reader::<(Vec<u32>, BTreeMap<u8, String>)>(&bytes[..bytes.len() - 1])
+    );
+}
+
+/// Records the `size_hint` of a sequence or map of `u16` before each element and at the end.
+struct SizeHints;
+
+impl<'de> serde::de::Visitor<'de> for SizeHints {
+    type Value = Vec<Option<usize>>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a sequence or map of u16")
+    }
+
+    fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut hints = vec![seq.size_hint()];
+        while seq.next_element::<u16>()?.is_some() {
+            hints.push(seq.size_hint());
+        }
+        Ok(hints)
+    }
+
+    fn visit_map<A: serde::de::MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut hints = vec![map.size_hint()];
+        while map.next_entry::<u16, u16>()?.is_some() {
+            hints.push(map.size_hint());
+        }
+        Ok(hints)
+    }
+}
+
+#[test]
+fn size_hints_are_exact() {
+    struct Hinted<const KIND: u8>(Vec<Option<usize>>);
+
+    impl<'de, const KIND: u8> Deserialize<'de> for Hinted<KIND> {
+        fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            match KIND {
+                0 => deserializer.deserialize_seq(SizeHints),
+                1 => deserializer.deserialize_map(SizeHints),
+                2 => deserializer.deserialize_tuple(3, SizeHints),
+                _ => deserializer.deserialize_struct("Point", &["x", "y"], SizeHints),
+            }
+            .map(Hinted)
+        }
+    }
+
+    fn check<const KIND: u8>(bytes: &[u8], len: usize) {
+        let expected: Vec<_> = (0..=len).rev().map(Some).collect();
+        assert_eq!(from_bytes::<Hinted<KIND>>(bytes).unwrap().0, expected);
+        assert_eq!(
+            from_bytes_via_reader::<Hinted<KIND>>(bytes).unwrap().0,
+            expected
+        );
+    }
+
+    for len in [0, 1, 5, 200] {
+        check::<0>(&to_bytes(&vec![7u16; len]).unwrap(), len);
+        let map: BTreeMap<u16, u16> = (0..len as u16).map(|i| (i, i)).collect();
+        check::<1>(&to_bytes(&map).unwrap(), len);
+    }
+    check::<2>(&to_bytes(&(1u16, 2u16, 3u16)).unwrap(), 3);
+    check::<3>(&to_bytes(&(1u16, 2u16)).unwrap(), 2);
+}
+
+#[test]
+fn eof_inside_option_value_is_tagged() {
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Account {
+        id: u32,
+        balance: Option<u64>,
+        memo: Option<Option<String>>,
+    }
+
+    let account = Account {
+        id: 1,
+        balance: Some(10),
+        memo: Some(Some("hello".to_owned())),
+    };
+    let bytes = to_bytes(&account).unwrap();
+    let inner_eof = Error::EofInOption(Box::new(Error::Eof {
+        expected: 5,
+        available: 2,
+    }));
+
+    // Truncated inside the nested string: only the innermost option is recorded.
+    let truncated = &bytes[..bytes.len() - 3];
+    assert_eq!(from_bytes::<Account>(truncated), Err(inner_eof.clone()));
+    assert_eq!(
+        inner_eof.to_string(),
+        "in the value of an option: unexpected end of input: expected 5 byte(s), found 2"
+    );
+    assert!(matches!(
+        from_bytes_via_reader::<Account>(truncated),
+        Err(Error::EofInOption(err)) if matches!(*err, Error::Eof { .. })
+    ));
+
+    // Truncated right after a `Some` tag.
+    assert!(matches!(
+        from_bytes::<Account>(&bytes[..5]),
+        Err(Error::EofInOption(_))
+    ));
+
+    // A missing length prefix is tagged too, from slices and readers alike, including readers
+    // reporting the end of their input as an error.
+    struct Exhausted;
+
+    impl std::io::Read for Exhausted {
+        fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+            Err(std::io::ErrorKind::UnexpectedEof.into())
+        }
+    }
+
+    let missing_len = Err(Error::EofInOption(Box::new(Error::Eof {
+        expected: 1,
+        available: 0,
+    })));
+    assert_eq!(from_bytes::<Option<String>>(&[1]), missing_len);
+    assert_eq!(from_bytes_via_reader::<Option<String>>(&[1]), missing_len);
+    assert_eq!(
+        from_reader::<Option<String>>(std::io::Read::chain(&[1][..], Exhausted)),
+        missing_len
+    );
+
+    // Other errors and truncated tags are reported as before.
+    assert!(matches!(
+        from_bytes::<Account>(&bytes[..4]),
+        Err(Error::Eof { .. })
+    ));
+    let mut bad = bytes.clone();
+    bad[13] = 2;
+    assert_eq!(from_bytes::<Account>(&bad), Err(Error::ExpectedOption(2)));
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+enum Payload<'a> {
+    Empty,
+    Text(&'a str),
+    Blob(#[serde(borrow)] Bytes<'a>),
+    Pair(u128, [u8; 3]),
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+struct Adversarial<'a> {
+    flag: bool,
+    id: u64,
+    #[serde(borrow)]
+    payloads: Vec<Payload<'a>>,
+    index: BTreeMap<u16, Option<String>>,
+    tail: Option<(u8, &'a [u8])>,
+}
+
+fn adversarial_sample() -> Vec<u8> {
+    let value = Adversarial {
+        flag: true,
+        id: u64::MAX - 1,
+        payloads: vec![
+            Payload::Empty,
+            Payload::Text("text"),
+            Payload::Blob(Bytes::new(&[1, 2, 3, 4])),
+            Payload::Pair(1 << 100, [5, 6, 7]),
+        ],
+        index: BTreeMap::from([(1, None), (300, Some("x".to_owned()))]),
+        tail: Some((9, &[10, 11])),
+    };
+    to_bytes(&value).unwrap()
+}
+
+#[test]
+fn truncated_inputs_fail_without_panicking() {
+    let bytes = adversarial_sample();
+    assert!(from_bytes::<Adversarial>(&bytes).is_ok());
+    for len in 0..bytes.len() {
+        let truncated = &bytes[..len];
+        let err = from_bytes::<Adversarial>(truncated).unwrap_err();
+        assert!(
+            matches!(err, Error::Eof { .. } | Error::EofInOption(_)),
+            "prefix of {len} bytes: {err:?}"
+        );
+        assert!(crate::try_from_bytes::<Adversarial>(truncated).is_err());
+    }
+    // Empty inputs for every primitive entry point of the slice deserializer.
+    assert!(from_bytes::<u8>(&[]).is_err());
+    assert!(from_bytes::<bool>(&[]).is_err());
+    assert!(from_bytes::<&str>(&[]).is_err());
+    assert!(from_bytes::<&[u8]>(&[]).is_err());
+    assert!(from_bytes::<[u8; 4]>(&[]).is_err());
+    assert!(from_bytes::<Payload>(&[]).is_err());
+}
+
+proptest! {
+    #[test]
+    fn proptest_mutated_inputs_never_panic(
+        edits in prop::collection::vec((any::<prop::sample::Index>(), any::<u8>()), 1..4),
+        cut in any::<prop::sample::Index>(),
+    ) {
+        let mut bytes = adversarial_sample();
+        for (index, byte) in edits {
+            let i = index.index(bytes.len());
+            bytes[i] = byte;
+        }
+        bytes.truncate(cut.index(bytes.len() + 1));
+        let _ = from_bytes::<Adversarial>(&bytes);
+        let _ = from_bytes_via_reader::<(bool, u64, Vec<Option<String>>)>(&bytes);
+    }
+
+    #[test]
+    fn proptest_random_inputs_never_panic(bytes in prop::collection::vec(any::<u8>(), 0..64)) {
+        let _ = from_bytes::<Adversarial>(&bytes);
+        let _ = from_bytes::<(String, Vec<u64>, Option<[u8; 2]>)>(&bytes);
+        let _ = from_bytes_via_reader::<(bool, u64, Vec<Option<String>>)>(&bytes);
+    }
+}
+
+#[test]
+fn read_stream_deserializer_stops_at_value_boundaries() {
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Record {
+        id: u64,
+        tags: Vec<String>,
+        parent: Option<u64>,
+    }
+
+    let records: Vec<Record> = (0..5)
+        .map(|id| Record {
+            id,
+            tags: vec!["t".repeat(id as usize); id as usize],
+            parent: id.checked_sub(1),
+        })
+        .collect();
+    let mut file = Vec::new();
+    for record in &records {
+        file.extend(to_bytes(record).unwrap());
+    }
+
+    // Clean end of input at a value boundary.
+    let decoded: Result<Vec<Record>, _> =
+        crate::ReadStreamDeserializer::new(crate::io::Cursor::new(file.clone())).collect();
+    assert_eq!(decoded.unwrap(), records);
+    assert_eq!(
+        crate::ReadStreamDeserializer::<_, Record>::new(&[][..]).count(),
+        0
+    );
+
+    // Every truncation inside a value yields exactly one error after the complete values.
+    let boundaries: Vec<usize> = records
+        .iter()
+        .scan(0, |end, record| {
+            *end += to_bytes(record).unwrap().len();
+            Some(*end)
+        })
+        .collect();
+    for len in 0..file.len() {
+        let results: Vec<_> =
+            crate::ReadStreamDeserializer::<_, Record>::new(&file[..len]).collect();
+        let complete = boundaries.iter().filter(|&&end| end <= len).count();
+        assert!(results[..complete].iter().all(Result::is_ok));
+        if boundaries.contains(&len) || len == 0 {
+            assert_eq!(results.len(), complete);
+        } else {
+            assert_eq!(results.len(), complete + 1);
+            assert!(results[complete].is_err());
+        }
+    }
+
+    // The reader is left right after the last value, so other data can follow.
+    let mut input = to_bytes(&7u16).unwrap();
+    input.extend(b"tail");
+    let mut stream = crate::ReadStreamDeserializer::<_, u16>::new(&input[..]);
+    assert_eq!(stream.next(), Some(Ok(7)));
+    assert_eq!(stream.into_inner(), b"tail");
+
+    // Zero-sized values cannot be delimited.
+    let mut stream = crate::ReadStreamDeserializer::<_, ()>::new(&[1u8][..]);
+    assert!(matches!(stream.next(), Some(Err(Error::NotSupported(_)))));
+    assert!(stream.next().is_none());
+}
+
+#[test]
+fn serialize_map_from_unsorted_matches_maps() {
+    // Keys without `Ord`, compared only through their BCS bytes.
+    #[derive(Serialize)]
+    struct Key(Pair);
+    #[derive(Serialize)]
+    struct Pair(u32, String);
+
+    let entries = vec![
+        (Key(Pair(2, "b".to_owned())), vec![1u8]),
+        (Key(Pair(1, "z".to_owned())), vec![]),
+        (Key(Pair(1, "a".to_owned())), vec![2, 3]),
+    ];
+    let mut output = Vec::new();
+    crate::serialize_map_from_unsorted(&mut output, entries).unwrap();
+
+    let map = BTreeMap::from([
+        ((2u32, "b"), vec![1u8]),
+        ((1, "z"), vec![]),
+        ((1, "a"), vec![2, 3]),
+    ]);
+    assert_eq!(output, to_bytes(&map).unwrap());
+
+    // Integer keys are ordered by their little-endian bytes, like in any BCS map.
+    let entries: Vec<(u16, u8)> = (0..600u16).rev().map(|k| (k, k as u8)).collect();
+    let mut output = Vec::new();
+    crate::serialize_map_from_unsorted(&mut output, entries.clone()).unwrap();
+    let map: BTreeMap<u16, u8> = entries.into_iter().collect();
+    assert_eq!(output, to_bytes(&map).unwrap());
+
+    let mut output = Vec::new();
+    crate::serialize_map_from_unsorted(&mut output, Vec::<(u8, u8)>::new()).unwrap();
+    assert_eq!(output, vec![0]);
+
+    assert_eq!(
+        crate::serialize_map_from_unsorted(&mut Vec::new(), vec![(5u8, 1u8), (3, 2), (5, 3)]),
+        Err(Error::DuplicateMapKey)
+    );
+    assert_eq!(
+        crate::serialize_map_from_unsorted(&mut Vec::new(), vec![(1u8, 1.5f32)]),
+        Err(Error::NotSupported("serialize_f32"))
+    );
+}
+
+#[test]
+fn unit_like_types_take_zero_bytes() {
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Unit;
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct EmptyTuple();
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct EmptyBraced {}
+
+    fn check<T>(value: T)
+    where
+        T: fmt::Debug + PartialEq + Serialize + DeserializeOwned,
+    {
+        assert_eq!(to_bytes(&value).unwrap(), Vec::<u8>::new());
+        assert_eq!(serialized_size(&value).unwrap(), 0);
+        assert_eq!(from_bytes::<T>(&[]).unwrap(), value);
+        assert_eq!(from_bytes_via_reader::<T>(&[]).unwrap(), value);
+        assert_eq!(
+            from_bytes::<T>(&[0]),
+            Err(Error::RemainingInput { len: 1, first: 0 })
+        );
+        assert_eq!(
+            from_bytes_via_reader::<T>(&[0]),
+            Err(Error::RemainingInput { len: 1, first: 0 })
+        );
+        assert_eq!(
+            crate::try_from_bytes::<T>(&[7, 8]).unwrap(),
+            (from_bytes::<T>(&[]).unwrap(), 0)
+        );
+        // As an option, only the tag is present.
+        assert_eq!(to_bytes(&Some(&value)).unwrap(), vec![1]);
+        assert_eq!(from_bytes::<Option<T>>(&[1]).unwrap(), Some(value));
+    }
+
+    check(());
+    check(core::marker::PhantomData::<u64>);
+    check(Unit);
+    check(EmptyTuple());
+    check(EmptyBraced {});
+    check(((), Unit, ((), core::marker::PhantomData::<String>)));
+
+    // Unit-like fields do not shift their neighbours.
+    assert_eq!(to_bytes(&(1u8, (), Unit, 2u8)).unwrap(), vec![1, 2]);
+}
+
+#[test]
+fn fixed_size_matches_serialized_size() {
+    use crate::FixedSize;
+
+    fn check<T: FixedSize + Serialize>(value: T) {
+        assert_eq!(serialized_size(&value).unwrap(), T::ENCODED_SIZE);
+    }
+
+    check(true);
+    check(7u8);
+    check(-7i16);
+    check(u32::MAX);
+    check(1u64 << 60);
+    check(-1i128);
+    check(crate::int::U256::from(5u128));
+    check(crate::int::U512::from(5u128));
+    check([0u16; 32]);
+    check([[1u8; 4]; 3]);
+    check(());
+    check(core::marker::PhantomData::<Vec<u8>>);
+    check((1u8, (2u64, [3i32; 2]), false));
+    check((
+        0u8, 1u8, 2u8, 3u8, 4u8, 5u8, 6u8, 7u8, 8u8, 9u8, 10u8, 11u8, 12u8, 13u8, 14u8, 15u8,
+    ));
+}
+
+#[cfg(feature = "derive")]
+#[test]
+fn derived_fixed_size_matches_serialized_size() {
+    use crate::{BcsFixedSize, FixedSize};
+
+    #[derive(Serialize, BcsFixedSize)]
+    struct Header {
+        version: u8,
+        #[serde(rename = "parent_hash")]
+        parent: [u8; 32],
+        height: u64,
+    }
+
+    #[derive(Serialize, BcsFixedSize)]
+    struct Signed<T>(T, [[u8; 32]; 2]);
+
+    let header = Header {
+        version: 1,
+        parent: [7; 32],
+        height: 9,
+    };
+    assert_eq!(Header::ENCODED_SIZE, 41);
+    assert_eq!(serialized_size(&header).unwrap(), Header::ENCODED_SIZE);
+    let signed = Signed(header, [[0; 32]; 2]);
+    assert_eq!(<Signed<Header>>::ENCODED_SIZE, 105);
+    let bytes = to_bytes_with_capacity(&signed, <Signed<Header>>::ENCODED_SIZE).unwrap();
+    assert_eq!(bytes.len(), bytes.capacity());
+}
+
+#[test]
+fn errors_are_classified() {
+    let truncated = [
+        Error::Eof {
+            expected: 2,
+            available: 1,
+        },
+        Error::EofInOption(Box::new(Error::Eof {
+            expected: 8,
+            available: 0,
+        })),
+        Error::from(crate::io::Error::from(crate::io::ErrorKind::UnexpectedEof)),
+    ];
+    for err in &truncated {
+        assert!(err.is_truncated(), "{err:?}");
+        assert!(err.is_eof(), "{err:?}");
+        assert!(!err.is_malformed(), "{err:?}");
+    }
+    assert!(!Error::MissingLen.is_truncated());
+
+    let malformed = [
+        from_bytes::<bool>(&[2]).unwrap_err(),
+        from_bytes::<Option<u8>>(&[2]).unwrap_err(),
+        from_bytes::<String>(&[1, 0xff]).unwrap_err(),
+        from_bytes::<Vec<u8>>(&[0x80, 0]).unwrap_err(),
+        from_bytes::<BTreeMap<u8, u8>>(&[2, 1, 0, 1, 0]).unwrap_err(),
+        from_bytes::<u8>(&[1, 2]).unwrap_err(),
+        from_bytes::<E>(&[9]).unwrap_err(),
+        from_bytes::<E>(&[0xff, 0xff, 0xff, 0xff, 0x7f]).unwrap_err(),
+        from_bytes_with_limit::<List<u8>>(&to_bytes(&List::integers(3)).unwrap(), 2)
+            .map(|_| ())
+            .unwrap_err(),
+    ];
+    for err in &malformed {
+        assert!(err.is_malformed(), "{err:?}");
+        assert!(!err.is_truncated(), "{err:?}");
+    }
+    assert_eq!(
+        malformed[7],
+        Error::MalformedVariantIndex { name: "E" },
+        "a variant index overflowing a u32 is malformed"
+    );
+
+    for err in [
+        Error::from(crate::io::Error::from(crate::io::ErrorKind::WouldBlock)),
+        Error::NotSupported("deserialize_any"),
+        Error::DuplicateMapKey,
+    ] {
+        assert!(!err.is_truncated() && !err.is_malformed(), "{err:?}");
+    }
+}
+
+#[test]
+fn flatten_is_unsupported() {
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Inner {
+        a: u8,
+        b: u16,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Outer {
+        x: u8,
+        #[serde(flatten)]
+        inner: Inner,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Nested {
+        x: u8,
+        inner: Inner,
+    }
+
+    let value = Outer {
+        x: 1,
+        inner: Inner { a: 2, b: 3 },
+    };
+    assert_eq!(to_bytes(&value), Err(Error::FlattenUnsupported));
+    assert_eq!(serialized_size(&value), Err(Error::FlattenUnsupported));
+    // The map keyed by field name that serde would encode is rejected as well.
+    let bytes = [3, 1, b'a', 2, 1, b'b', 3, 0, 1, b'x', 1];
+    assert_eq!(from_bytes::<Outer>(&bytes), Err(Error::FlattenUnsupported));
+    assert_eq!(
+        from_bytes_via_reader::<Outer>(&bytes),
+        Err(Error::FlattenUnsupported)
+    );
+
+    // Nesting the struct instead encodes its fields in place.
+    let nested = Nested {
+        x: 1,
+        inner: Inner { a: 2, b: 3 },
+    };
+    let bytes = to_bytes(&nested).unwrap();
+    assert_eq!(bytes, vec![1, 2, 3, 0]);
+    assert_eq!(from_bytes::<Nested>(&bytes).unwrap(), nested);
+
+    // Regular maps are still supported.
+    let map: BTreeMap<u8, u8> = [(1, 2)].into_iter().collect();
+    assert_eq!(
+        from_bytes::<BTreeMap<u8, u8>>(&to_bytes(&map).unwrap()).unwrap(),
+        map
+    );
+}
+
+#[test]
+fn maps_without_a_known_length_are_serialized() {
+    struct Filtered<'a>(&'a BTreeMap<u8, u8>);
+
+    impl Serialize for Filtered<'_> {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            // `Filter` has no exact size, so serde passes no length to `serialize_map`.
+            serializer.collect_map(self.0.iter().filter(|(key, _)| **key < 3))
+        }
+    }
+
+    let map: BTreeMap<u8, u8> = [(1, 5), (2, 1), (3, 7)].into_iter().collect();
+    assert_eq!(to_bytes(&Filtered(&map)).unwrap(), vec![2, 1, 5, 2, 1]);
+    assert_eq!(serialized_size(&Filtered(&map)).unwrap(), 5);
+
+    // String keys are not mistaken for the field names of a flattened struct.
+    struct FilteredNames<'a>(&'a BTreeMap<&'a str, u8>);
+
+    impl Serialize for FilteredNames<'_> {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_map(self.0.iter().filter(|(_, value)| **value > 1))
+        }
+    }
+
+    let names: BTreeMap<&str, u8> = [("a", 5), ("b", 1)].into_iter().collect();
+    assert_eq!(
+        to_bytes(&FilteredNames(&names)).unwrap(),
+        vec![1, 1, b'a', 5]
+    );
+}
+
+#[cfg(feature = "testing")]
+crate::roundtrip_test!(S);
+
+#[cfg(feature = "testing")]
+crate::roundtrip_test!(roundtrip_test_of_map: BTreeMap<u8, Vec<Option<u16>>>);
+
+#[test]
+#[cfg(feature = "testing")]
+#[should_panic(expected = "does not reproduce the decoded bytes")]
+fn roundtrip_test_detects_non_canonical_encodings() {
+    // Decoding drops `hint`, which equality ignores, so the decoded value re-encodes
+    // differently.
+    #[derive(Debug, Serialize)]
+    struct Lossy {
+        value: u8,
+        hint: u8,
+    }
+
+    impl PartialEq for Lossy {
+        fn eq(&self, other: &Self) -> bool {
+            self.value == other.value
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Lossy {
+        fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let (value, _) = <(u8, u8)>::deserialize(deserializer)?;
+            Ok(Lossy { value, hint: 0 })
+        }
+    }
+
+    impl Arbitrary for Lossy {
+        type Parameters = ();
+        type Strategy = BoxedStrategy<Self>;
+
+        fn arbitrary_with(_: ()) -> Self::Strategy {
+            (any::<u8>(), 1..=u8::MAX)
+                .prop_map(|(value, hint)| Lossy { value, hint })
+                .boxed()
+        }
+    }
+
+    crate::testing::assert_roundtrip_property::<Lossy>();
+}
+
+#[test]
+fn map_keys_are_borrowed_from_slices() {
+    let entries = [("alpha", 1u64), ("beta", 2), ("gamma", 3)];
+    let bytes = to_bytes(&entries.iter().copied().collect::<BTreeMap<_, _>>()).unwrap();
+    let input = bytes.as_ptr_range();
+
+    let map: BTreeMap<&str, u64> = from_bytes(&bytes).unwrap();
+    assert_eq!(map, entries.iter().copied().collect());
+    assert!(map.keys().all(|key| input.contains(&key.as_ptr())));
+
+    let map: HashMap<&[u8], u64> = from_bytes(&bytes).unwrap();
+    assert_eq!(map.len(), entries.len());
+    for (key, value) in entries {
+        assert_eq!(map[key.as_bytes()], value);
+    }
+    assert!(map.keys().all(|key| input.contains(&key.as_ptr())));
+}
+
+#[test]
+fn materialization_budget_bounds_decoded_data() {
+    use core::mem::size_of;
+
+    // 1000 empty strings: one byte each in the input, but a `String` each in memory.
+    let bytes = to_bytes(&vec![String::new(); 1000]).unwrap();
+    let limit = 1000 * size_of::<String>();
+    let config = Config::new().max_materialized_bytes(limit - 1);
+    assert_eq!(
+        from_bytes_with_config::<Vec<String>>(&bytes, &config),
+        Err(Error::ExceededMaterializationBudget { limit: limit - 1 })
+    );
+    assert_eq!(
+        from_reader_with_config::<Vec<String>>(&mut &bytes[..], &config),
+        Err(Error::ExceededMaterializationBudget { limit: limit - 1 })
+    );
+    let config = Config::new().max_materialized_bytes(limit);
+    assert_eq!(
+        from_bytes_with_config::<Vec<String>>(&bytes, &config)
+            .unwrap()
+            .len(),
+        1000
+    );
+
+    // Elements count their size in memory, not in the input.
+    let bytes = to_bytes(&vec![1u64; 100]).unwrap();
+    let config = Config::new().max_materialized_bytes(799);
+    assert_eq!(
+        from_bytes_with_config::<Vec<u64>>(&bytes, &config),
+        Err(Error::ExceededMaterializationBudget { limit: 799 })
+    );
+    let config = Config::new().max_materialized_bytes(800);
+    assert_eq!(
+        from_bytes_with_config::<Vec<u64>>(&bytes, &config).unwrap(),
+        vec![1; 100]
+    );
+
+    // Strings, byte arrays, sequences and maps all count, wherever they are nested: 3 bytes,
+    // 2 elements, 1 entry with a one-byte key and a `Vec` value, and 4 elements.
+    type Nested = (String, Vec<u8>, Option<BTreeMap<u8, Vec<u8>>>);
+    let value: Nested = (
+        "abc".to_owned(),
+        vec![1, 2],
+        Some([(1, vec![3; 4])].into_iter().collect()),
+    );
+    let bytes = to_bytes(&value).unwrap();
+    let limit = 3 + 2 + 1 + size_of::<Vec<u8>>() + 4;
+    let config = Config::new().max_materialized_bytes(limit);
+    assert_eq!(
+        from_reader_with_config::<Nested>(&mut &bytes[..], &config).unwrap(),
+        value
+    );
+    let config = Config::new().max_materialized_bytes(limit - 1);
+    let err = from_bytes_with_config::<Nested>(&bytes, &config).unwrap_err();
+    assert_eq!(
+        err,
+        Error::ExceededMaterializationBudget { limit: limit - 1 }
+    );
+    assert!(err.is_malformed());
+
+    // Strings borrowed from a slice allocate nothing, only the references to them count.
+    let bytes = to_bytes(&vec!["four", "five"]).unwrap();
+    let limit = 2 * size_of::<&str>();
+    let config = Config::new().max_materialized_bytes(limit);
+    assert_eq!(
+        from_bytes_with_config::<Vec<&str>>(&bytes, &config).unwrap(),
+        vec!["four", "five"]
+    );
+    assert_eq!(
+        from_bytes_with_config::<Vec<String>>(&bytes, &config),
+        Err(Error::ExceededMaterializationBudget { limit })
+    );
+
+    // Unbounded by default.
+    assert_eq!(Config::new(), Config::default());
+    from_bytes::<Vec<String>>(&to_bytes(&vec![String::new(); 1000]).unwrap()).unwrap();
+}
+
+proptest! {
+    #[test]
+    fn map_diff_matches_decoded_maps(
+        a in any::<BTreeMap<u16, Vec<u8>>>(),
+        b in any::<BTreeMap<u16, Vec<u8>>>(),
+    ) {
+        let (a_bytes, b_bytes) = (to_bytes(&a)?, to_bytes(&b)?);
+        use crate::Layout;
+
+        let value_layout = Layout::Seq(Box::new(Layout::U8));
+        let diff = crate::map_diff(&a_bytes, &b_bytes, &Layout::U16, &value_layout)?;
+
+        let decode = |bytes: &[u8]| from_bytes::<u16>(bytes).unwrap();
+        let decode_value = |bytes: &[u8]| from_bytes::<Vec<u8>>(bytes).unwrap();
+        // The diff follows the order of the serialized keys, not that of the integers.
+        let added: BTreeMap<_, _> = b
+            .iter()
+            .filter(|(k, _)| !a.contains_key(k))
+            .map(|(k, v)| (*k, v.clone()))
+            .collect();
+        let removed: BTreeMap<_, _> = a
+            .iter()
+            .filter(|(k, _)| !b.contains_key(k))
+            .map(|(k, v)| (*k, v.clone()))
+            .collect();
+        let changed: BTreeMap<_, _> = a
+            .iter()
+            .filter_map(|(k, v)| b.get(k).filter(|w| *w != v).map(|w| (*k, (v.clone(), w.clone()))))
+            .collect();
+        prop_assert_eq!(
+            diff.added.iter().map(|(k, v)| (decode(k), decode_value(v))).collect::<BTreeMap<_, _>>(),
+            added
+        );
+        prop_assert_eq!(
+            diff.removed.iter().map(|(k, v)| (decode(k), decode_value(v))).collect::<BTreeMap<_, _>>(),
+            removed
+        );
+        prop_assert_eq!(
+            diff.changed
+                .iter()
+                .map(|(k, v, w)| (decode(k), (decode_value(v), decode_value(w))))
+                .collect::<BTreeMap<_, _>>(),
+            changed
+        );
+        for entries in [&diff.added, &diff.removed] {
+            prop_assert!(entries.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        }
+        prop_assert_eq!(diff.is_empty(), a == b);
+    }
+}
+
+#[test]
+fn map_diff_follows_the_serialized_key_order() {
+    use crate::Layout;
+
+    // 65280 is encoded as [0x00, 0xff] and 11436 as [0xac, 0x2c], so it comes first.
+    let empty = to_bytes(&BTreeMap::<u16, Vec<u8>>::new()).unwrap();
+    let map = to_bytes(&BTreeMap::from([
+        (11436u16, vec![]),
+        (65280, Vec::<u8>::new()),
+    ]))
+    .unwrap();
+    let value_layout = Layout::Seq(Box::new(Layout::U8));
+    let diff = crate::map_diff(&empty, &map, &Layout::U16, &value_layout).unwrap();
+    assert_eq!(
+        diff.added,
+        vec![(&[0x00, 0xff][..], &[0][..]), (&[0xac, 0x2c][..], &[0][..])]
+    );
+    assert!(diff.removed.is_empty() && diff.changed.is_empty());
+
+    let diff = crate::map_diff(&map, &empty, &Layout::U16, &value_layout).unwrap();
+    assert_eq!(diff.removed.len(), 2);
+    assert_eq!(diff.removed[0].0, [0x00, 0xff]);
+}
+
+#[test]
+fn map_diff_validates_both_maps() {
+    use crate::Layout;
+
+    let map = to_bytes(&BTreeMap::from([(1u8, 2u8), (3, 4)])).unwrap();
+    let diff = crate::map_diff(&map, &map, &Layout::U8, &Layout::U8).unwrap();
+    assert!(diff.is_empty());
+
+    let unsorted = vec![2, 3, 4, 1, 2];
+    assert_eq!(
+        crate::map_diff(&map, &unsorted, &Layout::U8, &Layout::U8),
+        Err(Error::NonCanonicalMap {
+            index: 1,
+            offset: 3
+        })
+    );
+    assert_eq!(
+        crate::map_diff(
+            &[map.as_slice(), &[0]].concat(),
+            &map,
+            &Layout::U8,
+            &Layout::U8
+        ),
+        Err(Error::RemainingInput { len: 1, first: 0 })
+    );
+    assert!(crate::map_diff(&map[..4], &map, &Layout::U8, &Layout::U8)
+        .unwrap_err()
+        .is_eof());
+}
+
+#[test]
+fn lengths_near_u32_max_are_rejected_without_wrapping() {
+    fn uleb128(mut value: u32) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        while value >= 0x80 {
+            bytes.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        bytes.push(value as u8);
+        bytes
+    }
+
+    // The largest length that is allowed, and those right above it up to `u32::MAX`.
+    let max = MAX_SEQUENCE_LENGTH as u32;
+    assert_eq!(u64::from(max), (1 << 31) - 1);
+    for len in [max + 1, max + 2, u32::MAX - 1, u32::MAX] {
+        let bytes = uleb128(len);
+        let expected = Error::ExceededMaxLen {
+            len: len as usize,
+            max: MAX_SEQUENCE_LENGTH,
+        };
+        assert_eq!(from_bytes::<Vec<u8>>(&bytes), Err(expected.clone()));
+        assert_eq!(
+            from_reader::<Vec<u8>>(&mut &bytes[..]),
+            Err(expected.clone())
+        );
+        assert_eq!(crate::peek_length(&bytes), Err(expected));
+    }
+
+    // A forged maximal length fails on the missing bytes, without allocating them first.
+    let input = [uleb128(max), vec![b'a']].concat();
+    let expected = Error::Eof {
+        expected: MAX_SEQUENCE_LENGTH,
+        available: 1,
+    };
+    assert_eq!(from_bytes::<String>(&input), Err(expected.clone()));
+    assert_eq!(
+        from_reader::<String>(&mut &input[..]),
+        Err(expected.clone())
+    );
+    assert_eq!(from_reader::<ByteBuf>(&mut &input[..]), Err(expected));
+
+    // Byte arrays read from readers in several chunks are intact.
+    let data: Vec<u8> = (0..300_000u32).map(|i| i as u8).collect();
+    let bytes = to_bytes(&ByteBuf::from(data.clone())).unwrap();
+    assert_eq!(
+        from_reader::<ByteBuf>(&mut &bytes[..]).unwrap().into_vec(),
+        data
+    );
+    let truncated = &bytes[..bytes.len() - 1];
+    assert_eq!(
+        from_reader::<ByteBuf>(&mut &truncated[..]),
+        Err(Error::Eof {
+            expected: data.len(),
+            available: data.len() - 1,
+        })
+    );
+
+    // Variant indices near `u32::MAX` are out of range, not truncated.
+    let bytes = uleb128(u32::MAX);
+    assert!(from_bytes::<E>(&bytes).unwrap_err().is_malformed());
+    let layout = crate::Layout::Enum(vec![crate::Layout::Unit]);
+    assert!(crate::skip_value(&bytes, &layout).is_err());
+}
+
+#[test]
+fn hex_context_shows_where_decoding_stopped() {
+    let config = Config::new().hex_context_on_error(true);
+
+    // A long input is elided on both sides of the position.
+    let mut bytes: Vec<u8> = (0..40).collect();
+    bytes[20] = 2;
+    let err = from_bytes_with_config::<([u8; 20], bool, u8)>(&bytes, &config).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "expected boolean, found byte 0x02 (at byte 21: .. 05 06 07 08 09 0a 0b 0c 0d 0e 0f 10 \
+         11 12 13 02 | 15 16 17 18 19 1a 1b 1c 1d 1e 1f 20 21 22 23 24 ..)"
+    );
+    assert!(matches!(
+        &err,
+        Error::WithHexContext { error, .. } if **error == Error::ExpectedBoolean(2)
+    ));
+    assert!(err.is_malformed() && !err.is_truncated());
+
+    // Readers only show the bytes that were read.
+    let err =
+        from_reader_with_config::<([u8; 20], bool, u8)>(&mut &bytes[..], &config).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "expected boolean, found byte 0x02 (at byte 21: .. 05 06 07 08 09 0a 0b 0c 0d 0e 0f 10 \
+         11 12 13 02 |)"
+    );
+
+    // Errors at either end of the input.
+    let err = from_bytes_with_config::<(u8, u32)>(&[1, 2, 3], &config).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "unexpected end of input: expected 4 byte(s), found 2 (at byte 1: 01 | 02 03)"
+    );
+    assert!(err.is_truncated() && !err.is_malformed());
+    let err = from_bytes_with_config::<u8>(&[1, 2], &config).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "remaining input: 1 trailing byte(s), starting with 0x02 (at byte 1: 01 | 02)"
+    );
+    let err = from_bytes_with_config::<String>(&[], &config).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "unexpected end of input: expected 1 byte(s), found 0 (at byte 0: |)"
+    );
+    assert!(err.is_truncated());
+
+    // Disabled by default.
+    let config = Config::new();
+    assert_eq!(
+        from_bytes_with_config::<bool>(&[2], &config),
+        Err(Error::ExpectedBoolean(2))
+    );
+}
+
+#[test]
+fn byte_bufs_convert_into_boxed_slices_in_place() {
+    use std::{rc::Rc, sync::Arc};
+
+    // Larger than the chunks in which readers are read.
+    let payload: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+    let bytes = to_bytes(&payload).unwrap();
+
+    for buf in [
+        from_bytes::<ByteBuf>(&bytes).unwrap(),
+        from_reader::<ByteBuf>(&mut &bytes[..]).unwrap(),
+    ] {
+        // The payload is allocated once, with the exact size, so boxing it does not move it.
+        assert_eq!(buf.capacity(), payload.len());
+        let ptr = buf.as_ptr();
+        let boxed: Box<[u8]> = buf.into();
+        assert_eq!(boxed.as_ptr(), ptr);
+        assert_eq!(&*boxed, &payload[..]);
+    }
+
+    let shared: Arc<[u8]> = from_bytes::<ByteBuf>(&bytes).unwrap().into();
+    assert_eq!(&*shared, &payload[..]);
+    let shared: Rc<[u8]> = from_reader::<ByteBuf>(&mut &bytes[..]).unwrap().into();
+    assert_eq!(&*shared, &payload[..]);
+}
+
+proptest! {
+    #[test]
+    fn canonical_key_cmp_matches_serialized_maps(map in any::<BTreeMap<i32, u8>>()) {
+        let mut entries: Vec<_> = map.iter().map(|(k, v)| (to_bytes(k).unwrap(), *v)).collect();
+        entries.sort_by(|(a, _), (b, _)| crate::canonical_key_cmp(a, b));
+        let mut expected = to_bytes(&vec![(); entries.len()])?;
+        for (key, value) in entries {
+            expected.extend(key);
+            expected.push(value);
+        }
+        prop_assert_eq!(to_bytes(&map)?, expected);
+    }
+}
+
+#[test]
+fn for_each_map_entry_seed_skips_unselected_values() {
+    use crate::{for_each_map_entry_seed, Layout, ValueAction};
+    use std::marker::PhantomData;
+
+    let map: BTreeMap<u8, Vec<String>> = (0..6)
+        .map(|i| (i, vec![i.to_string(); usize::from(i)]))
+        .collect();
+    let bytes = [to_bytes(&map).unwrap(), vec![0xff]].concat();
+    let layout = Layout::Seq(Box::new(Layout::Str));
+
+    let mut selected = BTreeMap::new();
+    let mut seen = Vec::new();
+    let rest = for_each_map_entry_seed(
+        &bytes,
+        |key: &u8| {
+            seen.push(*key);
+            if key % 2 == 0 {
+                ValueAction::Decode(PhantomData::<Vec<String>>)
+            } else {
+                ValueAction::Skip(&layout)
+            }
+        },
+        |key, value| {
+            selected.insert(key, value);
+            Ok(())
+        },
+    )
+    .unwrap();
+    assert_eq!(rest, &[0xff]);
+    assert_eq!(seen, vec![0, 1, 2, 3, 4, 5]);
+    assert_eq!(
+        selected,
+        map.iter()
+            .filter(|(key, _)| *key % 2 == 0)
+            .map(|(key, value)| (*key, value.clone()))
+            .collect()
+    );
+
+    // Skipped values are still validated, and keys still checked.
+    let skip_all = |_: &u8| ValueAction::<PhantomData<()>>::Skip(&layout);
+    let mut invalid = to_bytes(&map).unwrap();
+    let last = invalid.len() - 1;
+    invalid[last] = 0xff;
+    assert_eq!(
+        for_each_map_entry_seed(&invalid, skip_all, |_, ()| Ok(())),
+        Err(Error::InvalidUtf8 { offset: 0 })
+    );
+    let unsorted = [3, 2, 0, 1, 0];
+    assert_eq!(
+        for_each_map_entry_seed(&unsorted, skip_all, |_, ()| Ok(())),
+        Err(Error::NonCanonicalMap {
+            index: 1,
+            offset: 3
+        })
+    );
+}
+
+#[test]
+fn to_bytes_sorted_check_checks_the_reported_length() {
+    for (len, yielded) in [(3, 2), (1, 2), (0, 1)] {
+        let entries = test_helpers::WrongLen {
+            items: (0u8..yielded).map(|key| (key, ())),
+            len,
+        };
+        assert_eq!(
+            crate::to_bytes_sorted_check(entries),
+            Err(Error::IteratorLengthMismatch {
+                reported: len,
+                yielded: yielded.into(),
+            })
+        );
+    }
+}
+
+proptest! {
+    #[test]
+    fn to_bytes_sorted_check_accepts_only_canonical_orders(
+        keys in prop::collection::vec(any::<u16>(), 0..20),
+    ) {
+        let map: BTreeMap<u16, u8> = keys.iter().map(|k| (*k, *k as u8)).collect();
+        let entries: Vec<_> = keys.iter().map(|k| (*k, *k as u8)).collect();
+        let result = crate::to_bytes_sorted_check(entries.iter().copied());
+        let first_unsorted = entries.windows(2).position(|pair| {
+            to_bytes(&pair[0].0).unwrap() >= to_bytes(&pair[1].0).unwrap()
+        });
+        match first_unsorted {
+            None => prop_assert_eq!(result?, to_bytes(&map)?),
+            Some(i) => prop_assert_eq!(
+                result,
+                Err(Error::NonCanonicalMap { index: i + 1, offset: 1 + 3 * (i + 1) })
+            ),
+        }
+    }
+}
+
+proptest! {
+    #[test]
+    fn from_chunks_decodes_across_any_split(
+        value in any::<(u16, String, Vec<u32>, Option<bool>)>(),
+        cuts in prop::collection::vec(any::<prop::sample::Index>(), 0..6),
+    ) {
+        let bytes = to_bytes(&value).unwrap();
+        let mut cuts: Vec<usize> = cuts.iter().map(|i| i.index(bytes.len() + 1)).collect();
+        cuts.sort_unstable();
+        let mut chunks = Vec::new();
+        let mut start = 0;
+        for cut in cuts.into_iter().chain(core::iter::once(bytes.len())) {
+            chunks.push(&bytes[start..cut]);
+            start = cut;
+        }
+        prop_assert_eq!(crate::from_chunks::<(u16, String, Vec<u32>, Option<bool>)>(&chunks)?, value);
+
+        let last = chunks.len() - 1;
+        let mut truncated = chunks.clone();
+        truncated[last] = &[];
+        if let Some(chunk) = truncated.iter_mut().rev().find(|chunk| !chunk.is_empty()) {
+            *chunk = &chunk[..chunk.len() - 1];
+            prop_assert!(crate::from_chunks::<(u16, String, Vec<u32>, Option<bool>)>(&truncated)
+                .unwrap_err()
+                .is_truncated());
+        }
+    }
+}
+
+#[test]
+fn context_is_threaded_through_nested_values() {
+    use crate::{from_bytes_with_context, ContextSeed, DeserializeWithContext};
+    use serde::de::{Deserializer, SeqAccess, Visitor};
+
+    /// A tree whose labels are interned in the context, children included.
+    #[derive(Debug, PartialEq)]
+    struct Node {
+        label: usize,
+        children: Vec<Node>,
+    }
+
+    #[derive(Serialize)]
+    struct PlainNode {
+        label: &'static str,
+        children: Vec<PlainNode>,
+    }
+
+    struct Label(usize);
+
+    impl<'de> DeserializeWithContext<'de, Vec<String>> for Label {
+        fn deserialize_with_context<D: Deserializer<'de>>(
+            deserializer: D,
+            labels: &mut Vec<String>,
+        ) -> core::result::Result<Self, D::Error> {
+            let label = String::deserialize(deserializer)?;
+            match labels.iter().position(|l| *l == label) {
+                Some(index) => Ok(Label(index)),
+                None => {
+                    labels.push(label);
+                    Ok(Label(labels.len() - 1))
+                }
+            }
+        }
+    }
+
+    impl<'de> DeserializeWithContext<'de, Vec<String>> for Node {
+        fn deserialize_with_context<D: Deserializer<'de>>(
+            deserializer: D,
+            labels: &mut Vec<String>,
+        ) -> core::result::Result<Self, D::Error> {
+            struct NodeVisitor<'c>(ContextSeed<'c, Vec<String>, Node>);
+
+            impl<'de> Visitor<'de> for NodeVisitor<'_> {
+                type Value = Node;
+
+                fn expecting(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                    formatter.write_str("a node")
+                }
+
+                fn visit_seq<A: SeqAccess<'de>>(
+                    mut self,
+                    mut seq: A,
+                ) -> core::result::Result<Node, A::Error> {
+                    let missing = || serde::de::Error::custom("missing field");
+                    let Label(label) = seq.next_element_seed(self.0.seed())?.ok_or_else(missing)?;
+                    let children = seq.next_element_seed(self.0.seed())?.ok_or_else(missing)?;
+                    Ok(Node { label, children })
+                }
+            }
+
+            deserializer.deserialize_struct(
+                "Node",
+                &["label", "children"],
+                NodeVisitor(ContextSeed::new(labels)),
+            )
+        }
+    }
+
+    let leaf = |label| PlainNode {
+        label,
+        children: vec![],
+    };
+    let tree = PlainNode {
+        label: "a",
+        children: vec![
+            leaf("b"),
+            PlainNode {
+                label: "c",
+                children: vec![leaf("a"), leaf("b")],
+            },
+        ],
+    };
+    let bytes = to_bytes(&tree).unwrap();
+
+    let mut labels = vec!["z".to_owned()];
+    let node: Node = from_bytes_with_context(&bytes, &mut labels).unwrap();
+    let leaf = |label| Node {
+        label,
+        children: vec![],
+    };
+    assert_eq!(
+        node,
+        Node {
+            label: 1,
+            children: vec![
+                leaf(2),
+                Node {
+                    label: 3,
+                    children: vec![leaf(1), leaf(2)],
+                },
+            ],
+        }
+    );
+    assert_eq!(labels, ["z", "a", "b", "c"]);
+
+    // Labels decoded before an error stay in the context.
+    let mut labels = Vec::new();
+    let err = from_bytes_with_context::<Node, _>(&bytes[..bytes.len() - 1], &mut labels);
+    assert!(err.unwrap_err().is_truncated());
+    assert_eq!(labels, ["a", "b", "c"]);
+}
+
+#[test]
+fn map_lengths_are_checked_against_the_input() {
+    // A million entries cannot fit in 10 bytes.
+    let mut bytes = vec![0xc0, 0x84, 0x3d];
+    bytes.extend([0; 10]);
+    assert_eq!(
+        from_bytes::<BTreeMap<u8, u8>>(&bytes),
+        Err(Error::Eof {
+            expected: 1_000_000,
+            available: 10,
+        })
+    );
+
+    // Once the first entry is known to take two bytes, so do the other four: the second
+    // value and the last three entries need 7 bytes.
+    assert_eq!(
+        from_bytes::<BTreeMap<u8, u8>>(&[5, 1, 1, 2, 2, 3, 3]),
+        Err(Error::Eof {
+            expected: 7,
+            available: 3,
+        })
+    );
+    // The order of the second key is checked first, but a later unsorted key is not reached.
+    assert_eq!(
+        from_bytes::<BTreeMap<u8, u8>>(&[5, 1, 1, 1, 1, 3, 3]),
+        Err(Error::NonCanonicalMap {
+            index: 1,
+            offset: 3
+        })
+    );
+    assert_eq!(
+        from_bytes::<BTreeMap<u8, u8>>(&[5, 1, 1, 2, 2, 1, 1]),
+        Err(Error::Eof {
+            expected: 7,
+            available: 3,
+        })
+    );
+    assert_eq!(
+        from_bytes::<BTreeMap<u8, u8>>(&[3, 1, 1, 2, 2, 3, 3]).unwrap(),
+        [(1, 1), (2, 2), (3, 3)].into()
+    );
+
+    // Zero-sized values only need one byte per entry.
+    assert_eq!(
+        from_bytes::<BTreeMap<u8, ()>>(&[3, 1, 2, 3]).unwrap(),
+        [(1, ()), (2, ()), (3, ())].into()
+    );
+    assert_eq!(
+        from_bytes::<BTreeMap<(), u8>>(&[1, 7]).unwrap(),
+        [((), 7)].into()
+    );
+}
+
+#[test]
+fn serialized_size_breakdown_adds_up_to_the_total() {
+    use crate::serialized_size_breakdown;
+
+    #[derive(Serialize)]
+    struct Header {
+        version: u8,
+        tags: Vec<String>,
+    }
+
+    #[derive(Serialize)]
+    struct Message {
+        header: Header,
+        body: Vec<u8>,
+        kind: E,
+    }
+
+    #[derive(Serialize)]
+    struct Envelope(Message);
+
+    #[derive(Serialize)]
+    struct Empty;
+
+    let message = Message {
+        header: Header {
+            version: 1,
+            tags: vec!["a".to_owned(), "bc".to_owned()],
+        },
+        body: vec![0; 200],
+        kind: E::Struct { a: 3 },
+    };
+    let expected = [("header", 7), ("body", 202), ("kind", 5)];
+    assert_eq!(serialized_size_breakdown(&message).unwrap(), expected);
+    assert_eq!(
+        serialized_size_breakdown(&Envelope(message)).unwrap(),
+        expected
+    );
+
+    // Other values, including unit structs, are a single unnamed entry.
+    assert_eq!(serialized_size_breakdown(&Empty).unwrap(), [("", 0)]);
+    assert_eq!(
+        serialized_size_breakdown(&Some(Envelope(Message {
+            header: Header {
+                version: 1,
+                tags: Vec::new(),
+            },
+            body: Vec::new(),
+            kind: E::Unit,
+        })))
+        .unwrap(),
+        [("", 5)]
+    );
+    assert_eq!(
+        serialized_size_breakdown(&vec![1u32; 3]).unwrap(),
+        [("", 13)]
+    );
+    assert_eq!(serialized_size_breakdown(&E::Unit).unwrap(), [("", 1)]);
+    assert_eq!(serialized_size_breakdown(&(1u8, 2u16)).unwrap(), [("", 3)]);
+
+    // Errors are those of `serialized_size`.
+    let deep = List::integers(crate::MAX_CONTAINER_DEPTH);
+    assert_eq!(
+        serialized_size_breakdown(&deep),
+        Err(serialized_size(&deep).unwrap_err())
+    );
+    let deep = List::integers(crate::MAX_CONTAINER_DEPTH - 1);
+    let sizes = serialized_size_breakdown(&deep).unwrap();
+    assert_eq!(
+        sizes.iter().map(|(_, size)| size).sum::<usize>(),
+        serialized_size(&deep).unwrap()
+    );
+}
+
+#[test]
+fn asymmetric_skip_is_caught_by_layouts() {
+    use crate::{from_bytes_with_layout, Layout};
+
+    #[derive(Serialize)]
+    struct Sent {
+        id: u8,
+        nonce: u8,
+        amount: u16,
+    }
+
+    // The receiver skips `nonce`, which the sender encodes.
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Received {
+        id: u8,
+        #[serde(skip)]
+        nonce: u8,
+        amount: u16,
+    }
+
+    let bytes = to_bytes(&Sent {
+        id: 1,
+        nonce: 7,
+        amount: 2,
+    })
+    .unwrap();
+    let mut input = &bytes[..];
+    // `amount` is decoded from the bytes of `nonce` and half of the actual amount.
+    assert_eq!(
+        crate::from_slice_advancing::<Received>(&mut input).unwrap(),
+        Received {
+            id: 1,
+            nonce: 0,
+            amount: 0x0207,
+        }
+    );
+    assert_eq!(
+        from_bytes::<Received>(&bytes),
+        Err(Error::RemainingInput { len: 1, first: 0 })
+    );
+
+    let layout = Layout::Struct(vec![Layout::U8, Layout::U8, Layout::U16]);
+    assert_eq!(
+        from_bytes_with_layout::<Received>(&bytes, &layout),
+        Err(Error::LayoutMismatch {
+            expected: 4,
+            found: 3,
+        })
+    );
+}
+
+#[test]
+fn strings_from_readers_are_validated_as_they_arrive() {
+    use std::io::Read as _;
+
+    // A corrupt string declaring 10 MB is rejected after the first chunk.
+    let len = 10_000_000u32;
+    let mut header = Vec::new();
+    let mut len_prefix = len;
+    while len_prefix >= 0x80 {
+        header.push((len_prefix & 0x7f) as u8 | 0x80);
+        len_prefix >>= 7;
+    }
+    header.push(len_prefix as u8);
+    header.extend_from_slice(b"ok\xff");
+    let mut input = header[..]
+        .chain(std::io::repeat(b'a'))
+        .take(header.len() as u64 + u64::from(len) - 3);
+    assert_eq!(
+        from_reader::<String>(&mut input),
+        Err(Error::InvalidUtf8 { offset: 2 })
+    );
+    assert!(input.limit() > u64::from(len) - 128 * 1024);
+
+    // An error in the last chunk is found when the string is converted as a whole.
+    let mut bytes = to_bytes(&"a".repeat(64 * 1024 + 10)).unwrap();
+    let last = bytes.len() - 5;
+    bytes[last] = 0xff;
+    assert_eq!(
+        from_bytes_via_reader::<String>(&bytes),
+        Err(Error::InvalidUtf8 {
+            offset: 64 * 1024 + 5
+        })
+    );
+
+    // Characters split across chunks, or at the end of the input, are handled as with slices.
+    let mut value = "a".repeat(64 * 1024 - 1);
+    value.push_str("é€😀");
+    assert_eq!(
+        from_bytes_via_reader::<String>(&to_bytes(&value).unwrap()).unwrap(),
+        value
+    );
+    for cut in 1..4 {
+        let mut bytes = to_bytes("😀").unwrap();
+        bytes[0] = cut as u8;
+        bytes.truncate(1 + cut);
+        assert_eq!(
+            from_bytes_via_reader::<String>(&bytes),
+            from_bytes::<String>(&bytes)
+        );
+        assert_eq!(
+            from_bytes::<String>(&bytes),
+            Err(Error::InvalidUtf8 { offset: 0 })
+        );
+    }
+}
+
+#[test]
+fn max_len_bounds_sequences_per_type() {
+    use crate::max_len::MaxLen;
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Signature(u16);
+
+    impl MaxLen for Signature {
+        const MAX: usize = 3;
+    }
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Bounded {
+        #[serde(with = "crate::max_len")]
+        signatures: Vec<Signature>,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Unbounded {
+        signatures: Vec<Signature>,
+    }
+
+    let three = Bounded {
+        signatures: (0..3).map(Signature).collect(),
+    };
+    let bytes = to_bytes(&three).unwrap();
+    assert_eq!(
+        bytes,
+        to_bytes(&Unbounded {
+            signatures: (0..3).map(Signature).collect(),
+        })
+        .unwrap()
+    );
+    assert_eq!(from_bytes::<Bounded>(&bytes).unwrap(), three);
+
+    // Without the attribute, only the global limit applies.
+    let four = Unbounded {
+        signatures: (0..4).map(Signature).collect(),
+    };
+    let bytes = to_bytes(&four).unwrap();
+    assert_eq!(from_bytes::<Unbounded>(&bytes).unwrap(), four);
+    let err = from_bytes::<Bounded>(&bytes).unwrap_err();
+    assert_eq!(
+        err,
+        Error::Custom("invalid length 4, expected a sequence of at most 3 elements".to_owned())
+    );
+    assert!(err.is_malformed());
+    assert!(to_bytes(&Bounded {
+        signatures: four.signatures
+    })
+    .is_err());
+
+    // Forged lengths are rejected before the input is checked for the elements.
+    assert_eq!(
+        from_bytes::<Bounded>(&[0xc0, 0x84, 0x3d]),
+        Err(Error::Custom(
+            "invalid length 1000000, expected a sequence of at most 3 elements".to_owned()
+        ))
+    );
+    assert_eq!(
+        from_bytes_via_reader::<Bounded>(&[0xc0, 0x84, 0x3d]),
+        from_bytes::<Bounded>(&[0xc0, 0x84, 0x3d])
+    );
+}
+
+#[test]
+fn format_profiles_summarize_configs() {
+    use crate::{Config, FormatProfile};
+
+    let default = Config::new().format_profile();
+    assert_eq!(
+        default,
+        FormatProfile {
+            format_version: crate::version(),
+            max_container_depth: crate::MAX_CONTAINER_DEPTH,
+            max_map_entries: crate::MAX_SEQUENCE_LENGTH,
+            max_materialized_bytes: usize::MAX,
+            max_collection_nesting: usize::MAX,
+            canonical_map_key_order: true,
+            canonical_uleb128: true,
+            positional_variant_indices: true,
+        }
+    );
+    assert!(default.is_canonical());
+    assert_eq!(
+        Config::new().hex_context_on_error(true).format_profile(),
+        default
+    );
+    assert_eq!(
+        Config::new()
+            .max_container_depth(usize::MAX)
+            .format_profile(),
+        default
+    );
+
+    // Custom orders cannot be compared, so any of them counts as non-canonical.
+    let reversed = Config::new()
+        .map_key_order(|a, b| b.cmp(a))
+        .format_profile();
+    assert!(!reversed.canonical_map_key_order);
+    assert!(!reversed.is_canonical());
+    assert_eq!(
+        Config::new()
+            .map_key_order(crate::canonical_key_cmp)
+            .format_profile(),
+        reversed
+    );
+    assert_ne!(
+        Config::new().map_key_order(crate::canonical_key_cmp),
+        Config::new()
+    );
+    assert_eq!(
+        Config::new().map_key_order(crate::canonical_key_cmp),
+        Config::new().map_key_order(|a, b| b.cmp(a))
+    );
+
+    // Variant maps decode old indices that `to_bytes` no longer produces.
+    const MAPS: &[crate::VariantMap] = &[crate::VariantMap {
+        enum_name: "Message",
+        variants: &[("Ping", 1)],
+    }];
+    let mapped = Config::new().variant_maps(MAPS).format_profile();
+    assert!(!mapped.positional_variant_indices);
+    assert!(!mapped.is_canonical());
+
+    let bounded = Config::new()
+        .max_materialized_bytes(1 << 20)
+        .format_profile();
+    assert!(bounded.is_canonical());
+    assert_ne!(bounded, default);
+    assert_eq!(
+        from_bytes::<FormatProfile>(&to_bytes(&bounded).unwrap()).unwrap(),
+        bounded
+    );
+}
+
+#[test]
+fn errors_can_be_wrapped_by_applications() {
+    use std::error::Error as _;
+
+    #[derive(Debug, thiserror::Error)]
+    enum AppError {
+        #[error("cannot decode the request")]
+        Decode(#[from] Error),
+    }
+
+    fn decode(bytes: &[u8]) -> Result<(u8, String), AppError> {
+        Ok(from_bytes(bytes)?)
+    }
+
+    let err = decode(&[1, 5, b'a']).unwrap_err();
+    let source = err.source().unwrap();
+    assert_eq!(
+        source.to_string(),
+        "unexpected end of input: expected 5 byte(s), found 1"
+    );
+    assert!(source.downcast_ref::<Error>().unwrap().is_truncated());
+
+    struct FailingReader;
+
+    impl std::io::Read for FailingReader {
+        fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+            Err(std::io::Error::new(std::io::ErrorKind::Other, "link down"))
+        }
+    }
+
+    // I/O errors stay reachable through the chain, even after crossing threads.
+    let err: Box<dyn std::error::Error + Send + Sync> =
+        std::thread::spawn(|| from_reader::<u32>(FailingReader).unwrap_err().into())
+            .join()
+            .unwrap();
+    let io = core::iter::successors(Some(&*err as &dyn std::error::Error), |&e| e.source())
+        .find_map(|e| e.downcast_ref::<std::io::Error>())
+        .unwrap();
+    assert_eq!(io.kind(), std::io::ErrorKind::Other);
+    assert_eq!(io.to_string(), "link down");
+}
+
+#[test]
+fn append_from_bytes_extends_in_place() {
+    use crate::append_from_bytes;
+
+    let mut names: Vec<&str> = Vec::with_capacity(8);
+    let capacity = names.capacity();
+    let first = to_bytes(&vec!["a", "b"]).unwrap();
+    let second = to_bytes(&vec!["c", "d", "e"]).unwrap();
+    append_from_bytes(&mut names, &first).unwrap();
+    append_from_bytes(&mut names, &second).unwrap();
+    append_from_bytes(&mut names, &[0]).unwrap();
+    assert_eq!(names, ["a", "b", "c", "d", "e"]);
+    assert_eq!(names.capacity(), capacity);
+    assert_eq!(names[0].as_ptr(), first[2..].as_ptr());
+
+    // Elements of a failed chunk are removed, whatever the error.
+    let mut truncated = to_bytes(&vec!["f", "g"]).unwrap();
+    truncated.pop();
+    assert!(append_from_bytes(&mut names, &truncated)
+        .unwrap_err()
+        .is_eof());
+    let mut trailing = to_bytes(&vec!["f"]).unwrap();
+    trailing.push(0);
+    assert_eq!(
+        append_from_bytes(&mut names, &trailing),
+        Err(Error::RemainingInput { len: 1, first: 0 })
+    );
+    assert_eq!(names, ["a", "b", "c", "d", "e"]);
+
+    // A forged length does not reserve more than a page of elements.
+    let mut numbers: Vec<u64> = Vec::new();
+    let forged = [0xfe, 0xff, 0xff, 0xff, 0x07, 1, 0, 0, 0, 0, 0, 0, 0];
+    assert!(append_from_bytes(&mut numbers, &forged).is_err());
+    assert!(numbers.is_empty());
+    assert!(numbers.capacity() <= 4096);
+}
+
+#[test]
+fn variant_maps_translate_encoded_indices() {
+    use crate::{from_bytes_with_config, from_reader_with_config, Config, VariantMap};
+
+    // Version 1 of the schema.
+    #[derive(Serialize)]
+    enum ShapeV1 {
+        Circle(u8),
+        Square(u8),
+        Line,
+    }
+
+    // Version 2 inserted `Triangle` and dropped `Line`.
+    #[derive(Debug, Deserialize, PartialEq)]
+    enum Shape {
+        Triangle(u8),
+        Circle(u8),
+        Square(u8),
+    }
+
+    const V1: &[VariantMap] = &[VariantMap {
+        enum_name: "Shape",
+        variants: &[("Circle", 0), ("Square", 1), ("Line", 2)],
+    }];
+    let config = Config::new().variant_maps(V1);
+
+    let old = to_bytes(&(ShapeV1::Square(4), Some(E::Newtype(5)))).unwrap();
+    // Other enums keep positional indices.
+    let expected = (Shape::Square(4), Some(E::Newtype(5)));
+    assert_eq!(
+        from_bytes_with_config::<(Shape, Option<E>)>(&old, &config).unwrap(),
+        expected
+    );
+    assert_eq!(
+        from_reader_with_config::<(Shape, Option<E>)>(&old[..], &config).unwrap(),
+        expected
+    );
+    assert_eq!(
+        from_bytes::<(Shape, Option<E>)>(&old).unwrap(),
+        (Shape::Circle(4), Some(E::Newtype(5)))
+    );
+
+    assert_eq!(
+        from_bytes_with_config::<Shape>(&to_bytes(&ShapeV1::Circle(1)).unwrap(), &config),
+        Ok(Shape::Circle(1))
+    );
+    assert_eq!(
+        from_bytes_with_config::<Shape>(&to_bytes(&ShapeV1::Line).unwrap(), &config),
+        Err(Error::Custom(
+            "unknown variant `Line`, expected one of `Triangle`, `Circle`, `Square`".to_owned()
+        ))
+    );
+    assert_eq!(
+        from_bytes_with_config::<Shape>(&[3, 0], &config),
+        Err(Error::Custom(
+            "invalid value: integer `3`, expected a variant index of the variant map".to_owned()
+        ))
+    );
+}
+
+#[test]
+fn collection_nesting_is_bounded_by_config() {
+    use crate::{from_bytes_with_config, from_reader_with_config, Config};
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Wrapper(Vec<u8>);
+
+    let config = Config::new().max_collection_nesting(2);
+    let nested = vec![Some(Wrapper(vec![1]))];
+    let bytes = to_bytes(&nested).unwrap();
+    assert_eq!(
+        from_bytes_with_config::<Vec<Option<Wrapper>>>(&bytes, &config),
+        Err(Error::ExceededCollectionNesting { limit: 2 })
+    );
+    assert_eq!(
+        from_reader_with_config::<Vec<Option<Wrapper>>>(&bytes[..], &config),
+        Err(Error::ExceededCollectionNesting { limit: 2 })
+    );
+    assert_eq!(
+        from_bytes_with_config::<Vec<Option<Wrapper>>>(&bytes, &config.max_collection_nesting(3))
+            .unwrap(),
+        nested
+    );
+
+    // Siblings, `None`, strings and byte arrays do not nest.
+    let flat = (
+        vec![1u8],
+        BTreeMap::from([(2u8, "two".to_owned())]),
+        None::<Vec<u8>>,
+    );
+    let bytes = to_bytes(&flat).unwrap();
+    let config = Config::new().max_collection_nesting(1);
+    assert_eq!(
+        from_bytes_with_config::<(Vec<u8>, BTreeMap<u8, String>, Option<Vec<u8>>)>(&bytes, &config)
+            .unwrap(),
+        flat
+    );
+    let bytes = to_bytes(&(Some(vec![1u8]),)).unwrap();
+    let err = from_bytes_with_config::<(Option<Vec<u8>>,)>(&bytes, &config).unwrap_err();
+    assert_eq!(err, Error::ExceededCollectionNesting { limit: 1 });
+    assert!(err.is_malformed());
+    assert_eq!(
+        from_bytes_with_config::<Option<ByteBuf>>(&bytes, &config).unwrap(),
+        Some(ByteBuf::from(vec![1]))
+    );
+}
+
+#[test]
+fn captured_values_keep_their_bytes() {
+    use crate::Captured;
+    use serde::de::{value::U8Deserializer, IntoDeserializer};
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Inner {
+        id: u16,
+        tags: Vec<String>,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Outer {
+        header: u8,
+        inner: Captured<(Captured<Inner>, u8)>,
+        keys: BTreeMap<Captured<String>, u8>,
+    }
+
+    let inner = Inner {
+        id: 0x0102,
+        tags: vec!["a".to_owned(), "bc".to_owned()],
+    };
+    let inner_bytes = to_bytes(&inner).unwrap();
+    let bytes = to_bytes(&(
+        7u8,
+        (&inner, 9u8),
+        BTreeMap::from([("k".to_owned(), 1u8), ("kk".to_owned(), 2)]),
+    ))
+    .unwrap();
+
+    let from_slice: Outer = from_bytes(&bytes).unwrap();
+    let from_reader: Outer = from_bytes_via_reader(&bytes).unwrap();
+    for outer in [&from_slice, &from_reader] {
+        assert_eq!(outer.header, 7);
+        let (captured_inner, trailer) = outer.inner.value();
+        assert_eq!(captured_inner.value(), &inner);
+        assert_eq!(captured_inner.bytes(), inner_bytes);
+        assert_eq!(*trailer, 9);
+        assert_eq!(outer.inner.bytes(), &bytes[1..1 + inner_bytes.len() + 1]);
+        let keys: Vec<_> = outer.keys.keys().map(|key| key.bytes().to_vec()).collect();
+        assert_eq!(keys, [b"\x01k".to_vec(), b"\x02kk".to_vec()]);
+    }
+    assert_eq!(from_slice, from_reader);
+
+    // Captured values are encoded as their values.
+    assert_eq!(to_bytes(&from_slice).unwrap(), bytes);
+    let (value, captured) = from_bytes::<Captured<u32>>(&[1, 0, 0, 0])
+        .unwrap()
+        .into_parts();
+    assert_eq!((value, captured), (1, vec![1, 0, 0, 0]));
+
+    // Only BCS knows where values end.
+    let other: U8Deserializer<Error> = 1u8.into_deserializer();
+    assert_eq!(
+        Captured::<u8>::deserialize(other),
+        Err(Error::Custom(
+            "invalid type: integer `1`, expected a value with its BCS bytes".to_owned()
+        ))
+    );
+}
+
+#[test]
+fn reader_errors_are_told_apart_from_format_errors() {
+    use crate::{from_reader_with_config, Config};
+
+    #[derive(Debug)]
+    struct Timeout;
+
+    impl fmt::Display for Timeout {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("peer timed out")
+        }
+    }
+
+    impl std::error::Error for Timeout {}
+
+    /// Delivers `bytes`, then fails instead of ending.
+    struct Flaky<'a> {
+        bytes: &'a [u8],
+    }
+
+    impl std::io::Read for Flaky<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.bytes.is_empty() {
+                return Err(std::io::Error::new(std::io::ErrorKind::TimedOut, Timeout));
+            }
+            std::io::Read::read(&mut self.bytes, buf)
+        }
+    }
+
+    let bytes = to_bytes(&(1u8, Some("retry me"))).unwrap();
+    let config = Config::new().hex_context_on_error(true);
+    for len in 0..bytes.len() {
+        let flaky = Flaky {
+            bytes: &bytes[..len],
+        };
+        let err = from_reader::<(u8, Option<String>)>(flaky).unwrap_err();
+        assert!(err.is_io());
+        assert!(!err.is_eof() && !err.is_truncated() && !err.is_malformed());
+        let Error::Io(io) = &err else {
+            panic!("unexpected error: {}", err)
+        };
+        assert_eq!(io.kind(), std::io::ErrorKind::TimedOut);
+        assert!(io.get_ref().get_ref().unwrap().is::<Timeout>());
+
+        let flaky = Flaky {
+            bytes: &bytes[..len],
+        };
+        let err = from_reader_with_config::<(u8, Option<String>)>(flaky, &config).unwrap_err();
+        assert!(err.is_io() && !err.is_malformed());
+    }
+
+    // The same data decodes once the transport recovers, and format errors are not I/O errors.
+    assert_eq!(
+        from_reader::<(u8, Option<String>)>(&bytes[..]).unwrap(),
+        (1, Some("retry me".to_owned()))
+    );
+    let err = from_reader::<(u8, Option<String>)>(&[1, 2][..]).unwrap_err();
+    assert!(err.is_malformed() && !err.is_io());
+    assert!(!from_reader::<u8>(&[][..]).unwrap_err().is_io());
+}
+
+#[test]
+fn fixed_bytes_match_element_wise_arrays() {
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Account {
+        #[serde(with = "crate::fixed_bytes")]
+        hash: [u8; 32],
+        nonce: u8,
+    }
+
+    let mut hash = [0u8; 32];
+    for (i, b) in hash.iter_mut().enumerate() {
+        *b = (i as u8).wrapping_mul(37);
+    }
+    let account = Account { hash, nonce: 9 };
+    let bytes = to_bytes(&account).unwrap();
+
+    // No length prefix: same bytes as the plain, element-wise `[u8; 32]`.
+    assert_eq!(bytes, to_bytes(&(hash, 9u8)).unwrap());
+    assert_eq!(bytes.len(), 33);
+    is_same(account);
+
+    assert_eq!(
+        from_bytes::<Account>(&bytes[..31]),
+        Err(Error::Eof {
+            expected: 32,
+            available: 31
+        })
+    );
+    assert_eq!(
+        from_bytes_via_reader::<Account>(&bytes[..31]),
+        Err(Error::Eof {
+            expected: 32,
+            available: 31
+        })
+    );
+}
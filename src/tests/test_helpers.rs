@@ -14,3 +14,24 @@ where
     let s_from_reader = crate::from_reader(reader).unwrap();
     assert_eq!(t, s_from_reader);
 }
+
+/// An iterator over `items` whose `ExactSizeIterator::len` wrongly reports `len`.
+#[derive(Clone)]
+pub struct WrongLen<I> {
+    pub items: I,
+    pub len: usize,
+}
+
+impl<I: Iterator> Iterator for WrongLen<I> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        self.items.next()
+    }
+}
+
+impl<I: Iterator> ExactSizeIterator for WrongLen<I> {
+    fn len(&self) -> usize {
+        self.len
+    }
+}
@@ -0,0 +1,88 @@
+// Copyright (c) The Diem Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Canonical encodings for time types, to be used with `#[serde(with = "...")]`.
+//!
+//! * `Duration` is encoded as a pair `(u64, u32)` of whole seconds and nanoseconds. The
+//! nanoseconds must be lower than 10^9 so that every duration has a single encoding.
+//! * `SystemTime` is encoded as the `Duration` elapsed since the Unix epoch. Times before the
+//! epoch cannot be serialized.
+//!
+//! ```rust
+//! # use bcs::{from_bytes, to_bytes, Result};
+//! # use serde::{Deserialize, Serialize};
+//! # use std::time::Duration;
+//! # fn main() -> Result<()> {
+//! #[derive(Debug, Deserialize, PartialEq, Serialize)]
+//! struct Lease {
+//!     #[serde(with = "bcs::time::duration")]
+//!     ttl: Duration,
+//! }
+//!
+//! let lease = Lease {
+//!     ttl: Duration::new(1, 500),
+//! };
+//! let bytes = to_bytes(&lease)?;
+//! assert_eq!(bytes, vec![1, 0, 0, 0, 0, 0, 0, 0, 0xf4, 0x01, 0, 0]);
+//! assert_eq!(from_bytes::<Lease>(&bytes)?, lease);
+//! # Ok(())}
+//! ```
+
+use core::time::Duration;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+const NANOS_PER_SEC: u32 = 1_000_000_000;
+
+/// Encodes a `Duration` as `(u64, u32)` seconds and nanoseconds.
+pub mod duration {
+    use super::*;
+
+    pub fn serialize<S>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        (duration.as_secs(), duration.subsec_nanos()).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let (secs, nanos) = <(u64, u32)>::deserialize(deserializer)?;
+        if nanos >= NANOS_PER_SEC {
+            return Err(de::Error::invalid_value(
+                de::Unexpected::Unsigned(nanos.into()),
+                &"fewer than 10^9 nanoseconds",
+            ));
+        }
+        Ok(Duration::new(secs, nanos))
+    }
+}
+
+/// Encodes a `SystemTime` as the `Duration` elapsed since the Unix epoch.
+#[cfg(feature = "std")]
+pub mod system_time {
+    use super::*;
+    use serde::ser;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    pub fn serialize<S>(time: &SystemTime, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let since_epoch = time
+            .duration_since(UNIX_EPOCH)
+            .map_err(|_| ser::Error::custom("SystemTime is before the Unix epoch"))?;
+        duration::serialize(&since_epoch, serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<SystemTime, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let since_epoch = duration::deserialize(deserializer)?;
+        UNIX_EPOCH
+            .checked_add(since_epoch)
+            .ok_or_else(|| de::Error::custom("SystemTime is out of range"))
+    }
+}
@@ -0,0 +1,22 @@
+// Copyright (c) The Diem Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Helpers for the ULEB128 encoding used by BCS for sequence lengths and enum variant indices.
+
+/// Returns the number of bytes in the canonical ULEB128 encoding of `value`, between 1 and 5.
+///
+/// # Examples
+///
+/// ```
+/// use bcs::uleb128::encoded_len;
+///
+/// assert_eq!(encoded_len(0), 1);
+/// assert_eq!(encoded_len(127), 1);
+/// assert_eq!(encoded_len(128), 2);
+/// assert_eq!(encoded_len(u32::MAX), 5);
+/// ```
+pub const fn encoded_len(value: u32) -> usize {
+    // Each byte carries 7 bits of data, and zero still takes one byte.
+    let significant_bits = 32 - (value | 1).leading_zeros() as usize;
+    (significant_bits + 6) / 7
+}
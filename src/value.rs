@@ -0,0 +1,376 @@
+// Copyright (c) The Diem Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+#[cfg(not(feature = "std"))]
+use alloc::{boxed::Box, string::String, vec::Vec};
+
+use crate::{error::Result, layout::Layout};
+use core::{fmt, marker::PhantomData};
+use serde::{
+    de::{
+        self, DeserializeSeed, Deserializer, EnumAccess, MapAccess, SeqAccess, VariantAccess,
+        Visitor,
+    },
+    ser::{
+        Serialize, SerializeMap, SerializeSeq, SerializeTuple, SerializeTupleStruct, Serializer,
+    },
+};
+
+/// A BCS value decoded without knowing its Rust type, following a [`Layout`].
+///
+/// Serializing a `Value` produces the BCS encoding of the original value: each node is written
+/// according to its shape, and map entries are re-sorted in canonical order.
+///
+/// # Examples
+///
+/// ```
+/// use bcs::{from_bytes_value, to_bytes, to_bytes_value, Layout, Value};
+///
+/// let bytes = to_bytes(&(7u8, vec!["a", "b"])).unwrap();
+/// let layout = Layout::Tuple(vec![Layout::U8, Layout::Seq(Box::new(Layout::Str))]);
+///
+/// let value = from_bytes_value(&bytes, &layout).unwrap();
+/// assert_eq!(
+///     value,
+///     Value::Tuple(vec![
+///         Value::U8(7),
+///         Value::Seq(vec![Value::Str("a".into()), Value::Str("b".into())]),
+///     ])
+/// );
+/// assert_eq!(to_bytes_value(&value).unwrap(), bytes);
+/// ```
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    U128(u128),
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    I128(i128),
+    Unit,
+    Str(String),
+    Option(Option<Box<Value>>),
+    /// A variable-length sequence.
+    Seq(Vec<Value>),
+    /// A fixed-length sequence: the value of a `Layout::Array` or a `Layout::Tuple`.
+    Tuple(Vec<Value>),
+    /// The fields of a struct. Counts towards the container depth.
+    Struct(Vec<Value>),
+    /// An enum variant and its payload. Counts towards the container depth.
+    Enum(u32, Box<Value>),
+    /// Map entries, in any order.
+    Map(Vec<(Value, Value)>),
+}
+
+/// Container name reported in depth errors for `Value::Struct`.
+const STRUCT_NAME: &str = "struct";
+/// Container name reported in depth errors for `Value::Enum`.
+const ENUM_NAME: &str = "enum";
+
+/// Deserializes a `&[u8]` into a [`Value`] of the given layout.
+pub fn from_bytes_value(bytes: &[u8], layout: &Layout) -> Result<Value> {
+    crate::from_bytes_seed(ValueSeed(layout), bytes)
+}
+
+/// Serializes a [`Value`] as BCS.
+pub fn to_bytes_value(value: &Value) -> Result<Vec<u8>> {
+    crate::to_bytes(value)
+}
+
+impl Serialize for Value {
+    fn serialize<S>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            Value::Bool(v) => serializer.serialize_bool(*v),
+            Value::U8(v) => serializer.serialize_u8(*v),
+            Value::U16(v) => serializer.serialize_u16(*v),
+            Value::U32(v) => serializer.serialize_u32(*v),
+            Value::U64(v) => serializer.serialize_u64(*v),
+            Value::U128(v) => serializer.serialize_u128(*v),
+            Value::I8(v) => serializer.serialize_i8(*v),
+            Value::I16(v) => serializer.serialize_i16(*v),
+            Value::I32(v) => serializer.serialize_i32(*v),
+            Value::I64(v) => serializer.serialize_i64(*v),
+            Value::I128(v) => serializer.serialize_i128(*v),
+            Value::Unit => serializer.serialize_unit(),
+            Value::Str(v) => serializer.serialize_str(v),
+            Value::Option(None) => serializer.serialize_none(),
+            Value::Option(Some(v)) => serializer.serialize_some(v),
+            Value::Seq(elements) => {
+                let mut seq = serializer.serialize_seq(Some(elements.len()))?;
+                for element in elements {
+                    seq.serialize_element(element)?;
+                }
+                seq.end()
+            }
+            Value::Tuple(elements) => {
+                let mut tuple = serializer.serialize_tuple(elements.len())?;
+                for element in elements {
+                    tuple.serialize_element(element)?;
+                }
+                tuple.end()
+            }
+            Value::Struct(fields) => {
+                let mut tuple = serializer.serialize_tuple_struct(STRUCT_NAME, fields.len())?;
+                for field in fields {
+                    tuple.serialize_field(field)?;
+                }
+                tuple.end()
+            }
+            Value::Enum(index, payload) => {
+                serializer.serialize_newtype_variant(ENUM_NAME, *index, "", payload)
+            }
+            Value::Map(entries) => {
+                let mut map = serializer.serialize_map(Some(entries.len()))?;
+                for (key, value) in entries {
+                    map.serialize_entry(key, value)?;
+                }
+                map.end()
+            }
+        }
+    }
+}
+
+/// A `DeserializeSeed` producing a [`Value`] of the given layout.
+#[derive(Clone, Copy, Debug)]
+pub struct ValueSeed<'a>(pub &'a Layout);
+
+impl<'de, 'a> DeserializeSeed<'de> for ValueSeed<'a> {
+    type Value = Value;
+
+    fn deserialize<D>(self, deserializer: D) -> core::result::Result<Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let visitor = ValueVisitor(self.0, PhantomData);
+        match self.0 {
+            Layout::Bool => deserializer.deserialize_bool(visitor),
+            Layout::U8 => deserializer.deserialize_u8(visitor),
+            Layout::U16 => deserializer.deserialize_u16(visitor),
+            Layout::U32 => deserializer.deserialize_u32(visitor),
+            Layout::U64 => deserializer.deserialize_u64(visitor),
+            Layout::U128 => deserializer.deserialize_u128(visitor),
+            Layout::I8 => deserializer.deserialize_i8(visitor),
+            Layout::I16 => deserializer.deserialize_i16(visitor),
+            Layout::I32 => deserializer.deserialize_i32(visitor),
+            Layout::I64 => deserializer.deserialize_i64(visitor),
+            Layout::I128 => deserializer.deserialize_i128(visitor),
+            Layout::Unit => deserializer.deserialize_unit(visitor),
+            Layout::Str => deserializer.deserialize_string(visitor),
+            Layout::Option(_) => deserializer.deserialize_option(visitor),
+            Layout::Seq(_) => deserializer.deserialize_seq(visitor),
+            Layout::Array(_, len) => deserializer.deserialize_tuple(*len, visitor),
+            Layout::Tuple(fields) => deserializer.deserialize_tuple(fields.len(), visitor),
+            Layout::Struct(fields) => {
+                deserializer.deserialize_tuple_struct(STRUCT_NAME, fields.len(), visitor)
+            }
+            Layout::Enum(_) => deserializer.deserialize_enum(ENUM_NAME, &[], visitor),
+            Layout::Map(_, _) => deserializer.deserialize_map(visitor),
+        }
+    }
+}
+
+/// Visitor building a [`Value`] of the given layout.
+struct ValueVisitor<'a, 'de>(&'a Layout, PhantomData<&'de ()>);
+
+macro_rules! visit_primitive {
+    ($($method:ident($ty:ty) => $variant:ident,)*) => {
+        $(
+            fn $method<E>(self, v: $ty) -> core::result::Result<Value, E>
+            where
+                E: de::Error,
+            {
+                Ok(Value::$variant(v.into()))
+            }
+        )*
+    };
+}
+
+impl<'a, 'de> Visitor<'de> for ValueVisitor<'a, 'de> {
+    type Value = Value;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "a value of layout {:?}", self.0)
+    }
+
+    visit_primitive! {
+        visit_bool(bool) => Bool,
+        visit_u8(u8) => U8,
+        visit_u16(u16) => U16,
+        visit_u32(u32) => U32,
+        visit_u64(u64) => U64,
+        visit_u128(u128) => U128,
+        visit_i8(i8) => I8,
+        visit_i16(i16) => I16,
+        visit_i32(i32) => I32,
+        visit_i64(i64) => I64,
+        visit_i128(i128) => I128,
+        visit_str(&str) => Str,
+        visit_string(String) => Str,
+    }
+
+    fn visit_unit<E>(self) -> core::result::Result<Value, E>
+    where
+        E: de::Error,
+    {
+        Ok(Value::Unit)
+    }
+
+    fn visit_none<E>(self) -> core::result::Result<Value, E>
+    where
+        E: de::Error,
+    {
+        Ok(Value::Option(None))
+    }
+
+    fn visit_some<D>(self, deserializer: D) -> core::result::Result<Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        match self.0 {
+            Layout::Option(inner) => Ok(Value::Option(Some(Box::new(
+                ValueSeed(inner).deserialize(deserializer)?,
+            )))),
+            _ => Err(de::Error::invalid_type(de::Unexpected::Option, &self)),
+        }
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> core::result::Result<Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        match self.0 {
+            Layout::Seq(element) => {
+                let mut elements = Vec::new();
+                while let Some(value) = seq.next_element_seed(ValueSeed(element))? {
+                    elements.push(value);
+                }
+                Ok(Value::Seq(elements))
+            }
+            Layout::Array(element, len) => {
+                let mut elements = Vec::new();
+                for i in 0..*len {
+                    elements.push(
+                        seq.next_element_seed(ValueSeed(element))?
+                            .ok_or_else(|| de::Error::invalid_length(i, &self))?,
+                    );
+                }
+                Ok(Value::Tuple(elements))
+            }
+            Layout::Tuple(fields) | Layout::Struct(fields) => {
+                let mut values = Vec::with_capacity(fields.len());
+                for (i, field) in fields.iter().enumerate() {
+                    values.push(
+                        seq.next_element_seed(ValueSeed(field))?
+                            .ok_or_else(|| de::Error::invalid_length(i, &self))?,
+                    );
+                }
+                match self.0 {
+                    Layout::Struct(_) => Ok(Value::Struct(values)),
+                    _ => Ok(Value::Tuple(values)),
+                }
+            }
+            _ => Err(de::Error::invalid_type(de::Unexpected::Seq, &self)),
+        }
+    }
+
+    fn visit_map<A>(self, mut map: A) -> core::result::Result<Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        match self.0 {
+            Layout::Map(key, value) => {
+                let mut entries = Vec::new();
+                while let Some(entry) = map.next_entry_seed(ValueSeed(key), ValueSeed(value))? {
+                    entries.push(entry);
+                }
+                Ok(Value::Map(entries))
+            }
+            _ => Err(de::Error::invalid_type(de::Unexpected::Map, &self)),
+        }
+    }
+
+    fn visit_enum<A>(self, data: A) -> core::result::Result<Value, A::Error>
+    where
+        A: EnumAccess<'de>,
+    {
+        match self.0 {
+            Layout::Enum(variants) => {
+                let (index, variant) = data.variant_seed(PhantomData::<u32>)?;
+                match usize::try_from(index).ok().and_then(|i| variants.get(i)) {
+                    Some(payload) => Ok(Value::Enum(
+                        index,
+                        Box::new(variant.newtype_variant_seed(ValueSeed(payload))?),
+                    )),
+                    None => Err(de::Error::invalid_value(
+                        de::Unexpected::Unsigned(index.into()),
+                        &self,
+                    )),
+                }
+            }
+            _ => Err(de::Error::invalid_type(de::Unexpected::Enum, &self)),
+        }
+    }
+}
+
+/// Converts a [`Value`] to JSON, e.g. to export decoded BCS to other tools.
+///
+/// * Integers up to 64 bits become JSON numbers, while `u128` and `i128` become decimal strings
+///   so that no precision is lost.
+/// * `Unit` and `None` become `null`, and `Some(v)` becomes the conversion of `v`.
+/// * Sequences, tuples, and structs become arrays.
+/// * Enums become `{"variant": index, "value": payload}`.
+/// * Maps become arrays of `[key, value]` pairs, since keys are not necessarily strings.
+///
+/// # Examples
+///
+/// ```
+/// use bcs::{to_json, Value};
+/// use serde_json::json;
+///
+/// let value = Value::Struct(vec![Value::U128(u128::MAX), Value::Option(None)]);
+/// assert_eq!(
+///     to_json(&value),
+///     json!(["340282366920938463463374607431768211455", null])
+/// );
+/// ```
+#[cfg(feature = "json")]
+pub fn to_json(value: &Value) -> serde_json::Value {
+    use serde_json::Value as Json;
+
+    let array = |values: &[Value]| Json::Array(values.iter().map(to_json).collect());
+    match value {
+        Value::Bool(v) => Json::from(*v),
+        Value::U8(v) => Json::from(*v),
+        Value::U16(v) => Json::from(*v),
+        Value::U32(v) => Json::from(*v),
+        Value::U64(v) => Json::from(*v),
+        Value::U128(v) => Json::from(v.to_string()),
+        Value::I8(v) => Json::from(*v),
+        Value::I16(v) => Json::from(*v),
+        Value::I32(v) => Json::from(*v),
+        Value::I64(v) => Json::from(*v),
+        Value::I128(v) => Json::from(v.to_string()),
+        Value::Unit | Value::Option(None) => Json::Null,
+        Value::Str(v) => Json::from(v.as_str()),
+        Value::Option(Some(v)) => to_json(v),
+        Value::Seq(values) | Value::Tuple(values) | Value::Struct(values) => array(values),
+        Value::Enum(index, payload) => serde_json::json!({
+            "variant": index,
+            "value": to_json(payload),
+        }),
+        Value::Map(entries) => Json::Array(
+            entries
+                .iter()
+                .map(|(key, value)| Json::Array(vec![to_json(key), to_json(value)]))
+                .collect(),
+        ),
+    }
+}